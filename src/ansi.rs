@@ -0,0 +1,279 @@
+//! ANSI escape sequence parsing for captured pane output.
+
+/// A terminal color as set by an SGR escape sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnsiColor {
+    /// One of the 256 indexed palette colors (0-15 are the standard colors)
+    Indexed(u8),
+    /// A 24-bit color
+    Rgb(u8, u8, u8),
+}
+
+impl AnsiColor {
+    /// Returns the color as a CSS hex string
+    #[must_use]
+    pub fn to_hex(self) -> String {
+        let (r, g, b) = match self {
+            Self::Rgb(r, g, b) => (r, g, b),
+            Self::Indexed(i) => indexed_rgb(i),
+        };
+        format!("#{r:02x}{g:02x}{b:02x}")
+    }
+}
+
+/// Text attributes active for a run of characters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)] // Mirrors independent SGR attributes
+pub struct AnsiStyle {
+    /// Foreground color
+    pub fg: Option<AnsiColor>,
+    /// Background color
+    pub bg: Option<AnsiColor>,
+    /// Bold text
+    pub bold: bool,
+    /// Dim text
+    pub dim: bool,
+    /// Italic text
+    pub italic: bool,
+    /// Underlined text
+    pub underline: bool,
+}
+
+impl AnsiStyle {
+    /// Returns true if no attribute is set
+    #[must_use]
+    pub fn is_plain(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn apply_sgr(&mut self, params: &[u16]) {
+        if params.is_empty() {
+            *self = Self::default();
+            return;
+        }
+
+        let mut i = 0;
+        while i < params.len() {
+            match params[i] {
+                0 => *self = Self::default(),
+                1 => self.bold = true,
+                2 => self.dim = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                22 => {
+                    self.bold = false;
+                    self.dim = false;
+                }
+                23 => self.italic = false,
+                24 => self.underline = false,
+                n @ 30..=37 => self.fg = Some(AnsiColor::Indexed(low_byte(n - 30))),
+                n @ 90..=97 => self.fg = Some(AnsiColor::Indexed(low_byte(n - 90 + 8))),
+                39 => self.fg = None,
+                n @ 40..=47 => self.bg = Some(AnsiColor::Indexed(low_byte(n - 40))),
+                n @ 100..=107 => self.bg = Some(AnsiColor::Indexed(low_byte(n - 100 + 8))),
+                49 => self.bg = None,
+                n @ (38 | 48) => {
+                    let (color, consumed) = extended_color(&params[i + 1..]);
+                    if n == 38 {
+                        self.fg = color;
+                    } else {
+                        self.bg = color;
+                    }
+                    i += consumed;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+}
+
+/// Splits text containing escape sequences into styled runs
+///
+/// SGR sequences update the style; every other escape sequence is dropped.
+#[must_use]
+pub fn parse(input: &str) -> Vec<(AnsiStyle, String)> {
+    let mut spans: Vec<(AnsiStyle, String)> = Vec::new();
+    let mut style = AnsiStyle::default();
+    let mut text = String::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            text.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('[') => {
+                let mut body = String::new();
+                let mut final_byte = None;
+                for c in chars.by_ref() {
+                    if ('\x40'..='\x7e').contains(&c) {
+                        final_byte = Some(c);
+                        break;
+                    }
+                    body.push(c);
+                }
+                if final_byte == Some('m') {
+                    if !text.is_empty() {
+                        spans.push((style, std::mem::take(&mut text)));
+                    }
+                    let params: Vec<u16> = body
+                        .split([';', ':'])
+                        .filter(|p| !p.is_empty())
+                        .map(|p| p.parse().unwrap_or(0))
+                        .collect();
+                    style.apply_sgr(&params);
+                }
+            }
+            Some(']') => {
+                // OSC: terminated by BEL or ST (ESC \)
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if !text.is_empty() {
+        spans.push((style, text));
+    }
+
+    spans
+}
+
+/// Removes all escape sequences, keeping only the text
+#[must_use]
+pub fn strip(input: &str) -> String {
+    parse(input).into_iter().map(|(_, text)| text).collect()
+}
+
+fn extended_color(params: &[u16]) -> (Option<AnsiColor>, usize) {
+    match params {
+        [5, n, ..] => (Some(AnsiColor::Indexed(low_byte(*n))), 2),
+        [2, r, g, b, ..] => (
+            Some(AnsiColor::Rgb(low_byte(*r), low_byte(*g), low_byte(*b))),
+            4,
+        ),
+        _ => (None, params.len()),
+    }
+}
+
+fn low_byte(n: u16) -> u8 {
+    u8::try_from(n).unwrap_or(u8::MAX)
+}
+
+fn indexed_rgb(i: u8) -> (u8, u8, u8) {
+    const BASE: [(u8, u8, u8); 16] = [
+        (0x00, 0x00, 0x00),
+        (0xcd, 0x00, 0x00),
+        (0x00, 0xcd, 0x00),
+        (0xcd, 0xcd, 0x00),
+        (0x00, 0x00, 0xee),
+        (0xcd, 0x00, 0xcd),
+        (0x00, 0xcd, 0xcd),
+        (0xe5, 0xe5, 0xe5),
+        (0x7f, 0x7f, 0x7f),
+        (0xff, 0x00, 0x00),
+        (0x00, 0xff, 0x00),
+        (0xff, 0xff, 0x00),
+        (0x5c, 0x5c, 0xff),
+        (0xff, 0x00, 0xff),
+        (0x00, 0xff, 0xff),
+        (0xff, 0xff, 0xff),
+    ];
+
+    match i {
+        0..=15 => BASE[usize::from(i)],
+        16..=231 => {
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            let i = i - 16;
+            (level(i / 36), level((i / 6) % 6), level(i % 6))
+        }
+        _ => {
+            let gray = 8 + (i - 232) * 10;
+            (gray, gray, gray)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_plain_text() {
+        let spans = parse("hello");
+        assert_eq!(spans, vec![(AnsiStyle::default(), "hello".to_string())]);
+    }
+
+    #[test]
+    fn test_parse_colors_and_reset() {
+        let spans = parse("\x1b[1;31merr\x1b[0m ok");
+        assert_eq!(spans.len(), 2);
+        assert!(spans[0].0.bold);
+        assert_eq!(spans[0].0.fg, Some(AnsiColor::Indexed(1)));
+        assert_eq!(spans[0].1, "err");
+        assert!(spans[1].0.is_plain());
+        assert_eq!(spans[1].1, " ok");
+    }
+
+    #[test]
+    fn test_parse_bright_and_background() {
+        let spans = parse("\x1b[92;104mx\x1b[39;49my");
+        assert_eq!(spans[0].0.fg, Some(AnsiColor::Indexed(10)));
+        assert_eq!(spans[0].0.bg, Some(AnsiColor::Indexed(12)));
+        assert!(spans[1].0.is_plain());
+    }
+
+    #[test]
+    fn test_parse_extended_colors() {
+        let spans = parse("\x1b[38;5;208ma\x1b[48;2;1;2;3mb");
+        assert_eq!(spans[0].0.fg, Some(AnsiColor::Indexed(208)));
+        assert_eq!(spans[1].0.bg, Some(AnsiColor::Rgb(1, 2, 3)));
+        assert_eq!(spans[1].0.fg, Some(AnsiColor::Indexed(208)));
+    }
+
+    #[test]
+    fn test_parse_attributes_toggle() {
+        let spans = parse("\x1b[2;3;4ma\x1b[22;23;24mb\x1bmc");
+        assert!(spans[0].0.dim && spans[0].0.italic && spans[0].0.underline);
+        assert!(spans[1].0.is_plain());
+        // A lone ESC that does not start a sequence is dropped with its next char
+        assert_eq!(spans[1].1, "bc");
+    }
+
+    #[test]
+    fn test_parse_empty_sgr_resets() {
+        let spans = parse("\x1b[31ma\x1b[mb");
+        assert!(spans[1].0.is_plain());
+    }
+
+    #[test]
+    fn test_parse_malformed_extended_color() {
+        let spans = parse("\x1b[38;9mx");
+        assert_eq!(spans[0].0.fg, None);
+    }
+
+    #[test]
+    fn test_strip_drops_non_sgr_sequences() {
+        assert_eq!(strip("\x1b[2Ka\x1b]0;title\x07b\x1b]8;;url\x1b\\c"), "abc");
+    }
+
+    #[test]
+    fn test_color_to_hex() {
+        assert_eq!(AnsiColor::Indexed(1).to_hex(), "#cd0000");
+        assert_eq!(AnsiColor::Indexed(16).to_hex(), "#000000");
+        assert_eq!(AnsiColor::Indexed(231).to_hex(), "#ffffff");
+        assert_eq!(AnsiColor::Indexed(232).to_hex(), "#080808");
+        assert_eq!(AnsiColor::Rgb(255, 128, 0).to_hex(), "#ff8000");
+    }
+}
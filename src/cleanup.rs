@@ -0,0 +1,437 @@
+//! Cleanup policies for forgotten sessions, applied by `ams gc`.
+
+use crate::config::CleanupConfig;
+use crate::session::{AgentState, Session, SessionStatus};
+use crate::tmux::{self, TmuxError};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::BuildHasher;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// What a policy does to a session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionKind {
+    /// Kill the session
+    Kill,
+    /// Detach its clients
+    Detach,
+}
+
+impl std::fmt::Display for ActionKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Kill => write!(f, "kill"),
+            Self::Detach => write!(f, "detach"),
+        }
+    }
+}
+
+/// A policy's decision about one session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Action {
+    /// What to do
+    pub kind: ActionKind,
+    /// Session name
+    pub session: String,
+    /// Why the policy applies, e.g. `idle for 5h 2m (limit 4h)`
+    pub reason: String,
+}
+
+impl Action {
+    /// Carries out the action
+    pub fn apply(&self) -> Result<(), TmuxError> {
+        match self.kind {
+            ActionKind::Kill => tmux::kill_session(&self.session),
+            ActionKind::Detach => tmux::detach_session(&self.session),
+        }
+    }
+}
+
+/// Decides what the policies do to the sessions at `now`
+///
+/// Attached sessions are never killed: they are detached once they have
+/// waited for input too long. Only sessions ams manages (see
+/// [`Session::is_managed`]) are killed or counted towards a repository's
+/// limit. `states` holds the agent state of attached sessions and `repo_of`
+/// maps a working directory to its repository.
+#[must_use]
+pub fn plan<S: BuildHasher>(
+    policy: &CleanupConfig,
+    sessions: &[Session],
+    agent_commands: &[String],
+    states: &HashMap<String, AgentState, S>,
+    now: DateTime<Utc>,
+    repo_of: impl Fn(&Path) -> PathBuf,
+) -> Vec<Action> {
+    let idle_for = |session: &Session| {
+        (now - session.last_activity)
+            .to_std()
+            .unwrap_or(Duration::ZERO)
+    };
+    let mut actions = Vec::new();
+
+    for session in sessions {
+        let idle = idle_for(session);
+        if session.status == SessionStatus::Active {
+            let waiting = matches!(
+                states.get(&session.name),
+                Some(AgentState::WaitingForInput | AgentState::NeedsApproval)
+            );
+            if let Some(limit) = policy
+                .detach_waiting_after
+                .filter(|&limit| waiting && idle >= limit)
+            {
+                actions.push(Action {
+                    kind: ActionKind::Detach,
+                    session: session.name.clone(),
+                    reason: format!(
+                        "waiting for input for {} (limit {})",
+                        format_duration(idle),
+                        format_duration(limit)
+                    ),
+                });
+            }
+        } else if let Some(limit) = policy
+            .kill_idle_after
+            .filter(|&limit| idle >= limit && session.is_managed(agent_commands))
+        {
+            actions.push(Action {
+                kind: ActionKind::Kill,
+                session: session.name.clone(),
+                reason: format!(
+                    "idle for {} (limit {})",
+                    format_duration(idle),
+                    format_duration(limit)
+                ),
+            });
+        }
+    }
+
+    if let Some(max) = policy.max_sessions_per_repo {
+        let killed = |session: &Session| {
+            actions
+                .iter()
+                .any(|action| action.kind == ActionKind::Kill && action.session == session.name)
+        };
+        let mut repos: BTreeMap<PathBuf, Vec<&Session>> = BTreeMap::new();
+        let counted = sessions
+            .iter()
+            .filter(|session| session.is_managed(agent_commands) && !killed(session));
+        for session in counted {
+            repos
+                .entry(repo_of(&session.working_directory))
+                .or_default()
+                .push(session);
+        }
+
+        for (repo, mut members) in repos {
+            let excess = members.len().saturating_sub(max);
+            members.sort_by_key(|session| (session.last_activity, session.created_at));
+            let reason = format!(
+                "{} has {} sessions (limit {max}); least recently active",
+                repo.display(),
+                members.len()
+            );
+            actions.extend(
+                members
+                    .into_iter()
+                    .filter(|session| session.status != SessionStatus::Active)
+                    .take(excess)
+                    .map(|session| Action {
+                        kind: ActionKind::Kill,
+                        session: session.name.clone(),
+                        reason: reason.clone(),
+                    }),
+            );
+        }
+    }
+
+    actions
+}
+
+/// Returns the root of the git repository containing `dir`, or `dir` itself
+#[must_use]
+pub fn repo_root(dir: &Path) -> PathBuf {
+    dir.ancestors()
+        .find(|ancestor| ancestor.join(".git").exists())
+        .unwrap_or(dir)
+        .to_path_buf()
+}
+
+/// Parses a duration such as `90s`, `30m`, `4h`, `1d` or `1h30m`
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration {text:?} (expected e.g. 30m, 4h or 1h30m)");
+    let mut total = 0u64;
+    let mut digits = String::new();
+
+    for c in text.trim().chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        let amount: u64 = digits.parse().map_err(|_| invalid())?;
+        total = amount
+            .checked_mul(unit)
+            .and_then(|seconds| total.checked_add(seconds))
+            .ok_or_else(invalid)?;
+        digits.clear();
+    }
+
+    if !digits.is_empty() || total == 0 {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(total))
+}
+
+/// Formats a duration with its two largest units, e.g. `5h 2m`
+#[must_use]
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let parts = [
+        (seconds / 86400, "d"),
+        (seconds / 3600 % 24, "h"),
+        (seconds / 60 % 60, "m"),
+        (seconds % 60, "s"),
+    ];
+    let parts: Vec<String> = parts
+        .iter()
+        .skip_while(|(amount, _)| *amount == 0)
+        .take(2)
+        .filter(|(amount, _)| *amount > 0)
+        .map(|(amount, unit)| format!("{amount}{unit}"))
+        .collect();
+    if parts.is_empty() {
+        "0s".to_string()
+    } else {
+        parts.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap()
+    }
+
+    fn session(name: &str, repo: &str, idle_minutes: i64, attached: bool) -> Session {
+        Session {
+            name: name.to_string(),
+            status: if attached {
+                SessionStatus::Active
+            } else {
+                SessionStatus::Idle
+            },
+            working_directory: PathBuf::from(repo),
+            last_activity: now() - chrono::Duration::minutes(idle_minutes),
+            created_at: now() - chrono::Duration::days(1),
+            window_count: 1,
+            created_by: Some("ams new".to_string()),
+            ..Session::default()
+        }
+    }
+
+    fn agent_commands() -> Vec<String> {
+        vec!["claude".to_string()]
+    }
+
+    fn policy(text: &str) -> CleanupConfig {
+        crate::Config::parse(text).unwrap().cleanup
+    }
+
+    fn summary(actions: &[Action]) -> Vec<(ActionKind, &str)> {
+        actions
+            .iter()
+            .map(|action| (action.kind, action.session.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_kill_idle_sessions() {
+        let policy = policy("[cleanup]\nkill_idle_after = \"4h\"");
+        let sessions = [
+            session("fresh", "/a", 30, false),
+            session("stale", "/a", 5 * 60 + 2, false),
+            session("attached", "/a", 10 * 60, true),
+        ];
+        let actions = plan(
+            &policy,
+            &sessions,
+            &agent_commands(),
+            &HashMap::new(),
+            now(),
+            Path::to_path_buf,
+        );
+        assert_eq!(summary(&actions), vec![(ActionKind::Kill, "stale")]);
+        assert_eq!(actions[0].reason, "idle for 5h 2m (limit 4h)");
+    }
+
+    #[test]
+    fn test_detach_waiting_sessions() {
+        let policy = policy("[cleanup]\ndetach_waiting_after = \"30m\"");
+        let sessions = [
+            session("waiting", "/a", 45, true),
+            session("working", "/a", 45, true),
+            session("recent", "/a", 5, true),
+            session("detached", "/a", 45, false),
+        ];
+        let states = HashMap::from([
+            ("waiting".to_string(), AgentState::WaitingForInput),
+            ("working".to_string(), AgentState::Working),
+            ("recent".to_string(), AgentState::WaitingForInput),
+        ]);
+        let actions = plan(
+            &policy,
+            &sessions,
+            &agent_commands(),
+            &states,
+            now(),
+            Path::to_path_buf,
+        );
+        assert_eq!(summary(&actions), vec![(ActionKind::Detach, "waiting")]);
+        assert_eq!(actions[0].reason, "waiting for input for 45m (limit 30m)");
+    }
+
+    #[test]
+    fn test_max_sessions_per_repo() {
+        let policy = policy("[cleanup]\nmax_sessions_per_repo = 2");
+        let sessions = [
+            session("newest", "/repo/a", 1, false),
+            session("oldest-attached", "/repo/b", 300, true),
+            session("old", "/repo", 200, false),
+            session("middle", "/repo", 100, false),
+            session("other", "/elsewhere", 500, false),
+        ];
+        let repo_of = |dir: &Path| {
+            if dir.starts_with("/repo") {
+                PathBuf::from("/repo")
+            } else {
+                dir.to_path_buf()
+            }
+        };
+        let actions = plan(
+            &policy,
+            &sessions,
+            &agent_commands(),
+            &HashMap::new(),
+            now(),
+            repo_of,
+        );
+        assert_eq!(
+            summary(&actions),
+            vec![(ActionKind::Kill, "old"), (ActionKind::Kill, "middle")]
+        );
+        assert_eq!(
+            actions[0].reason,
+            "/repo has 4 sessions (limit 2); least recently active"
+        );
+    }
+
+    #[test]
+    fn test_idle_kills_count_towards_repo_limit() {
+        let policy = policy("[cleanup]\nkill_idle_after = \"1h\"\nmax_sessions_per_repo = 1");
+        let sessions = [
+            session("stale", "/repo", 120, false),
+            session("fresh", "/repo", 1, false),
+        ];
+        let actions = plan(
+            &policy,
+            &sessions,
+            &agent_commands(),
+            &HashMap::new(),
+            now(),
+            Path::to_path_buf,
+        );
+        assert_eq!(summary(&actions), vec![(ActionKind::Kill, "stale")]);
+    }
+
+    #[test]
+    fn test_unmanaged_sessions_are_never_killed() {
+        let policy = policy("[cleanup]\nkill_idle_after = \"1h\"\nmax_sessions_per_repo = 1");
+        let unmanaged = |name| Session {
+            created_by: None,
+            ..session(name, "/repo", 600, false)
+        };
+        let mut agent = unmanaged("agent");
+        agent.panes.push(crate::session::Pane {
+            session: "agent".to_string(),
+            current_command: "claude".to_string(),
+            current_path: PathBuf::from("/repo"),
+            ..crate::session::Pane::default()
+        });
+        let sessions = [
+            unmanaged("shell"),
+            agent,
+            session("fresh", "/repo", 1, false),
+        ];
+        let actions = plan(
+            &policy,
+            &sessions,
+            &agent_commands(),
+            &HashMap::new(),
+            now(),
+            Path::to_path_buf,
+        );
+        assert_eq!(summary(&actions), vec![(ActionKind::Kill, "agent")]);
+    }
+
+    #[test]
+    fn test_no_policies_do_nothing() {
+        let sessions = [session("stale", "/a", 10_000, false)];
+        let actions = plan(
+            &CleanupConfig::default(),
+            &sessions,
+            &agent_commands(),
+            &HashMap::new(),
+            now(),
+            Path::to_path_buf,
+        );
+        assert!(actions.is_empty());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(1800)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("2d"), Ok(Duration::from_secs(172_800)));
+        for invalid in ["", "4", "h", "0m", "4x", "-1h", "99999999999999999999d"] {
+            assert!(parse_duration(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(0)), "0s");
+        assert_eq!(format_duration(Duration::from_secs(45)), "45s");
+        assert_eq!(format_duration(Duration::from_secs(4 * 3600)), "4h");
+        assert_eq!(
+            format_duration(Duration::from_secs(5 * 3600 + 125)),
+            "5h 2m"
+        );
+        assert_eq!(format_duration(Duration::from_secs(90_000)), "1d 1h");
+    }
+
+    #[test]
+    fn test_repo_root() {
+        let dir = std::env::temp_dir().join(format!("ams-cleanup-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join(".git")).unwrap();
+        std::fs::create_dir_all(dir.join("src/deep")).unwrap();
+        assert_eq!(repo_root(&dir.join("src/deep")), dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            repo_root(Path::new("/nonexistent/dir")),
+            PathBuf::from("/nonexistent/dir")
+        );
+    }
+}
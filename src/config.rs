@@ -0,0 +1,943 @@
+//! User configuration loaded from `~/.config/ams/config.toml`.
+
+use crate::environment::{self, EnvError, EnvValue};
+use crate::shell;
+use crate::tmux::{Host, Socket};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::DirBuilder;
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+/// Errors that can occur while loading the configuration.
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    /// Failed to read the configuration file.
+    #[error("Failed to read config file {0}: {1}")]
+    Io(PathBuf, #[source] std::io::Error),
+
+    /// The configuration file is not valid TOML or has invalid values.
+    #[error("Invalid config file {0}: {1}")]
+    Parse(PathBuf, #[source] toml::de::Error),
+
+    /// A host name that is not defined under `[hosts]`.
+    #[error("Unknown host: {0} (define it under [hosts.{0}])")]
+    UnknownHost(String),
+
+    /// An agent name that is not defined under `[agents]`.
+    #[error("Unknown agent: {0} (define it under [agents.{0}])")]
+    UnknownAgent(String),
+}
+
+/// Top-level ams configuration
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Agent to spawn when none is given
+    pub default_agent: Option<String>,
+    /// Agent command definitions, keyed by agent name
+    pub agents: BTreeMap<String, AgentProfile>,
+    /// TUI settings
+    pub tui: TuiConfig,
+    /// Session watcher and notification settings
+    pub notifications: NotificationConfig,
+    /// Which tmux servers to use
+    pub tmux: TmuxConfig,
+    /// Remote hosts reached over SSH, keyed by host name
+    pub hosts: BTreeMap<String, HostConfig>,
+    /// Defaults for sessions created with `ams new`
+    pub session: SessionConfig,
+    /// Policies applied to forgotten sessions by `ams gc`
+    pub cleanup: CleanupConfig,
+    /// Concurrency limits for `ams queue`
+    pub queue: QueueConfig,
+}
+
+/// Concurrency limits for `ams queue`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct QueueConfig {
+    /// Tasks of one agent running at once
+    pub max_per_agent: usize,
+    /// Per-agent overrides of `max_per_agent`, keyed by agent name
+    pub agents: BTreeMap<String, usize>,
+    /// Tasks running at once on one host
+    pub max_per_host: Option<usize>,
+    /// How often `ams queue run` checks tasks
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub interval: Duration,
+    /// How long a new session may take to show the agent's prompt
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub ready_timeout: Duration,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            max_per_agent: 2,
+            agents: BTreeMap::new(),
+            max_per_host: None,
+            interval: Duration::from_secs(5),
+            ready_timeout: Duration::from_secs(2 * 60),
+        }
+    }
+}
+
+/// Policies applied to forgotten sessions by `ams gc`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct CleanupConfig {
+    /// Kill detached sessions with no activity for this long, e.g. `4h`
+    #[serde(deserialize_with = "deserialize_optional_duration")]
+    pub kill_idle_after: Option<Duration>,
+    /// Detach clients from sessions whose agent has been waiting for input
+    /// for this long, e.g. `30m`
+    #[serde(deserialize_with = "deserialize_optional_duration")]
+    pub detach_waiting_after: Option<Duration>,
+    /// Kill the least recently active detached sessions of a repository
+    /// beyond this many
+    pub max_sessions_per_repo: Option<usize>,
+    /// Show a desktop notification with `notify-send` for every action
+    pub notify_send: bool,
+    /// How often the daemon and `ams gc --watch` apply the policies
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub interval: Duration,
+}
+
+impl CleanupConfig {
+    /// Whether any policy is set
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.kill_idle_after.is_none()
+            && self.detach_waiting_after.is_none()
+            && self.max_sessions_per_repo.is_none()
+    }
+}
+
+impl Default for CleanupConfig {
+    fn default() -> Self {
+        Self {
+            kill_idle_after: None,
+            detach_waiting_after: None,
+            max_sessions_per_repo: None,
+            notify_send: false,
+            interval: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+/// Reads a duration such as `30m` or `4h`
+fn deserialize_duration<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let text = String::deserialize(deserializer)?;
+    crate::cleanup::parse_duration(&text).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_optional_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    deserialize_duration(deserializer).map(Some)
+}
+
+/// Defaults for sessions created with `ams new`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// Prepended to session names that do not already start with it
+    pub prefix: Option<String>,
+    /// Environment variables set in the session
+    pub env: BTreeMap<String, EnvValue>,
+    /// Shell command run in the session directory before the session is
+    /// created, e.g. `npm install`
+    pub setup: Option<String>,
+    /// Directory for the project's git worktrees, exported to the session
+    /// as `AMS_WORKTREE_ROOT`
+    pub worktree_root: Option<PathBuf>,
+}
+
+impl SessionConfig {
+    /// Returns the session name with the prefix applied
+    #[must_use]
+    pub fn session_name(&self, name: &str) -> String {
+        match &self.prefix {
+            Some(prefix) if !name.starts_with(prefix.as_str()) => format!("{prefix}{name}"),
+            _ => name.to_string(),
+        }
+    }
+
+    /// Returns the environment for the session, including
+    /// `AMS_WORKTREE_ROOT`, with secrets resolved
+    pub fn environment(&self) -> Result<BTreeMap<String, String>, EnvError> {
+        let mut env = environment::resolve(&self.env)?;
+        if let Some(root) = &self.worktree_root {
+            env.insert(
+                "AMS_WORKTREE_ROOT".to_string(),
+                root.to_string_lossy().into_owned(),
+            );
+        }
+        Ok(env)
+    }
+}
+
+/// Which tmux servers to use
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct TmuxConfig {
+    /// Socket name, as with `tmux -L`
+    pub socket_name: Option<String>,
+    /// Socket path, as with `tmux -S`; takes precedence over `socket_name`
+    pub socket_path: Option<PathBuf>,
+    /// Servers whose sessions `ams list` and the TUI show together, as
+    /// socket names, socket paths or `default`
+    pub servers: Vec<String>,
+    /// Hosts whose sessions `ams list` and the TUI show together, as names
+    /// from `[hosts]` or `local`
+    pub hosts: Vec<String>,
+}
+
+impl TmuxConfig {
+    /// Returns the configured server
+    #[must_use]
+    pub fn socket(&self) -> Socket {
+        match (&self.socket_path, &self.socket_name) {
+            (Some(path), _) => Socket::Path(path.clone()),
+            (None, Some(name)) => Socket::Name(name.clone()),
+            (None, None) => Socket::Default,
+        }
+    }
+}
+
+/// How to reach a remote host
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct HostConfig {
+    /// SSH destination; defaults to the host name
+    pub destination: Option<String>,
+    /// Program that runs a command on the host, normally `ssh`
+    pub command: String,
+    /// Arguments passed to the program before the destination
+    pub args: Vec<String>,
+}
+
+impl Default for HostConfig {
+    fn default() -> Self {
+        Self {
+            destination: None,
+            command: "ssh".to_string(),
+            args: Vec::new(),
+        }
+    }
+}
+
+/// How to launch a coding agent
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct AgentProfile {
+    /// Executable to run
+    pub command: String,
+    /// Arguments passed to the executable
+    pub args: Vec<String>,
+    /// Foreground commands tmux shows for the agent's pane, e.g. `node`
+    /// for agents run by Node.js; defaults to the file name of `command`
+    pub processes: Vec<String>,
+    /// Environment variables for sessions running the agent
+    pub env: BTreeMap<String, EnvValue>,
+    /// Patterns matching the agent's input prompt, which tell when it is
+    /// ready for an initial prompt; default to the `waiting` state patterns
+    pub ready_patterns: Vec<String>,
+}
+
+impl AgentProfile {
+    /// Returns the command and its arguments as a shell command line
+    #[must_use]
+    pub fn command_line(&self) -> String {
+        std::iter::once(&self.command)
+            .chain(&self.args)
+            .map(|word| shell::word(word))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Returns the foreground commands that identify the agent's pane
+    #[must_use]
+    pub fn process_names(&self) -> Vec<String> {
+        if !self.processes.is_empty() {
+            return self.processes.clone();
+        }
+        Path::new(&self.command)
+            .file_name()
+            .map(|name| vec![name.to_string_lossy().into_owned()])
+            .unwrap_or_default()
+    }
+}
+
+/// Agent pane commands assumed when no agents are configured
+pub const DEFAULT_AGENT_COMMANDS: [&str; 3] = ["claude", "codex", "aider"];
+
+/// TUI settings
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct TuiConfig {
+    /// How often the dashboard refreshes, in milliseconds
+    pub refresh_rate_ms: u64,
+    /// Columns of the session table, in order
+    pub columns: Vec<Column>,
+    /// Column the sessions are sorted by
+    pub sort: Column,
+    /// Sort in descending order
+    pub sort_reverse: bool,
+}
+
+impl Default for TuiConfig {
+    fn default() -> Self {
+        Self {
+            refresh_rate_ms: 1000,
+            columns: vec![
+                Column::Name,
+                Column::Status,
+                Column::Agent,
+                Column::Windows,
+                Column::Tags,
+                Column::Dir,
+                Column::LastActivity,
+            ],
+            sort: Column::Name,
+            sort_reverse: false,
+        }
+    }
+}
+
+/// A column of the TUI session table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Column {
+    /// Session name
+    Name,
+    /// Whether a client is attached
+    Status,
+    /// Agent profile, or the command in the agent pane
+    Agent,
+    /// What the agent is doing, detected from its screen
+    State,
+    /// Number of windows
+    Windows,
+    /// Working directory of the agent pane
+    Dir,
+    /// Git branch of the working directory
+    Branch,
+    /// Time since the agent pane's window was last active
+    LastActivity,
+    /// Time since the session was created
+    Age,
+    /// CPU use of the processes in the session's panes
+    Cpu,
+    /// Resident memory of the processes in the session's panes
+    Memory,
+    /// Tags from `ams tag`; hidden while no session has any
+    Tags,
+}
+
+/// Session watcher and notification settings
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct NotificationConfig {
+    /// How often sessions are polled for state changes, in milliseconds
+    pub poll_interval_ms: u64,
+    /// Screen patterns used to detect agent states
+    pub patterns: StatePatterns,
+    /// What to do when a session changes state
+    pub rules: Vec<NotificationRule>,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval_ms: 2000,
+            patterns: StatePatterns::default(),
+            rules: vec![NotificationRule {
+                on: vec![
+                    Trigger::Waiting,
+                    Trigger::NeedsApproval,
+                    Trigger::Finished,
+                    Trigger::Errored,
+                    Trigger::Bell,
+                ],
+                command: None,
+                notify_send: false,
+                bell: true,
+            }],
+        }
+    }
+}
+
+/// Regular expressions matched against the bottom of a pane's screen
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct StatePatterns {
+    /// The agent is idle at its input prompt
+    pub waiting: Vec<String>,
+    /// The agent asks for permission to continue
+    pub needs_approval: Vec<String>,
+    /// The agent hit an error
+    pub errored: Vec<String>,
+    /// Foreground commands meaning the agent exited back to a shell
+    pub shells: Vec<String>,
+}
+
+impl Default for StatePatterns {
+    fn default() -> Self {
+        Self {
+            waiting: vec![
+                r"^\s*[│┃|]?\s*>\s*[│┃|]?\s*$".to_string(),
+                r"^\s*\w*>\s*$".to_string(),
+            ],
+            needs_approval: vec![
+                r"(?i)do you want to (proceed|continue|allow|make this edit)".to_string(),
+                r"(?i)[\[(]y/n[\])]".to_string(),
+                r"(?i)\ballow\b.*\?\s*$".to_string(),
+            ],
+            errored: vec![
+                r"(?i)^\s*error(\[\w+\])?:".to_string(),
+                r"panicked at".to_string(),
+                r"^Traceback \(most recent call last\)".to_string(),
+            ],
+            shells: ["bash", "zsh", "fish", "sh", "dash", "ksh", "tcsh", "nu"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        }
+    }
+}
+
+/// Condition that fires a notification rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    /// Session started waiting for input
+    Waiting,
+    /// Session started asking for approval
+    NeedsApproval,
+    /// Agent exited
+    Finished,
+    /// Agent errored
+    Errored,
+    /// A window in the session rang the bell
+    Bell,
+    /// A window in the session had monitored activity
+    Activity,
+}
+
+impl std::fmt::Display for Trigger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Waiting => write!(f, "waiting"),
+            Self::NeedsApproval => write!(f, "needs_approval"),
+            Self::Finished => write!(f, "finished"),
+            Self::Errored => write!(f, "errored"),
+            Self::Bell => write!(f, "bell"),
+            Self::Activity => write!(f, "activity"),
+        }
+    }
+}
+
+/// Actions to run when any of the triggers fire
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct NotificationRule {
+    /// Triggers this rule reacts to
+    pub on: Vec<Trigger>,
+    /// Shell command to run, with `AMS_*` variables describing the session
+    #[serde(default)]
+    pub command: Option<String>,
+    /// Show a desktop notification with `notify-send`
+    #[serde(default)]
+    pub notify_send: bool,
+    /// Ring the terminal bell
+    #[serde(default)]
+    pub bell: bool,
+}
+
+impl Config {
+    /// Loads the configuration from the default location
+    ///
+    /// A missing file yields the default configuration.
+    pub fn load() -> Result<Self, ConfigError> {
+        match config_path() {
+            Some(path) if path.exists() => Self::load_from(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Loads the configuration from a specific file
+    pub fn load_from(path: &Path) -> Result<Self, ConfigError> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        Self::parse(&contents).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))
+    }
+
+    /// Parses configuration from TOML text
+    pub fn parse(contents: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(contents)
+    }
+
+    /// Returns the foreground commands that identify agent panes, from the
+    /// agent profiles or `DEFAULT_AGENT_COMMANDS` when there are none
+    #[must_use]
+    pub fn agent_commands(&self) -> Vec<String> {
+        if self.agents.is_empty() {
+            return DEFAULT_AGENT_COMMANDS.map(String::from).to_vec();
+        }
+        self.agents
+            .values()
+            .flat_map(AgentProfile::process_names)
+            .collect()
+    }
+
+    /// Looks up an agent profile by name
+    pub fn agent(&self, name: &str) -> Result<&AgentProfile, ConfigError> {
+        self.agents
+            .get(name)
+            .ok_or_else(|| ConfigError::UnknownAgent(name.to_string()))
+    }
+
+    /// Looks up a host by name; `local` is the local machine
+    pub fn host(&self, name: &str) -> Result<Option<Host>, ConfigError> {
+        if name == Host::LOCAL {
+            return Ok(None);
+        }
+        let host = self
+            .hosts
+            .get(name)
+            .ok_or_else(|| ConfigError::UnknownHost(name.to_string()))?;
+        Ok(Some(Host {
+            name: name.to_string(),
+            destination: host.destination.clone().unwrap_or_else(|| name.to_string()),
+            command: host.command.clone(),
+            args: host.args.clone(),
+        }))
+    }
+}
+
+/// Returns the path of the user configuration file
+///
+/// Honors `XDG_CONFIG_HOME`, falling back to `~/.config`.
+#[must_use]
+pub fn config_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("ams").join("config.toml"))
+}
+
+/// Returns the directory for state that outlives a reboot, such as the
+/// task queue
+///
+/// Uses `$XDG_STATE_HOME/ams`, falling back to `~/.local/state/ams`.
+#[must_use]
+pub fn state_dir() -> PathBuf {
+    std::env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state"))
+        })
+        .map_or_else(runtime_dir, |dir| dir.join("ams"))
+}
+
+/// Returns the file recording which project configs the user trusts
+#[must_use]
+pub fn trust_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("ams").join("trusted.toml"))
+}
+
+/// Returns the directory holding session templates (`ams/templates`)
+#[must_use]
+pub fn templates_dir() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("ams").join("templates"))
+}
+
+/// Returns the directory for sockets and other per-session runtime files
+///
+/// Uses `$XDG_RUNTIME_DIR/ams`, falling back to a per-user directory under
+/// the system temp dir. Create it with [`create_private_dir`].
+#[must_use]
+pub fn runtime_dir() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map_or_else(
+            || {
+                let user = std::env::var("USER").unwrap_or_else(|_| "default".to_string());
+                std::env::temp_dir().join(format!("ams-{user}"))
+            },
+            |dir| PathBuf::from(dir).join("ams"),
+        )
+}
+
+/// Creates `dir` with mode 0700, or checks that the existing directory is
+/// owned by the current user with mode 0700
+///
+/// The runtime directory may sit in the shared temp dir, where another user
+/// could create it first to take over the daemon socket.
+pub fn create_private_dir(dir: &Path) -> io::Result<()> {
+    DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    let metadata = std::fs::symlink_metadata(dir)?;
+    // SAFETY: getuid has no preconditions and cannot fail
+    let uid = unsafe { libc::getuid() };
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o777 != 0o700 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "not a directory owned by the current user with mode 0700",
+        ));
+    }
+    Ok(())
+}
+
+fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_private_dir() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("ams-private-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        create_private_dir(&dir.join("ams")).unwrap();
+        let mode = std::fs::metadata(dir.join("ams"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o700);
+        assert!(create_private_dir(&dir.join("ams")).is_ok());
+
+        std::fs::set_permissions(dir.join("ams"), std::fs::Permissions::from_mode(0o755)).unwrap();
+        let err = create_private_dir(&dir.join("ams")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+        std::os::unix::fs::symlink(dir.join("ams"), dir.join("link")).unwrap();
+        assert!(create_private_dir(&dir.join("link")).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_empty_uses_defaults() {
+        let config = Config::parse("").unwrap();
+        assert!(config.default_agent.is_none());
+        assert!(config.agents.is_empty());
+        assert_eq!(config.tui.refresh_rate_ms, 1000);
+        assert_eq!(config.notifications, NotificationConfig::default());
+    }
+
+    #[test]
+    fn test_parse_readme_example() {
+        let config = Config::parse(
+            r#"
+            default_agent = "claude-code"
+
+            [agents.claude-code]
+            command = "claude"
+            args = []
+
+            [agents.aider]
+            command = "aider"
+            args = ["--no-auto-commits"]
+
+            [tui]
+            refresh_rate_ms = 500
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.default_agent.as_deref(), Some("claude-code"));
+        assert_eq!(config.agents["claude-code"].command, "claude");
+        assert_eq!(config.agents["aider"].args, vec!["--no-auto-commits"]);
+        assert_eq!(config.tui.refresh_rate_ms, 500);
+        assert_eq!(config.tui.columns, TuiConfig::default().columns);
+    }
+
+    #[test]
+    fn test_parse_tui_columns() {
+        let config = Config::parse(
+            r#"
+            [tui]
+            columns = ["name", "state", "branch", "last_activity", "cpu", "memory"]
+            sort = "last_activity"
+            sort_reverse = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.tui.columns,
+            vec![
+                Column::Name,
+                Column::State,
+                Column::Branch,
+                Column::LastActivity,
+                Column::Cpu,
+                Column::Memory,
+            ]
+        );
+        assert_eq!(config.tui.sort, Column::LastActivity);
+        assert!(config.tui.sort_reverse);
+
+        assert!(Config::parse("[tui]\ncolumns = [\"uptime\"]").is_err());
+    }
+
+    #[test]
+    fn test_parse_notifications() {
+        let config = Config::parse(
+            r#"
+            [notifications]
+            poll_interval_ms = 500
+
+            [notifications.patterns]
+            waiting = ["^READY$"]
+
+            [[notifications.rules]]
+            on = ["needs_approval", "bell"]
+            command = "say approve"
+            notify_send = true
+            "#,
+        )
+        .unwrap();
+        let notifications = config.notifications;
+        assert_eq!(notifications.poll_interval_ms, 500);
+        assert_eq!(notifications.patterns.waiting, vec!["^READY$"]);
+        assert_eq!(
+            notifications.patterns.shells,
+            StatePatterns::default().shells
+        );
+        assert_eq!(notifications.rules.len(), 1);
+        assert_eq!(
+            notifications.rules[0].on,
+            vec![Trigger::NeedsApproval, Trigger::Bell]
+        );
+        assert_eq!(
+            notifications.rules[0].command.as_deref(),
+            Some("say approve")
+        );
+        assert!(notifications.rules[0].notify_send);
+        assert!(!notifications.rules[0].bell);
+    }
+
+    #[test]
+    fn test_parse_tmux_servers() {
+        let config = Config::parse(
+            r#"
+            [tmux]
+            socket_name = "agents"
+            servers = ["default", "agents", "/tmp/other.sock"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.tmux.socket(), Socket::Name("agents".to_string()));
+        assert_eq!(config.tmux.servers.len(), 3);
+
+        let config =
+            Config::parse("[tmux]\nsocket_name = \"a\"\nsocket_path = \"/tmp/s\"").unwrap();
+        assert_eq!(config.tmux.socket(), Socket::Path(PathBuf::from("/tmp/s")));
+        assert_eq!(Config::default().tmux.socket(), Socket::Default);
+    }
+
+    #[test]
+    fn test_agent_commands() {
+        assert_eq!(
+            Config::default().agent_commands(),
+            vec!["claude", "codex", "aider"]
+        );
+
+        let config = Config::parse(
+            r#"
+            [agents.claude-code]
+            command = "/usr/local/bin/claude"
+
+            [agents.codex]
+            command = "codex"
+            processes = ["node", "codex"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.agent_commands(), vec!["claude", "node", "codex"]);
+    }
+
+    #[test]
+    fn test_agent_command_line() {
+        let config = Config::parse(
+            r#"
+            [agents.claude-code]
+            command = "claude"
+            args = ["--model=opus", "my prompt", ""]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.agent("claude-code").unwrap().command_line(),
+            "claude --model=opus 'my prompt' ''"
+        );
+        assert_eq!(
+            config.agent("codex").unwrap_err().to_string(),
+            "Unknown agent: codex (define it under [agents.codex])"
+        );
+    }
+
+    #[test]
+    fn test_session_defaults() {
+        let config = Config::parse(
+            r#"
+            [session]
+            prefix = "web-"
+            worktree_root = "/src/worktrees"
+            env = { NODE_ENV = "development", TOKEN = { command = "echo secret" } }
+            "#,
+        )
+        .unwrap();
+        let session = &config.session;
+        assert_eq!(session.session_name("api"), "web-api");
+        assert_eq!(session.session_name("web-api"), "web-api");
+        assert_eq!(Config::default().session.session_name("api"), "api");
+
+        let env = session.environment().unwrap();
+        assert_eq!(env["NODE_ENV"], "development");
+        assert_eq!(env["TOKEN"], "secret");
+        assert_eq!(env["AMS_WORKTREE_ROOT"], "/src/worktrees");
+    }
+
+    #[test]
+    fn test_parse_cleanup() {
+        let config = Config::parse(
+            r#"
+            [cleanup]
+            kill_idle_after = "4h"
+            detach_waiting_after = "30m"
+            max_sessions_per_repo = 3
+            "#,
+        )
+        .unwrap();
+        let cleanup = &config.cleanup;
+        assert_eq!(cleanup.kill_idle_after, Some(Duration::from_secs(4 * 3600)));
+        assert_eq!(
+            cleanup.detach_waiting_after,
+            Some(Duration::from_secs(1800))
+        );
+        assert_eq!(cleanup.max_sessions_per_repo, Some(3));
+        assert_eq!(cleanup.interval, Duration::from_secs(300));
+        assert!(!cleanup.is_empty());
+        assert!(Config::default().cleanup.is_empty());
+
+        let err = Config::parse("[cleanup]\nkill_idle_after = \"soon\"").unwrap_err();
+        assert!(err.to_string().contains("invalid duration"));
+    }
+
+    #[test]
+    fn test_parse_queue() {
+        let queue = Config::parse(
+            r#"
+            [queue]
+            max_per_agent = 1
+            agents = { codex = 3 }
+            max_per_host = 4
+            ready_timeout = "5m"
+            "#,
+        )
+        .unwrap()
+        .queue;
+        assert_eq!(queue.max_per_agent, 1);
+        assert_eq!(queue.agents["codex"], 3);
+        assert_eq!(queue.max_per_host, Some(4));
+        assert_eq!(queue.interval, Duration::from_secs(5));
+        assert_eq!(queue.ready_timeout, Duration::from_secs(300));
+    }
+
+    #[test]
+    fn test_host_lookup() {
+        let config = Config::parse(
+            r#"
+            [tmux]
+            hosts = ["local", "devbox"]
+
+            [hosts.devbox]
+
+            [hosts.gpu]
+            destination = "me@gpu.example.com"
+            args = ["-p", "2222"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.tmux.hosts, vec!["local", "devbox"]);
+        assert_eq!(config.host("local").unwrap(), None);
+        assert_eq!(
+            config.host("devbox").unwrap(),
+            Some(Host::new("devbox", "devbox"))
+        );
+
+        let gpu = config.host("gpu").unwrap().unwrap();
+        assert_eq!(gpu.destination, "me@gpu.example.com");
+        assert_eq!(gpu.command, "ssh");
+        assert_eq!(gpu.args, vec!["-p", "2222"]);
+
+        let err = config.host("nope").unwrap_err();
+        assert!(matches!(err, ConfigError::UnknownHost(_)));
+        assert_eq!(
+            err.to_string(),
+            "Unknown host: nope (define it under [hosts.nope])"
+        );
+    }
+
+    #[test]
+    fn test_trigger_display_matches_config_names() {
+        for trigger in [
+            Trigger::Waiting,
+            Trigger::NeedsApproval,
+            Trigger::Finished,
+            Trigger::Errored,
+            Trigger::Bell,
+            Trigger::Activity,
+        ] {
+            let rule = format!("on = [\"{trigger}\"]");
+            let parsed: NotificationRule = toml::from_str(&rule).unwrap();
+            assert_eq!(parsed.on, vec![trigger]);
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid_trigger() {
+        assert!(Config::parse("[[notifications.rules]]\non = [\"sometimes\"]").is_err());
+    }
+
+    #[test]
+    fn test_load_from_missing_file() {
+        let err = Config::load_from(Path::new("/nonexistent/ams/config.toml")).unwrap_err();
+        assert!(matches!(err, ConfigError::Io(_, _)));
+        assert!(err.to_string().starts_with("Failed to read config file"));
+    }
+
+    #[test]
+    fn test_load_from_invalid_file() {
+        let path = std::env::temp_dir().join(format!("ams-config-{}.toml", std::process::id()));
+        std::fs::write(&path, "tui = 3").unwrap();
+        let err = Config::load_from(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(err, ConfigError::Parse(_, _)));
+    }
+
+    #[test]
+    fn test_default_patterns_compile() {
+        let patterns = StatePatterns::default();
+        for pattern in patterns
+            .waiting
+            .iter()
+            .chain(&patterns.needs_approval)
+            .chain(&patterns.errored)
+        {
+            assert!(regex::Regex::new(pattern).is_ok(), "{pattern}");
+        }
+    }
+}
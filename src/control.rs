@@ -0,0 +1,510 @@
+//! Tmux control-mode (`tmux -C`) client for event-driven updates.
+//!
+//! A control-mode client keeps one tmux process running and receives
+//! notifications such as `%sessions-changed` as they happen, and it runs
+//! commands over the same connection, so consumers do not need to spawn a
+//! process for every refresh.
+
+use crate::session::Session;
+use crate::tmux::{parse_sessions, TmuxError, LIST_SESSIONS, SESSION_FORMAT};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryIter};
+use std::time::Duration;
+
+/// Name of the hidden session the control client attaches to
+///
+/// The session is destroyed when the control client disconnects and is
+/// left out of session listings.
+pub const CONTROL_SESSION: &str = "__ams_control";
+
+/// How long to wait for the reply to a control-mode command
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A notification received from a control-mode connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlEvent {
+    /// A pane in the attached session produced output
+    Output {
+        /// Pane ID, e.g. `%1`
+        pane_id: String,
+        /// Raw bytes written to the pane
+        data: Vec<u8>,
+    },
+    /// The control client was attached to another session
+    SessionChanged {
+        /// Session ID, e.g. `$1`
+        session_id: String,
+        /// Session name
+        name: String,
+    },
+    /// A session was created or destroyed
+    SessionsChanged,
+    /// A session was renamed
+    SessionRenamed {
+        /// Session ID, e.g. `$1`
+        session_id: String,
+        /// New session name
+        name: String,
+    },
+    /// A window was added to any session
+    WindowAdd {
+        /// Window ID, e.g. `@1`
+        window_id: String,
+    },
+    /// A window was closed in any session
+    WindowClose {
+        /// Window ID, e.g. `@1`
+        window_id: String,
+    },
+    /// A window was renamed
+    WindowRenamed {
+        /// Window ID, e.g. `@1`
+        window_id: String,
+        /// New window name
+        name: String,
+    },
+    /// The control client is exiting
+    Exit {
+        /// Why tmux closed the connection, if it said
+        reason: Option<String>,
+    },
+    /// Any other notification, kept verbatim without the leading `%`
+    Other(String),
+}
+
+impl ControlEvent {
+    /// Whether the event can change the result of `list_sessions`
+    #[must_use]
+    pub const fn changes_sessions(&self) -> bool {
+        matches!(
+            self,
+            Self::SessionsChanged
+                | Self::SessionRenamed { .. }
+                | Self::WindowAdd { .. }
+                | Self::WindowClose { .. }
+        )
+    }
+}
+
+/// One line of control-mode output, classified
+#[derive(Debug, PartialEq, Eq)]
+enum Line {
+    /// Start of a command reply; `from_client` is false for replies to the
+    /// commands given on the tmux command line
+    Begin { number: u64, from_client: bool },
+    /// End of a command reply
+    End { number: u64, success: bool },
+    /// An asynchronous notification
+    Event(ControlEvent),
+}
+
+/// Reply to a command: output lines, or the error lines on failure
+type Reply = Result<Vec<String>, String>;
+
+/// A running control-mode connection
+pub struct ControlClient {
+    child: Child,
+    stdin: ChildStdin,
+    events: Receiver<ControlEvent>,
+    replies: Receiver<Reply>,
+}
+
+impl ControlClient {
+    /// Connects to the running tmux server through a hidden helper session
+    ///
+    /// Delivers session and window notifications for the whole server. Does
+    /// not start a server when none is running.
+    pub fn connect() -> Result<Self, TmuxError> {
+        if !crate::tmux::server_running()? {
+            return Err(TmuxError::ServerNotRunning);
+        }
+
+        Self::spawn(&[
+            "new-session",
+            "-A",
+            "-s",
+            CONTROL_SESSION,
+            ";",
+            "set-option",
+            "destroy-unattached",
+            "on",
+        ])
+    }
+
+    /// Attaches read-only to a session to also receive its pane output
+    ///
+    /// Note that tmux counts the control client as an attached client.
+    pub fn attach(session: &str) -> Result<Self, TmuxError> {
+        crate::tmux::get_session(session)?;
+        Self::spawn(&["attach-session", "-r", "-t", session])
+    }
+
+    fn spawn(args: &[&str]) -> Result<Self, TmuxError> {
+        let mut child = crate::tmux::tmux_command()
+            .arg("-C")
+            .args(args)
+            .command()
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| TmuxError::ControlMode("control client has no stdin".to_string()))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| TmuxError::ControlMode("control client has no stdout".to_string()))?;
+
+        let (event_tx, events) = mpsc::channel();
+        let (reply_tx, replies) = mpsc::channel();
+        std::thread::spawn(move || read_messages(BufReader::new(stdout), &event_tx, &reply_tx));
+
+        Ok(Self {
+            child,
+            stdin,
+            events,
+            replies,
+        })
+    }
+
+    /// Runs a tmux command over the connection and returns its output lines
+    pub fn command(&mut self, command: &str) -> Result<Vec<String>, TmuxError> {
+        writeln!(self.stdin, "{command}")?;
+        self.stdin.flush()?;
+
+        match self.replies.recv_timeout(REPLY_TIMEOUT) {
+            Ok(Ok(lines)) => Ok(lines),
+            Ok(Err(message)) => Err(TmuxError::ParseError(message)),
+            Err(RecvTimeoutError::Timeout) => {
+                Err(TmuxError::ControlMode(format!("no reply to `{command}`")))
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                Err(TmuxError::ControlMode("connection closed".to_string()))
+            }
+        }
+    }
+
+    /// Lists all sessions without spawning a tmux process
+    pub fn list_sessions(&mut self) -> Result<Vec<Session>, TmuxError> {
+        let lines = self.command(&format!("{} '{SESSION_FORMAT}'", LIST_SESSIONS.join(" ")))?;
+        parse_sessions(&lines.join("\n"))
+    }
+
+    /// Returns the notifications received so far without blocking
+    #[must_use]
+    pub fn try_events(&self) -> TryIter<'_, ControlEvent> {
+        self.events.try_iter()
+    }
+
+    /// Returns the notification channel, for blocking consumers
+    #[must_use]
+    pub const fn events(&self) -> &Receiver<ControlEvent> {
+        &self.events
+    }
+}
+
+impl Drop for ControlClient {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Reads control-mode output until the connection closes
+///
+/// Reply blocks to our own commands go to `replies`, notifications to
+/// `events`. An `Exit` event is always sent last.
+fn read_messages(mut reader: impl BufRead, events: &Sender<ControlEvent>, replies: &Sender<Reply>) {
+    let mut block: Option<(u64, bool, Vec<String>)> = None;
+    let mut line = Vec::new();
+
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+
+        if let Some((number, from_client, lines)) = &mut block {
+            match parse_line(&line) {
+                Some(Line::End {
+                    number: end,
+                    success,
+                }) if end == *number => {
+                    if *from_client {
+                        let lines = std::mem::take(lines);
+                        let reply = if success {
+                            Ok(lines)
+                        } else {
+                            Err(lines.join("\n"))
+                        };
+                        let _ = replies.send(reply);
+                    }
+                    block = None;
+                }
+                _ => lines.push(String::from_utf8_lossy(&line).into_owned()),
+            }
+            continue;
+        }
+
+        match parse_line(&line) {
+            Some(Line::Begin {
+                number,
+                from_client,
+            }) => block = Some((number, from_client, Vec::new())),
+            Some(Line::Event(event)) => {
+                let exit = matches!(event, ControlEvent::Exit { .. });
+                let _ = events.send(event);
+                if exit {
+                    return;
+                }
+            }
+            Some(Line::End { .. }) | None => {}
+        }
+    }
+
+    let _ = events.send(ControlEvent::Exit { reason: None });
+}
+
+/// Classifies one line of control-mode output outside a reply block
+fn parse_line(line: &[u8]) -> Option<Line> {
+    if let Some(rest) = line.strip_prefix(b"%output ") {
+        let split = rest.iter().position(|&b| b == b' ')?;
+        return Some(Line::Event(ControlEvent::Output {
+            pane_id: String::from_utf8_lossy(&rest[..split]).into_owned(),
+            data: unescape(&rest[split + 1..]),
+        }));
+    }
+
+    let text = String::from_utf8_lossy(line);
+    let text = text.strip_prefix('%')?;
+    let (name, args) = text.split_once(' ').unwrap_or((text, ""));
+    let mut fields = args.splitn(2, ' ');
+    let mut field = || fields.next().unwrap_or_default().to_string();
+
+    let event = match name {
+        "begin" | "end" | "error" => {
+            let mut parts = args.split(' ').skip(1);
+            let number = parts.next()?.parse().ok()?;
+            let flags: u32 = parts.next()?.parse().ok()?;
+            return Some(match name {
+                "begin" => Line::Begin {
+                    number,
+                    from_client: flags & 1 == 1,
+                },
+                _ => Line::End {
+                    number,
+                    success: name == "end",
+                },
+            });
+        }
+        "session-changed" => ControlEvent::SessionChanged {
+            session_id: field(),
+            name: field(),
+        },
+        "sessions-changed" => ControlEvent::SessionsChanged,
+        "session-renamed" => ControlEvent::SessionRenamed {
+            session_id: field(),
+            name: field(),
+        },
+        "window-add" | "unlinked-window-add" => ControlEvent::WindowAdd { window_id: field() },
+        "window-close" | "unlinked-window-close" => {
+            ControlEvent::WindowClose { window_id: field() }
+        }
+        "window-renamed" | "unlinked-window-renamed" => ControlEvent::WindowRenamed {
+            window_id: field(),
+            name: field(),
+        },
+        "exit" => ControlEvent::Exit {
+            reason: Some(args.to_string()).filter(|reason| !reason.is_empty()),
+        },
+        _ => ControlEvent::Other(text.to_string()),
+    };
+    Some(Line::Event(event))
+}
+
+/// Decodes the octal escapes (`\ooo`) tmux uses in `%output` data
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut i = 0;
+
+    while i < data.len() {
+        let octal = data
+            .get(i + 1..i + 4)
+            .filter(|digits| data[i] == b'\\' && digits.iter().all(|d| (b'0'..=b'7').contains(d)));
+        if let Some(digits) = octal {
+            let value = digits
+                .iter()
+                .fold(0u32, |acc, d| acc * 8 + u32::from(d - b'0'));
+            out.push(u8::try_from(value).unwrap_or(u8::MAX));
+            i += 4;
+        } else {
+            out.push(data[i]);
+            i += 1;
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn event(line: &str) -> ControlEvent {
+        match parse_line(line.as_bytes()) {
+            Some(Line::Event(event)) => event,
+            other => panic!("not an event: {other:?}"),
+        }
+    }
+
+    fn read(input: &str) -> (Vec<ControlEvent>, Vec<Reply>) {
+        let (event_tx, events) = mpsc::channel();
+        let (reply_tx, replies) = mpsc::channel();
+        read_messages(Cursor::new(input.as_bytes()), &event_tx, &reply_tx);
+        (events.try_iter().collect(), replies.try_iter().collect())
+    }
+
+    #[test]
+    fn test_parse_session_events() {
+        assert_eq!(
+            event("%session-changed $1 my session"),
+            ControlEvent::SessionChanged {
+                session_id: "$1".to_string(),
+                name: "my session".to_string(),
+            }
+        );
+        assert_eq!(event("%sessions-changed"), ControlEvent::SessionsChanged);
+        assert_eq!(
+            event("%session-renamed $2 agent"),
+            ControlEvent::SessionRenamed {
+                session_id: "$2".to_string(),
+                name: "agent".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_window_events() {
+        let add = ControlEvent::WindowAdd {
+            window_id: "@3".to_string(),
+        };
+        assert_eq!(event("%window-add @3"), add);
+        assert_eq!(event("%unlinked-window-add @3"), add);
+        assert_eq!(
+            event("%unlinked-window-close @3"),
+            ControlEvent::WindowClose {
+                window_id: "@3".to_string(),
+            }
+        );
+        assert_eq!(
+            event("%window-renamed @3 vim"),
+            ControlEvent::WindowRenamed {
+                window_id: "@3".to_string(),
+                name: "vim".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_output_unescapes_octal() {
+        assert_eq!(
+            event(r"%output %1 ls\015\012a\\b \134"),
+            ControlEvent::Output {
+                pane_id: "%1".to_string(),
+                data: b"ls\r\na\\\\b \\".to_vec(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_unescape_keeps_incomplete_escapes() {
+        assert_eq!(unescape(br"a\01"), br"a\01".to_vec());
+        assert_eq!(unescape(br"\089"), br"\089".to_vec());
+        assert_eq!(unescape("é".as_bytes()), "é".as_bytes().to_vec());
+    }
+
+    #[test]
+    fn test_parse_exit_and_unknown() {
+        assert_eq!(event("%exit"), ControlEvent::Exit { reason: None });
+        assert_eq!(
+            event("%exit server exited"),
+            ControlEvent::Exit {
+                reason: Some("server exited".to_string()),
+            }
+        );
+        assert_eq!(
+            event("%layout-change @1 abcd"),
+            ControlEvent::Other("layout-change @1 abcd".to_string())
+        );
+        assert!(parse_line(b"plain text").is_none());
+    }
+
+    #[test]
+    fn test_parse_block_markers() {
+        assert_eq!(
+            parse_line(b"%begin 1792360145 263 1"),
+            Some(Line::Begin {
+                number: 263,
+                from_client: true,
+            })
+        );
+        assert_eq!(
+            parse_line(b"%error 1792360145 263 1"),
+            Some(Line::End {
+                number: 263,
+                success: false,
+            })
+        );
+        assert!(parse_line(b"%begin garbage").is_none());
+    }
+
+    #[test]
+    fn test_changes_sessions() {
+        assert!(ControlEvent::SessionsChanged.changes_sessions());
+        assert!(event("%unlinked-window-add @2").changes_sessions());
+        assert!(!event("%output %1 x").changes_sessions());
+        assert!(!event("%session-changed $1 x").changes_sessions());
+    }
+
+    #[test]
+    fn test_read_messages_routes_replies_and_events() {
+        let (events, replies) = read(
+            "%begin 1 10 0\n%end 1 10 0\n\
+             %sessions-changed\n\
+             %begin 1 11 1\n%not-an-event\nline two\n%end 1 11 1\n\
+             %begin 1 12 1\nparse error: unknown command: bogus\n%error 1 12 1\n\
+             %exit\n%sessions-changed\n",
+        );
+        assert_eq!(
+            events,
+            vec![
+                ControlEvent::SessionsChanged,
+                ControlEvent::Exit { reason: None },
+            ]
+        );
+        assert_eq!(
+            replies,
+            vec![
+                Ok(vec!["%not-an-event".to_string(), "line two".to_string()]),
+                Err("parse error: unknown command: bogus".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_messages_reports_exit_on_eof() {
+        let (events, replies) = read("%window-add @1\n");
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1], ControlEvent::Exit { reason: None });
+        assert!(replies.is_empty());
+    }
+}
@@ -0,0 +1,881 @@
+//! Background daemon keeping the session model in memory.
+//!
+//! The daemon polls tmux on an interval, tracks agent state transitions,
+//! runs notifications, applies the cleanup policies and serves clients over a Unix domain socket. Each
+//! connection carries one request line and one response line of JSON, both
+//! wrapped in an [`Envelope`] tagged with [`PROTOCOL_VERSION`].
+
+use crate::cleanup::{self, Action};
+use crate::config::{self, Config, Trigger};
+use crate::notify;
+use crate::session::{AgentState, Session};
+use crate::tmux::{self, Socket, TmuxError};
+use crate::watch::{snapshot_sessions, StateMatcher, TransitionDetector};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Version of the JSON protocol spoken over the socket
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Maximum number of history entries kept in memory
+const HISTORY_LIMIT: usize = 1000;
+
+/// How long a client waits for the daemon to answer
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a refresh waits for its poll before answering with the cached
+/// sessions, well within `CLIENT_TIMEOUT`
+const REFRESH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Errors that can occur while talking to the daemon.
+#[derive(Error, Debug)]
+pub enum DaemonError {
+    /// The socket could not be reached or read.
+    #[error("Daemon connection failed: {0}")]
+    Io(#[from] io::Error),
+
+    /// A message could not be encoded or decoded.
+    #[error("Invalid daemon message: {0}")]
+    Protocol(#[from] serde_json::Error),
+
+    /// The daemon speaks a different protocol version.
+    #[error("Daemon protocol version {0} is not supported (expected {PROTOCOL_VERSION})")]
+    VersionMismatch(u32),
+
+    /// The daemon answered with an error.
+    #[error("Daemon error: {0}")]
+    Remote(String),
+
+    /// The daemon answered with an unexpected response type.
+    #[error("Unexpected daemon response")]
+    UnexpectedResponse,
+
+    /// The daemon has not read tmux yet.
+    #[error("Daemon has not read tmux yet")]
+    NotReady,
+
+    /// The socket directory could not be created or is not private.
+    #[error("Cannot use socket directory {0}: {1}")]
+    SocketDir(PathBuf, io::Error),
+
+    /// Another daemon is already listening on the socket.
+    #[error("Daemon already running on {0}")]
+    AlreadyRunning(PathBuf),
+}
+
+/// A message with its protocol version
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    /// Protocol version of the sender
+    pub version: u32,
+    /// The message itself
+    #[serde(flatten)]
+    pub body: T,
+}
+
+/// Requests a client can send
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    /// Checks that the daemon is alive
+    Ping,
+    /// Returns the cached session list
+    ListSessions,
+    /// Returns the current agent state of every session
+    States,
+    /// Returns recorded state transitions, newest last
+    History {
+        /// Only return entries for this session
+        #[serde(default)]
+        session: Option<String>,
+        /// Return at most this many entries
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+    /// Re-reads tmux now instead of waiting for the next poll
+    Refresh,
+    /// Stops the daemon
+    Shutdown,
+}
+
+/// Responses the daemon sends
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    /// Answer to [`Request::Ping`]
+    Pong {
+        /// Version of the ams binary running the daemon
+        daemon_version: String,
+        /// Seconds since the daemon started
+        uptime_secs: u64,
+    },
+    /// Answer to [`Request::ListSessions`] and [`Request::Refresh`]
+    Sessions {
+        /// Sessions as of the last poll
+        sessions: Vec<Session>,
+        /// When tmux was last polled successfully
+        refreshed_at: Option<DateTime<Utc>>,
+    },
+    /// Answer to [`Request::States`]
+    States {
+        /// Agent state keyed by session name
+        states: HashMap<String, AgentState>,
+    },
+    /// Answer to [`Request::History`]
+    History {
+        /// Matching history entries
+        entries: Vec<HistoryEntry>,
+    },
+    /// The request succeeded with nothing to return
+    Ok,
+    /// The request failed
+    Error {
+        /// What went wrong
+        message: String,
+    },
+}
+
+/// A recorded state transition
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// When the transition was observed
+    pub time: DateTime<Utc>,
+    /// Session name
+    pub session: String,
+    /// What happened
+    pub trigger: Trigger,
+    /// Agent state before the change
+    pub previous: AgentState,
+    /// Agent state after the change
+    pub state: AgentState,
+}
+
+/// The in-memory session model
+#[derive(Debug, Default)]
+struct Model {
+    sessions: Vec<Session>,
+    states: HashMap<String, AgentState>,
+    history: VecDeque<HistoryEntry>,
+    refreshed_at: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+    /// Polls begun and finished so far, to tell when a refresh is served
+    polls_started: u64,
+    polls_finished: u64,
+    /// When the cleanup policies were last applied
+    cleaned_at: Option<Instant>,
+}
+
+/// Returns the path of the daemon socket for the selected host and tmux
+/// server
+///
+/// Uses `$XDG_RUNTIME_DIR/ams/daemon.sock`, falling back to a per-user
+/// directory under the system temp dir. Daemons for other hosts or tmux
+/// servers get their own socket so clients never read another server's
+/// sessions.
+#[must_use]
+pub fn socket_path() -> PathBuf {
+    let host = tmux::host().map(|host| host.name);
+    config::runtime_dir().join(socket_file(host.as_deref(), &tmux::socket()))
+}
+
+fn socket_file(host: Option<&str>, socket: &Socket) -> String {
+    let server = match socket {
+        Socket::Default => String::new(),
+        Socket::Name(name) => format!("-{name}"),
+        Socket::Path(path) => format!("-{:016x}", fnv1a(path.as_os_str().as_bytes())),
+    };
+    let host = host.map(|host| format!("@{host}")).unwrap_or_default();
+    format!("daemon{server}{host}.sock")
+}
+
+/// Hashes bytes with 64-bit FNV-1a, which unlike `DefaultHasher` gives the
+/// same result in every build, so clients and daemons of different ams
+/// versions agree on the socket name
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The daemon server
+pub struct Daemon {
+    model: Mutex<Model>,
+    detector: Mutex<TransitionDetector>,
+    matcher: StateMatcher,
+    config: Config,
+    started: Instant,
+    /// Wakes the polling thread early, once it runs
+    wake: Option<SyncSender<()>>,
+    /// Signalled after every poll
+    polled: Condvar,
+}
+
+impl Daemon {
+    /// Creates a daemon from the user configuration
+    pub fn new(config: Config) -> Result<Self, regex::Error> {
+        Ok(Self {
+            matcher: StateMatcher::new(&config.notifications.patterns)?,
+            model: Mutex::new(Model::default()),
+            detector: Mutex::new(TransitionDetector::new()),
+            config,
+            started: Instant::now(),
+            wake: None,
+            polled: Condvar::new(),
+        })
+    }
+
+    /// Binds the socket and serves clients until a shutdown request arrives
+    pub fn run(mut self, path: &Path) -> Result<(), DaemonError> {
+        let listener = bind(path)?;
+        // One pending wake-up is enough: refreshes asked for while a poll
+        // runs are all served by the next one
+        let (wake, woken) = mpsc::sync_channel(1);
+        self.wake = Some(wake);
+        let daemon = Arc::new(self);
+        let interval = Duration::from_millis(daemon.config.notifications.poll_interval_ms);
+
+        let poller = Arc::clone(&daemon);
+        std::thread::spawn(move || loop {
+            poller.poll();
+            // Times out at the interval, or returns early on a refresh
+            let _ = woken.recv_timeout(interval);
+        });
+
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            if serve(&daemon, &stream) {
+                break;
+            }
+        }
+
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    /// Reads tmux once, updating the model and firing notifications, then
+    /// wakes the refreshes waiting for it
+    fn poll(&self) {
+        self.model().polls_started += 1;
+        self.read_tmux();
+        self.finish_poll();
+    }
+
+    fn finish_poll(&self) {
+        self.model().polls_finished += 1;
+        self.polled.notify_all();
+    }
+
+    /// Wakes the polling thread and waits, up to `REFRESH_TIMEOUT`, for a
+    /// poll begun after the request
+    fn refresh(&self) -> Response {
+        let Some(wake) = &self.wake else {
+            return self.sessions_response();
+        };
+        let requested = self.model().polls_started;
+        let _ = wake.try_send(());
+        drop(
+            self.polled
+                .wait_timeout_while(self.model(), REFRESH_TIMEOUT, |model| {
+                    model.polls_finished <= requested
+                })
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+        self.sessions_response()
+    }
+
+    fn read_tmux(&self) {
+        let result = tmux::list_sessions().and_then(|sessions| {
+            let snapshots = snapshot_sessions(&sessions, &self.matcher)?;
+            Ok((sessions, snapshots))
+        });
+
+        let (sessions, snapshots) = match result {
+            Ok(data) => data,
+            Err(e) => {
+                self.model().last_error = Some(e.to_string());
+                return;
+            }
+        };
+
+        let states = snapshots
+            .iter()
+            .map(|s| (s.name.clone(), s.state))
+            .collect();
+        let actions = self.cleanup_actions(&sessions, &states);
+        let events = self
+            .detector
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .update(snapshots);
+
+        {
+            let mut model = self.model();
+            model.sessions = sessions;
+            model.states = states;
+            model.refreshed_at = Some(Utc::now());
+            model.last_error = None;
+            for event in &events {
+                if model.history.len() == HISTORY_LIMIT {
+                    model.history.pop_front();
+                }
+                model.history.push_back(HistoryEntry {
+                    time: Utc::now(),
+                    session: event.session.clone(),
+                    trigger: event.trigger,
+                    previous: event.previous,
+                    state: event.state,
+                });
+            }
+        }
+
+        for event in &events {
+            for failure in notify::dispatch(&self.config.notifications.rules, event) {
+                eprintln!("Notification failed: {failure}");
+            }
+        }
+        self.apply_cleanup(&actions);
+    }
+
+    /// Evaluates the cleanup policies, at most once per `[cleanup]`
+    /// interval
+    fn cleanup_actions(
+        &self,
+        sessions: &[Session],
+        states: &HashMap<String, AgentState>,
+    ) -> Vec<Action> {
+        let policy = &self.config.cleanup;
+        if policy.is_empty() {
+            return Vec::new();
+        }
+        {
+            let mut model = self.model();
+            if model
+                .cleaned_at
+                .is_some_and(|at| at.elapsed() < policy.interval)
+            {
+                return Vec::new();
+            }
+            model.cleaned_at = Some(Instant::now());
+        }
+        cleanup::plan(
+            policy,
+            sessions,
+            &self.config.agent_commands(),
+            states,
+            Utc::now(),
+            cleanup::repo_root,
+        )
+    }
+
+    /// Carries out cleanup actions, then polls again so clients see the
+    /// result
+    fn apply_cleanup(&self, actions: &[Action]) {
+        for action in actions {
+            match action.apply() {
+                Ok(()) => {
+                    eprintln!(
+                        "Cleanup: {} {}: {}",
+                        action.kind, action.session, action.reason
+                    );
+                    if self.config.cleanup.notify_send {
+                        let body = format!("{} ({})", action.kind, action.reason);
+                        if let Err(e) = notify::notify_send(&action.session, &body) {
+                            eprintln!("Notification failed: {e}");
+                        }
+                    }
+                }
+                Err(e) => eprintln!("Failed to {} {}: {e}", action.kind, action.session),
+            }
+        }
+        if !actions.is_empty() {
+            if let Some(wake) = &self.wake {
+                let _ = wake.try_send(());
+            }
+        }
+    }
+
+    fn model(&self) -> std::sync::MutexGuard<'_, Model> {
+        self.model.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn handle(&self, request: Request) -> Response {
+        match request {
+            Request::Ping => Response::Pong {
+                daemon_version: env!("CARGO_PKG_VERSION").to_string(),
+                uptime_secs: self.started.elapsed().as_secs(),
+            },
+            Request::Refresh => self.refresh(),
+            Request::ListSessions => self.sessions_response(),
+            Request::States => Response::States {
+                states: self.model().states.clone(),
+            },
+            Request::History { session, limit } => {
+                let mut entries: Vec<HistoryEntry> = self
+                    .model()
+                    .history
+                    .iter()
+                    .filter(|entry| session.as_ref().map_or(true, |s| *s == entry.session))
+                    .cloned()
+                    .collect();
+                if let Some(limit) = limit {
+                    entries.drain(..entries.len().saturating_sub(limit));
+                }
+                Response::History { entries }
+            }
+            Request::Shutdown => Response::Ok,
+        }
+    }
+
+    fn sessions_response(&self) -> Response {
+        let model = self.model();
+        match (&model.last_error, model.refreshed_at) {
+            (Some(error), _) => Response::Error {
+                message: error.clone(),
+            },
+            (None, refreshed_at) => Response::Sessions {
+                sessions: model.sessions.clone(),
+                refreshed_at,
+            },
+        }
+    }
+}
+
+/// Answers one client connection, returning true on shutdown
+///
+/// A refresh is answered from a thread of its own, so that waiting for the
+/// poll does not hold up other clients.
+fn serve(daemon: &Arc<Daemon>, stream: &UnixStream) -> bool {
+    let _ = stream.set_read_timeout(Some(CLIENT_TIMEOUT));
+    let mut line = String::new();
+    if BufReader::new(stream).read_line(&mut line).is_err() {
+        return false;
+    }
+
+    let (response, shutdown) = match serde_json::from_str::<Envelope<Request>>(&line) {
+        Ok(request) if request.version != PROTOCOL_VERSION => (
+            Response::Error {
+                message: format!(
+                    "unsupported protocol version {} (daemon speaks {PROTOCOL_VERSION})",
+                    request.version
+                ),
+            },
+            false,
+        ),
+        Ok(request) if request.body == Request::Refresh => {
+            if let Ok(stream) = stream.try_clone() {
+                let daemon = Arc::clone(daemon);
+                std::thread::spawn(move || respond(&stream, daemon.refresh()));
+            }
+            return false;
+        }
+        Ok(request) => {
+            let shutdown = request.body == Request::Shutdown;
+            (daemon.handle(request.body), shutdown)
+        }
+        Err(e) => (
+            Response::Error {
+                message: format!("invalid request: {e}"),
+            },
+            false,
+        ),
+    };
+
+    respond(stream, response);
+    shutdown
+}
+
+fn respond(stream: &UnixStream, response: Response) {
+    let _ = write_message(
+        stream,
+        &Envelope {
+            version: PROTOCOL_VERSION,
+            body: response,
+        },
+    );
+}
+
+/// Binds the socket, replacing a stale socket file left by a dead daemon
+fn bind(path: &Path) -> Result<UnixListener, DaemonError> {
+    if let Some(dir) = path.parent() {
+        // Only the owner may talk to the daemon
+        config::create_private_dir(dir)
+            .map_err(|e| DaemonError::SocketDir(dir.to_path_buf(), e))?;
+    }
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(DaemonError::AlreadyRunning(path.to_path_buf()));
+        }
+        std::fs::remove_file(path)?;
+    }
+    Ok(UnixListener::bind(path)?)
+}
+
+fn write_message<T: Serialize>(mut stream: &UnixStream, body: &T) -> io::Result<()> {
+    let mut line = serde_json::to_vec(body)?;
+    line.push(b'\n');
+    stream.write_all(&line)?;
+    stream.flush()
+}
+
+/// A connection to a running daemon
+pub struct Client {
+    path: PathBuf,
+}
+
+impl Client {
+    /// Returns a client if a daemon answers on the default socket
+    #[must_use]
+    pub fn connect() -> Option<Self> {
+        Self::connect_to(&socket_path())
+    }
+
+    /// Returns a client if a daemon answers on the given socket
+    #[must_use]
+    pub fn connect_to(path: &Path) -> Option<Self> {
+        let client = Self {
+            path: path.to_path_buf(),
+        };
+        matches!(client.request(&Request::Ping), Ok(Response::Pong { .. })).then_some(client)
+    }
+
+    /// Sends a request and waits for the response
+    pub fn request(&self, request: &Request) -> Result<Response, DaemonError> {
+        let stream = UnixStream::connect(&self.path)?;
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        write_message(
+            &stream,
+            &Envelope {
+                version: PROTOCOL_VERSION,
+                body: request,
+            },
+        )?;
+
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        let response: Envelope<Response> = serde_json::from_str(&line)?;
+        if response.version != PROTOCOL_VERSION {
+            return Err(DaemonError::VersionMismatch(response.version));
+        }
+        match response.body {
+            Response::Error { message } => Err(DaemonError::Remote(message)),
+            response => Ok(response),
+        }
+    }
+
+    /// Fetches the cached session list, failing if the daemon has not read
+    /// tmux yet
+    pub fn list_sessions(&self) -> Result<Vec<Session>, DaemonError> {
+        match self.request(&Request::ListSessions)? {
+            Response::Sessions {
+                refreshed_at: None, ..
+            } => Err(DaemonError::NotReady),
+            Response::Sessions { sessions, .. } => Ok(sessions),
+            _ => Err(DaemonError::UnexpectedResponse),
+        }
+    }
+}
+
+/// Lists sessions through the daemon when it is reachable and has read
+/// tmux, falling back to querying tmux directly
+pub fn list_sessions() -> Result<Vec<Session>, TmuxError> {
+    Client::connect()
+        .and_then(|client| client.list_sessions().ok())
+        .map_or_else(tmux::list_sessions, Ok)
+}
+
+/// Asks a running daemon to re-read tmux, e.g. after creating a session
+pub fn notify_changed() {
+    if let Some(client) = Client::connect() {
+        let _ = client.request(&Request::Refresh);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::SessionStatus;
+
+    fn test_socket(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("ams-test-{}-{name}", std::process::id()))
+            .join("daemon.sock")
+    }
+
+    #[test]
+    fn test_cleanup_runs_once_per_interval() {
+        let config =
+            Config::parse("[cleanup]\nkill_idle_after = \"1h\"\ninterval = \"1h\"").unwrap();
+        let daemon = Daemon::new(config).unwrap();
+        let sessions = [Session {
+            name: "stale".to_string(),
+            last_activity: Utc::now() - chrono::Duration::hours(2),
+            created_by: Some("ams new".to_string()),
+            ..Session::default()
+        }];
+
+        let actions = daemon.cleanup_actions(&sessions, &HashMap::new());
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].session, "stale");
+        assert!(daemon
+            .cleanup_actions(&sessions, &HashMap::new())
+            .is_empty());
+
+        let idle = Daemon::new(Config::default()).unwrap();
+        assert!(idle.cleanup_actions(&sessions, &HashMap::new()).is_empty());
+    }
+
+    fn test_daemon() -> Daemon {
+        let daemon = Daemon::new(Config::default()).unwrap();
+        {
+            let mut model = daemon.model();
+            model.sessions = vec![Session {
+                name: "agent".to_string(),
+                status: SessionStatus::Idle,
+                working_directory: PathBuf::from("/repo"),
+                last_activity: Utc::now(),
+                created_at: Utc::now(),
+                window_count: 1,
+                ..Session::default()
+            }];
+            model.refreshed_at = Some(Utc::now());
+            model
+                .states
+                .insert("agent".to_string(), AgentState::Working);
+            for (session, state) in [
+                ("agent", AgentState::WaitingForInput),
+                ("other", AgentState::Finished),
+                ("agent", AgentState::NeedsApproval),
+            ] {
+                model.history.push_back(HistoryEntry {
+                    time: Utc::now(),
+                    session: session.to_string(),
+                    trigger: Trigger::Waiting,
+                    previous: AgentState::Working,
+                    state,
+                });
+            }
+        }
+        daemon
+    }
+
+    #[test]
+    fn test_request_wire_format() {
+        let envelope = Envelope {
+            version: PROTOCOL_VERSION,
+            body: Request::History {
+                session: Some("agent".to_string()),
+                limit: None,
+            },
+        };
+        let json = serde_json::to_string(&envelope).unwrap();
+        assert_eq!(
+            json,
+            r#"{"version":1,"type":"history","session":"agent","limit":null}"#
+        );
+        let parsed: Envelope<Request> =
+            serde_json::from_str(r#"{"version":1,"type":"history"}"#).unwrap();
+        assert_eq!(
+            parsed.body,
+            Request::History {
+                session: None,
+                limit: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_handle_ping_and_sessions() {
+        let daemon = test_daemon();
+        assert!(matches!(
+            daemon.handle(Request::Ping),
+            Response::Pong { .. }
+        ));
+        match daemon.handle(Request::ListSessions) {
+            Response::Sessions { sessions, .. } => assert_eq!(sessions[0].name, "agent"),
+            other => panic!("unexpected {other:?}"),
+        }
+        match daemon.handle(Request::States) {
+            Response::States { states } => assert_eq!(states["agent"], AgentState::Working),
+            other => panic!("unexpected {other:?}"),
+        }
+        assert!(matches!(daemon.handle(Request::Shutdown), Response::Ok));
+    }
+
+    #[test]
+    fn test_handle_sessions_reports_poll_error() {
+        let daemon = test_daemon();
+        daemon.model().last_error = Some("tmux exploded".to_string());
+        match daemon.handle(Request::ListSessions) {
+            Response::Error { message } => assert_eq!(message, "tmux exploded"),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_handle_history_filters() {
+        let daemon = test_daemon();
+        let entries = |session: Option<&str>, limit| match daemon.handle(Request::History {
+            session: session.map(String::from),
+            limit,
+        }) {
+            Response::History { entries } => entries,
+            other => panic!("unexpected {other:?}"),
+        };
+
+        assert_eq!(entries(None, None).len(), 3);
+        assert_eq!(entries(Some("agent"), None).len(), 2);
+        let latest = entries(Some("agent"), Some(1));
+        assert_eq!(latest.len(), 1);
+        assert_eq!(latest[0].state, AgentState::NeedsApproval);
+    }
+
+    #[test]
+    fn test_refresh_waits_for_the_next_poll() {
+        let (wake, woken) = mpsc::sync_channel(1);
+        let daemon = Arc::new(Daemon {
+            wake: Some(wake),
+            ..test_daemon()
+        });
+        // A poll under way when the refresh arrives does not count
+        daemon.model().polls_started += 1;
+
+        let refresh = {
+            let daemon = Arc::clone(&daemon);
+            std::thread::spawn(move || daemon.handle(Request::Refresh))
+        };
+        woken.recv_timeout(Duration::from_secs(10)).unwrap();
+        daemon.finish_poll();
+
+        // The poller's next poll, begun after the request
+        daemon.model().polls_started += 1;
+        daemon.model().sessions[0].name = "fresh".to_string();
+        daemon.finish_poll();
+
+        match refresh.join().unwrap() {
+            Response::Sessions { sessions, .. } => assert_eq!(sessions[0].name, "fresh"),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_socket_round_trip() {
+        let path = test_socket("roundtrip");
+        let listener = bind(&path).unwrap();
+        let daemon = Arc::new(test_daemon());
+
+        let server = {
+            let daemon = Arc::clone(&daemon);
+            std::thread::spawn(move || {
+                // Ping from connect_to, two lists, then a bad version
+                for stream in listener.incoming().take(4) {
+                    serve(&daemon, &stream.unwrap());
+                }
+            })
+        };
+
+        let client = Client::connect_to(&path).unwrap();
+        let sessions = client.list_sessions().unwrap();
+        assert_eq!(sessions[0].name, "agent");
+
+        // Before the first poll there is nothing to serve yet
+        daemon.model().refreshed_at = None;
+        assert!(matches!(client.list_sessions(), Err(DaemonError::NotReady)));
+
+        let stream = UnixStream::connect(&path).unwrap();
+        write_message(&stream, &serde_json::json!({"version": 99, "type": "ping"})).unwrap();
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line).unwrap();
+        assert!(line.contains("unsupported protocol version 99"));
+
+        server.join().unwrap();
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_serve_rejects_invalid_json() {
+        let path = test_socket("invalid");
+        let listener = bind(&path).unwrap();
+        let daemon = Arc::new(test_daemon());
+
+        let mut stream = UnixStream::connect(&path).unwrap();
+        stream.write_all(b"not json\n").unwrap();
+        let (conn, _) = listener.accept().unwrap();
+        assert!(!serve(&daemon, &conn));
+
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line).unwrap();
+        assert!(line.contains("invalid request"));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_bind_refuses_running_daemon_and_replaces_stale_socket() {
+        let path = test_socket("bind");
+        let listener = bind(&path).unwrap();
+        assert!(matches!(bind(&path), Err(DaemonError::AlreadyRunning(_))));
+
+        drop(listener);
+        let listener = bind(&path);
+        assert!(listener.is_ok());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_connect_without_daemon() {
+        assert!(Client::connect_to(&test_socket("missing")).is_none());
+    }
+
+    #[test]
+    fn test_socket_file_per_tmux_server() {
+        assert_eq!(socket_file(None, &Socket::Default), "daemon.sock");
+        assert_eq!(
+            socket_file(None, &Socket::Name("agents".to_string())),
+            "daemon-agents.sock"
+        );
+        let path = socket_file(None, &Socket::Path(PathBuf::from("/tmp/agents.sock")));
+        assert_eq!(path, "daemon-c190fda693524a24.sock");
+        assert_ne!(
+            path,
+            socket_file(None, &Socket::Path(PathBuf::from("/tmp/other.sock")))
+        );
+    }
+
+    #[test]
+    fn test_fnv1a() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn test_socket_file_per_host() {
+        assert_eq!(
+            socket_file(Some("devbox"), &Socket::Default),
+            "daemon@devbox.sock"
+        );
+        assert_eq!(
+            socket_file(Some("devbox"), &Socket::Name("agents".to_string())),
+            "daemon-agents@devbox.sock"
+        );
+    }
+
+    #[test]
+    fn test_daemon_error_display() {
+        assert_eq!(
+            DaemonError::VersionMismatch(2).to_string(),
+            "Daemon protocol version 2 is not supported (expected 1)"
+        );
+        assert_eq!(
+            DaemonError::Remote("boom".to_string()).to_string(),
+            "Daemon error: boom"
+        );
+    }
+}
@@ -0,0 +1,447 @@
+//! Environment diagnostics for `ams doctor`.
+
+use crate::config::{config_path, create_private_dir, runtime_dir, Config, ConfigError};
+use crate::tmux::{
+    parse_sessions, tmux_command, tmux_command_on, Feature, Socket, TmuxCommand, TmuxVersion,
+    LIST_SESSIONS, SESSION_FORMAT,
+};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+/// Outcome of a single check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Everything is in order
+    Pass,
+    /// Works, but something may need attention
+    Warn,
+    /// Broken; ams will not work correctly
+    Fail,
+}
+
+/// Result of one diagnostic check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    /// Short name of what was checked
+    pub name: String,
+    /// Outcome of the check
+    pub status: Status,
+    /// What was found
+    pub summary: String,
+    /// How to fix a warning or failure
+    pub remediation: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &str, summary: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status: Status::Pass,
+            summary: summary.into(),
+            remediation: None,
+        }
+    }
+
+    fn warn(name: &str, summary: impl Into<String>, remediation: impl Into<String>) -> Self {
+        Self {
+            status: Status::Warn,
+            remediation: Some(remediation.into()),
+            ..Self::pass(name, summary)
+        }
+    }
+
+    fn fail(name: &str, summary: impl Into<String>, remediation: impl Into<String>) -> Self {
+        Self {
+            status: Status::Fail,
+            ..Self::warn(name, summary, remediation)
+        }
+    }
+}
+
+/// Runs every check, in the order they should be reported
+#[must_use]
+pub fn run_checks() -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let version = tmux_command().arg("-V").output();
+    checks.push(match &version {
+        Ok(output) if output.status.success() => {
+            check_version(&String::from_utf8_lossy(&output.stdout))
+        }
+        Ok(output) => Check::fail(
+            "tmux",
+            format!(
+                "`tmux -V` failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            "Reinstall tmux",
+        ),
+        Err(_) => Check::fail(
+            "tmux",
+            "tmux not found on PATH",
+            "Install tmux 3.0 or newer, e.g. `apt install tmux` or `brew install tmux`",
+        ),
+    });
+
+    if let Ok(output) = &version {
+        if let Some(version) = TmuxVersion::parse(&String::from_utf8_lossy(&output.stdout)) {
+            if version >= TmuxVersion::MINIMUM {
+                checks.push(check_features(version));
+            }
+        }
+        checks.push(check_server());
+        checks.push(check_formats());
+    }
+
+    let config = Config::load();
+    checks.push(check_config(&config, config_path().as_deref()));
+    if let Ok(config) = &config {
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        checks.extend(check_agents(config, &path));
+    }
+
+    checks.push(check_state_dir(&runtime_dir()));
+    checks
+}
+
+fn check_version(output: &str) -> Check {
+    let reported = output.trim();
+    match TmuxVersion::parse(reported) {
+        Some(version) if version >= TmuxVersion::MINIMUM => Check::pass("tmux", reported),
+        Some(_) => Check::fail(
+            "tmux",
+            format!(
+                "{reported} is older than the supported minimum {}",
+                TmuxVersion::MINIMUM
+            ),
+            format!("Upgrade tmux to {} or newer", TmuxVersion::MINIMUM),
+        ),
+        None => Check::warn(
+            "tmux",
+            format!("could not parse version from `{reported}`"),
+            "Make sure `tmux -V` reports a 3.0 or newer release",
+        ),
+    }
+}
+
+/// Lists the optional features the installed tmux lacks
+fn check_features(version: TmuxVersion) -> Check {
+    let missing: Vec<Feature> = Feature::ALL
+        .into_iter()
+        .filter(|feature| !version.supports(*feature))
+        .collect();
+
+    let Some(newest) = missing
+        .iter()
+        .map(|feature| feature.required_version())
+        .max()
+    else {
+        return Check::pass("features", "all optional features supported");
+    };
+
+    Check::warn(
+        "features",
+        missing
+            .iter()
+            .map(|feature| format!("{feature} needs {}", feature.required_version()))
+            .collect::<Vec<_>>()
+            .join(", "),
+        format!("Upgrade tmux to {newest} or newer for full functionality"),
+    )
+}
+
+/// Checks that the default tmux server socket can be reached
+fn check_server() -> Check {
+    let output = tmux_command()
+        .args(["list-sessions", "-F", "#{socket_path}"])
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let socket = stdout.lines().next().unwrap_or_default();
+            Check::pass("server", format!("listening on {socket}"))
+        }
+        Ok(output) => server_failure(&String::from_utf8_lossy(&output.stderr)),
+        Err(e) => Check::fail("server", e.to_string(), "Check that tmux can be executed"),
+    }
+}
+
+fn server_failure(stderr: &str) -> Check {
+    let stderr = stderr.trim();
+    if stderr.contains("no server running")
+        || stderr.contains("No such file or directory")
+        || stderr.contains("no sessions")
+    {
+        Check::warn(
+            "server",
+            "no tmux server running",
+            "It starts with the first session, e.g. `ams new <name>`",
+        )
+    } else {
+        Check::fail(
+            "server",
+            stderr.to_string(),
+            "Check the permissions of the tmux socket directory (TMUX_TMPDIR or /tmp/tmux-$UID)",
+        )
+    }
+}
+
+/// Checks the session format strings against a throwaway server so the
+/// result does not depend on the user's sessions
+fn check_formats() -> Check {
+    let socket = format!("ams-doctor-{}", std::process::id());
+    let tmux = |args: &[&str]| throwaway_server(&socket, args).output();
+
+    let result = tmux(&["new-session", "-d", "-s", "doctor"])
+        .and_then(|_| tmux(&[LIST_SESSIONS.as_slice(), &[SESSION_FORMAT]].concat()));
+    let _ = tmux(&["kill-server"]);
+
+    match result {
+        Ok(output) if output.status.success() => {
+            check_format_output(&String::from_utf8_lossy(&output.stdout))
+        }
+        Ok(output) => Check::fail(
+            "formats",
+            format!(
+                "listing sessions failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            "Upgrade tmux to 3.0 or newer",
+        ),
+        Err(e) => Check::fail("formats", e.to_string(), "Check that tmux can be executed"),
+    }
+}
+
+/// Builds a command for the throwaway server `socket`, which never inherits
+/// the server selected with `--socket-path` or `--socket-name`
+fn throwaway_server(socket: &str, args: &[&str]) -> TmuxCommand {
+    let mut command = tmux_command_on(&Socket::Name(socket.to_string()));
+    command.args(["-f", "/dev/null"]).args(args);
+    command
+}
+
+fn check_format_output(output: &str) -> Check {
+    match parse_sessions(output) {
+        Ok(sessions) if sessions.len() == 1 => {
+            Check::pass("formats", "session format variables supported")
+        }
+        Ok(sessions) => Check::fail(
+            "formats",
+            format!("expected 1 test session, tmux listed {}", sessions.len()),
+            "The probe reached a server other than its own throwaway one; \
+             report this as a bug",
+        ),
+        Err(e) => Check::fail(
+            "formats",
+            e.to_string(),
+            "Upgrade tmux to 3.0 or newer; this release lacks format variables ams needs",
+        ),
+    }
+}
+
+fn check_config(config: &Result<Config, ConfigError>, path: Option<&Path>) -> Check {
+    match (config, path) {
+        (Err(e), _) => Check::fail(
+            "config",
+            e.to_string(),
+            "Fix the file, or move it aside to fall back to the defaults",
+        ),
+        (Ok(_), Some(path)) if path.exists() => {
+            Check::pass("config", format!("loaded {}", path.display()))
+        }
+        (Ok(_), _) => Check::pass("config", "no config file, using defaults"),
+    }
+}
+
+/// Checks that every configured agent command can be found on `path`
+fn check_agents(config: &Config, path: &OsStr) -> Vec<Check> {
+    if config.agents.is_empty() {
+        return vec![Check::pass("agents", "no agents configured")];
+    }
+
+    config
+        .agents
+        .iter()
+        .map(|(name, profile)| {
+            let label = format!("agent {name}");
+            find_executable(&profile.command, path).map_or_else(
+                || {
+                    Check::fail(
+                        &label,
+                        format!("`{}` not found on PATH", profile.command),
+                        format!("Install it, or fix `agents.{name}.command` in the config file"),
+                    )
+                },
+                |found| Check::pass(&label, found.display().to_string()),
+            )
+        })
+        .collect()
+}
+
+/// Resolves a command the way the shell would, using the given `PATH`
+fn find_executable(command: &str, path: &OsStr) -> Option<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let is_executable = |candidate: &Path| {
+        candidate
+            .metadata()
+            .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+    };
+
+    if command.is_empty() {
+        return None;
+    }
+    if command.contains('/') {
+        let candidate = PathBuf::from(command);
+        return is_executable(&candidate).then_some(candidate);
+    }
+    std::env::split_paths(path)
+        .map(|dir| dir.join(command))
+        .find(|candidate| is_executable(candidate))
+}
+
+/// Checks that ams can create files in its runtime directory
+fn check_state_dir(dir: &Path) -> Check {
+    let probe = dir.join(format!(".doctor-{}", std::process::id()));
+    let result = create_private_dir(dir)
+        .and_then(|()| std::fs::write(&probe, b""))
+        .and_then(|()| std::fs::remove_file(&probe));
+
+    match result {
+        Ok(()) => Check::pass("state dir", format!("{} is writable", dir.display())),
+        Err(e) => Check::fail(
+            "state dir",
+            format!("{} is not writable: {e}", dir.display()),
+            "Make it a directory owned by you with mode 0700, or point XDG_RUNTIME_DIR elsewhere",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AgentProfile;
+
+    #[test]
+    fn test_check_features() {
+        assert_eq!(check_features(TmuxVersion::new(3, 3)).status, Status::Pass);
+        let old = check_features(TmuxVersion::new(3, 0));
+        assert_eq!(old.status, Status::Warn);
+        assert_eq!(
+            old.summary,
+            "list filters (-f) needs 3.1, new-session -e needs 3.2, \
+             split-window -l <percent>% needs 3.1"
+        );
+        assert_eq!(
+            old.remediation.as_deref(),
+            Some("Upgrade tmux to 3.2 or newer for full functionality")
+        );
+    }
+
+    #[test]
+    fn test_check_version() {
+        assert_eq!(check_version("tmux 3.4\n").status, Status::Pass);
+        let old = check_version("tmux 2.9a");
+        assert_eq!(old.status, Status::Fail);
+        assert_eq!(
+            old.summary,
+            "tmux 2.9a is older than the supported minimum 3.0"
+        );
+        assert_eq!(check_version("tmux master").status, Status::Warn);
+    }
+
+    #[test]
+    fn test_server_failure() {
+        let missing =
+            server_failure("error connecting to /tmp/tmux-0/default (No such file or directory)\n");
+        assert_eq!(missing.status, Status::Warn);
+        let denied =
+            server_failure("error connecting to /tmp/tmux-0/default (Permission denied)\n");
+        assert_eq!(denied.status, Status::Fail);
+        assert!(denied.remediation.unwrap().contains("permissions"));
+    }
+
+    #[test]
+    fn test_check_format_output() {
+        let good = "0\t1704067200\t1\t\t\t\t\t\t\tdoctor\t0\t1\t0\t1\t42\t80\t24\t1704067200\tbash\t/tmp\n";
+        assert_eq!(check_format_output(good).status, Status::Pass);
+        // Unknown format variables expand to nothing on old releases.
+        let bad = "0\t1704067200\t1\t\t\t\t\t\t\tdoctor\t0\t1\t0\t1\t42\t80\t24\t\tbash\t/tmp\n";
+        assert_eq!(check_format_output(bad).status, Status::Fail);
+
+        let two = check_format_output(&format!("{good}{}", good.replace("doctor", "keep")));
+        assert_eq!(two.status, Status::Fail);
+        assert!(!two.remediation.unwrap().contains("Upgrade"));
+    }
+
+    #[test]
+    fn test_throwaway_server_ignores_selected_socket() {
+        crate::tmux::set_socket(Socket::Path(PathBuf::from("/tmp/ams-user.sock")));
+        let command = throwaway_server("ams-doctor-1", &["kill-server"]).command();
+        crate::tmux::set_socket(Socket::Default);
+
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(
+            args,
+            ["-u", "-L", "ams-doctor-1", "-f", "/dev/null", "kill-server"]
+        );
+    }
+
+    #[test]
+    fn test_check_config() {
+        let missing = Path::new("/nonexistent/ams/config.toml");
+        let ok = check_config(&Ok(Config::default()), Some(missing));
+        assert_eq!(ok.summary, "no config file, using defaults");
+
+        let err = Config::load_from(missing);
+        assert_eq!(check_config(&err, Some(missing)).status, Status::Fail);
+    }
+
+    #[test]
+    fn test_check_agents() {
+        let mut config = Config::default();
+        assert_eq!(check_agents(&config, OsStr::new("/bin")).len(), 1);
+
+        for (name, command) in [("shell", "sh"), ("missing", "ams-no-such-agent")] {
+            config.agents.insert(
+                name.to_string(),
+                AgentProfile {
+                    command: command.to_string(),
+                    ..AgentProfile::default()
+                },
+            );
+        }
+        let path = std::env::var_os("PATH").unwrap();
+        let checks = check_agents(&config, &path);
+        assert_eq!(checks[0].name, "agent missing");
+        assert_eq!(checks[0].status, Status::Fail);
+        assert_eq!(checks[1].name, "agent shell");
+        assert_eq!(checks[1].status, Status::Pass);
+    }
+
+    #[test]
+    fn test_find_executable() {
+        let path = std::env::var_os("PATH").unwrap();
+        assert!(find_executable("sh", &path).is_some());
+        assert!(find_executable("sh", OsStr::new("")).is_none());
+        assert!(find_executable("", &path).is_none());
+        assert!(find_executable("/bin/sh", OsStr::new("")).is_some());
+
+        let plain = std::env::temp_dir().join(format!("ams-doctor-plain-{}", std::process::id()));
+        std::fs::write(&plain, "").unwrap();
+        let found = find_executable(plain.to_str().unwrap(), &path);
+        std::fs::remove_file(&plain).unwrap();
+        assert!(found.is_none());
+    }
+
+    #[test]
+    fn test_check_state_dir() {
+        let dir = std::env::temp_dir().join(format!("ams-doctor-{}", std::process::id()));
+        assert_eq!(check_state_dir(&dir).status, Status::Pass);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let blocked = Path::new("/proc/ams-doctor");
+        assert_eq!(check_state_dir(blocked).status, Status::Fail);
+    }
+}
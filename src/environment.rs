@@ -0,0 +1,268 @@
+//! Environment variables for new sessions: literal values, secrets read from
+//! a command at launch time, `KEY=VALUE` assignments and env files.
+//!
+//! Resolved values are only handed to `tmux new-session -e` through tmux's
+//! standard input, never its command line; they are never stored or
+//! included in error messages.
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use thiserror::Error;
+
+/// Errors that can occur while building a session environment.
+#[derive(Error, Debug)]
+pub enum EnvError {
+    /// Failed to read an env file.
+    #[error("Failed to read env file {0}: {1}")]
+    Io(PathBuf, #[source] std::io::Error),
+
+    /// A line of an env file is not a `KEY=VALUE` assignment.
+    #[error("Invalid line {1} in env file {0}: expected KEY=VALUE")]
+    InvalidLine(PathBuf, usize),
+
+    /// A variable name that the shell would not accept.
+    #[error("Invalid environment variable name: {0:?}")]
+    InvalidName(String),
+
+    /// `--env KEY` names a variable that is not set.
+    #[error("Environment variable {0} is not set")]
+    Unset(String),
+
+    /// The command providing a secret could not run or failed.
+    #[error("Secret command for {key} failed: {reason}")]
+    SecretFailed {
+        /// Variable the secret was for
+        key: String,
+        /// Exit status or launch error, never the command's output
+        reason: String,
+    },
+}
+
+/// Value of an environment variable in the configuration
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum EnvValue {
+    /// A literal value
+    Value(String),
+    /// A secret printed by a local command when the session is created,
+    /// e.g. `{ command = "pass show openai/api-key" }`
+    Secret {
+        /// Shell command whose output, minus trailing newlines, is the value
+        command: String,
+    },
+}
+
+impl EnvValue {
+    /// Returns the value, running the secret command if there is one
+    pub fn resolve(&self, key: &str) -> Result<String, EnvError> {
+        match self {
+            Self::Value(value) => Ok(value.clone()),
+            Self::Secret { command } => run_secret_command(key, command),
+        }
+    }
+
+    /// Returns the secret command, if the value comes from one
+    #[must_use]
+    pub fn command(&self) -> Option<&str> {
+        match self {
+            Self::Value(_) => None,
+            Self::Secret { command } => Some(command),
+        }
+    }
+}
+
+impl std::fmt::Debug for EnvValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Value(_) => write!(f, "Value(..)"),
+            Self::Secret { command } => f.debug_struct("Secret").field("command", command).finish(),
+        }
+    }
+}
+
+impl From<&str> for EnvValue {
+    fn from(value: &str) -> Self {
+        Self::Value(value.to_string())
+    }
+}
+
+/// Resolves every variable, running secret commands in key order
+pub fn resolve(vars: &BTreeMap<String, EnvValue>) -> Result<BTreeMap<String, String>, EnvError> {
+    vars.iter()
+        .map(|(key, value)| Ok((key.clone(), value.resolve(key)?)))
+        .collect()
+}
+
+/// Runs a secret command with the terminal available for prompts such as
+/// a GPG passphrase, capturing only its standard output
+fn run_secret_command(key: &str, command: &str) -> Result<String, EnvError> {
+    let failed = |reason: String| EnvError::SecretFailed {
+        key: key.to_string(),
+        reason,
+    };
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| failed(e.to_string()))?;
+    if !output.status.success() {
+        return Err(failed(output.status.to_string()));
+    }
+    let value = String::from_utf8(output.stdout)
+        .map_err(|_| failed("output is not valid UTF-8".to_string()))?;
+    Ok(value.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Parses a `--env` argument: `KEY=VALUE`, or `KEY` to pass on the value
+/// from the current environment
+pub fn parse_assignment(assignment: &str) -> Result<(String, String), EnvError> {
+    let Some((key, value)) = assignment.split_once('=') else {
+        check_name(assignment)?;
+        let value =
+            std::env::var(assignment).map_err(|_| EnvError::Unset(assignment.to_string()))?;
+        return Ok((assignment.to_string(), value));
+    };
+    check_name(key)?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Reads an env file: `KEY=VALUE` lines, optionally prefixed with
+/// `export` and with the value in single or double quotes; blank lines and
+/// `#` comments are ignored
+pub fn read_env_file(path: &Path) -> Result<BTreeMap<String, String>, EnvError> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|e| EnvError::Io(path.to_path_buf(), e))?;
+    parse_env_file(&contents).map_err(|line| EnvError::InvalidLine(path.to_path_buf(), line))
+}
+
+/// Parses env file contents, returning the number of the first bad line
+fn parse_env_file(contents: &str) -> Result<BTreeMap<String, String>, usize> {
+    let mut vars = BTreeMap::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+        let (key, value) = line
+            .split_once('=')
+            .filter(|(key, _)| check_name(key.trim_end()).is_ok())
+            .ok_or(index + 1)?;
+        let value = value.trim();
+        let value = ['"', '\'']
+            .iter()
+            .find_map(|&quote| {
+                value
+                    .strip_prefix(quote)
+                    .and_then(|value| value.strip_suffix(quote))
+            })
+            .unwrap_or(value);
+        vars.insert(key.trim_end().to_string(), value.to_string());
+    }
+    Ok(vars)
+}
+
+/// Checks that a variable name is letters, digits and underscores, not
+/// starting with a digit
+fn check_name(name: &str) -> Result<(), EnvError> {
+    let valid = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(EnvError::InvalidName(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_env_values() {
+        let vars: BTreeMap<String, EnvValue> = toml::from_str(
+            r#"
+            PLAIN = "value"
+            TOKEN = { command = "printf 's3cret\n'" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(vars["PLAIN"], EnvValue::from("value"));
+        assert_eq!(vars["TOKEN"].command(), Some("printf 's3cret\n'"));
+
+        let resolved = resolve(&vars).unwrap();
+        assert_eq!(resolved["PLAIN"], "value");
+        assert_eq!(resolved["TOKEN"], "s3cret");
+    }
+
+    #[test]
+    fn test_secret_failure_hides_output() {
+        let secret = EnvValue::Secret {
+            command: "echo leaked; exit 3".to_string(),
+        };
+        let message = secret.resolve("TOKEN").unwrap_err().to_string();
+        assert!(message.starts_with("Secret command for TOKEN failed: exit status: 3"));
+        assert!(!message.contains("leaked"));
+    }
+
+    #[test]
+    fn test_debug_hides_values() {
+        let debug = format!("{:?}", EnvValue::from("hunter2"));
+        assert!(!debug.contains("hunter2"));
+    }
+
+    #[test]
+    fn test_parse_assignment() {
+        assert_eq!(
+            parse_assignment("KEY=a=b").unwrap(),
+            ("KEY".to_string(), "a=b".to_string())
+        );
+        assert_eq!(
+            parse_assignment("EMPTY=").unwrap(),
+            ("EMPTY".to_string(), String::new())
+        );
+        assert_eq!(
+            parse_assignment("PATH").unwrap(),
+            ("PATH".to_string(), std::env::var("PATH").unwrap())
+        );
+        assert!(matches!(
+            parse_assignment("AMS_TEST_SURELY_UNSET"),
+            Err(EnvError::Unset(_))
+        ));
+        assert!(matches!(
+            parse_assignment("1KEY=x"),
+            Err(EnvError::InvalidName(_))
+        ));
+        assert!(matches!(
+            parse_assignment("=x"),
+            Err(EnvError::InvalidName(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_env_file() {
+        let vars = parse_env_file(
+            "# comment\n\nexport API_URL=https://example.com/?a=b\nNAME = \"two words\"\nQUOTED='x'\n",
+        )
+        .unwrap();
+        assert_eq!(vars["API_URL"], "https://example.com/?a=b");
+        assert_eq!(vars["NAME"], "two words");
+        assert_eq!(vars["QUOTED"], "x");
+
+        assert_eq!(parse_env_file("A=1\nnot an assignment\n"), Err(2));
+        assert_eq!(parse_env_file("BAD-NAME=1"), Err(1));
+    }
+
+    #[test]
+    fn test_read_env_file_missing() {
+        let err = read_env_file(Path::new("/nonexistent/.env")).unwrap_err();
+        assert!(matches!(err, EnvError::Io(_, _)));
+    }
+}
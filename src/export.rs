@@ -0,0 +1,315 @@
+//! Session transcript export to Markdown, HTML and plain text.
+
+use crate::ansi::{self, AnsiStyle};
+use crate::git;
+use crate::tmux::{capture_pane_with_escapes, display_message, get_session, TmuxError};
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Output format of an exported transcript
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Markdown with the scrollback in a fenced code block, colors stripped
+    Markdown,
+    /// Standalone HTML page with colors preserved as styled spans
+    Html,
+    /// Plain text, colors stripped
+    Text,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "md" | "markdown" => Ok(Self::Markdown),
+            "html" => Ok(Self::Html),
+            "txt" | "text" => Ok(Self::Text),
+            other => Err(format!(
+                "unknown format '{other}' (expected md, html or txt)"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Markdown => write!(f, "md"),
+            Self::Html => write!(f, "html"),
+            Self::Text => write!(f, "txt"),
+        }
+    }
+}
+
+/// Session details written at the top of a transcript
+#[derive(Debug, Clone)]
+pub struct TranscriptMetadata {
+    /// Exported session or pane target
+    pub session: String,
+    /// Command running in the exported pane
+    pub agent: Option<String>,
+    /// Working directory of the exported pane
+    pub directory: PathBuf,
+    /// When the session was created
+    pub started_at: DateTime<Utc>,
+    /// When the transcript was captured
+    pub ended_at: DateTime<Utc>,
+    /// Git branch checked out in the working directory
+    pub git_branch: Option<String>,
+}
+
+/// Captures a session's scrollback and renders it as a transcript
+///
+/// The target may be a session name or a `session:window.pane` target; a
+/// bare session name exports its agent pane.
+pub fn export_session(target: &str, format: ExportFormat) -> Result<String, TmuxError> {
+    let session_name = target.split(':').next().unwrap_or(target);
+    let session = get_session(session_name)?;
+    let pane = if target.contains(':') {
+        target.to_string()
+    } else {
+        session.agent_target()
+    };
+
+    let agent = display_message(&pane, "#{pane_current_command}")
+        .ok()
+        .filter(|command| !command.is_empty());
+    let directory = display_message(&pane, "#{pane_current_path}")
+        .map_or(session.working_directory, PathBuf::from);
+    let git_branch = git::branch(&directory);
+    let raw = capture_pane_with_escapes(&pane)?;
+
+    let metadata = TranscriptMetadata {
+        session: target.to_string(),
+        agent,
+        directory,
+        started_at: session.created_at,
+        ended_at: Utc::now(),
+        git_branch,
+    };
+
+    Ok(render(&metadata, &raw, format))
+}
+
+/// Renders captured scrollback (with escape sequences) as a transcript
+#[must_use]
+pub fn render(metadata: &TranscriptMetadata, raw: &str, format: ExportFormat) -> String {
+    let lines = content_lines(raw);
+    match format {
+        ExportFormat::Markdown => render_markdown(metadata, &lines),
+        ExportFormat::Html => render_html(metadata, &lines),
+        ExportFormat::Text => render_text(metadata, &lines),
+    }
+}
+
+/// Returns the captured lines without the blank padding tmux adds at the end
+fn content_lines(raw: &str) -> Vec<&str> {
+    let mut lines: Vec<&str> = raw.lines().collect();
+    while lines
+        .last()
+        .is_some_and(|line| ansi::strip(line).trim().is_empty())
+    {
+        lines.pop();
+    }
+    lines
+}
+
+fn metadata_fields(metadata: &TranscriptMetadata) -> Vec<(&'static str, String)> {
+    vec![
+        ("Session", metadata.session.clone()),
+        (
+            "Agent",
+            metadata.agent.clone().unwrap_or_else(|| "-".to_string()),
+        ),
+        ("Directory", metadata.directory.display().to_string()),
+        (
+            "Git branch",
+            metadata
+                .git_branch
+                .clone()
+                .unwrap_or_else(|| "-".to_string()),
+        ),
+        (
+            "Started",
+            metadata
+                .started_at
+                .to_rfc3339_opts(SecondsFormat::Secs, false),
+        ),
+        (
+            "Ended",
+            metadata
+                .ended_at
+                .to_rfc3339_opts(SecondsFormat::Secs, false),
+        ),
+    ]
+}
+
+fn render_markdown(metadata: &TranscriptMetadata, lines: &[&str]) -> String {
+    let body = lines
+        .iter()
+        .map(|line| ansi::strip(line))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    // The fence must be longer than any backtick run inside the transcript
+    let longest_run = body.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest_run.max(2) + 1);
+
+    let mut out = format!("# Session transcript: {}\n\n", metadata.session);
+    for (key, value) in metadata_fields(metadata) {
+        let _ = writeln!(out, "- **{key}:** {value}");
+    }
+    let _ = write!(out, "\n{fence}text\n{body}\n{fence}\n");
+    out
+}
+
+fn render_text(metadata: &TranscriptMetadata, lines: &[&str]) -> String {
+    let mut out = String::new();
+    for (key, value) in metadata_fields(metadata) {
+        let _ = writeln!(out, "{key}: {value}");
+    }
+    out.push('\n');
+    for line in lines {
+        out.push_str(&ansi::strip(line));
+        out.push('\n');
+    }
+    out
+}
+
+fn render_html(metadata: &TranscriptMetadata, lines: &[&str]) -> String {
+    let title = escape_html(&metadata.session);
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Session transcript: {title}</title>\n\
+         <style>body {{ font-family: sans-serif; }} \
+         pre {{ background: #1e1e1e; color: #e5e5e5; padding: 1em; }}</style>\n\
+         </head>\n<body>\n<h1>Session transcript: {title}</h1>\n<dl>\n"
+    );
+    for (key, value) in metadata_fields(metadata) {
+        let _ = writeln!(out, "<dt>{key}</dt><dd>{}</dd>", escape_html(&value));
+    }
+    out.push_str("</dl>\n<pre>");
+    for line in lines {
+        for (style, text) in ansi::parse(line) {
+            let text = escape_html(&text);
+            if style.is_plain() {
+                out.push_str(&text);
+            } else {
+                let _ = write!(out, "<span style=\"{}\">{text}</span>", css(&style));
+            }
+        }
+        out.push('\n');
+    }
+    out.push_str("</pre>\n</body>\n</html>\n");
+    out
+}
+
+fn css(style: &AnsiStyle) -> String {
+    let mut rules = Vec::new();
+    if let Some(fg) = style.fg {
+        rules.push(format!("color:{}", fg.to_hex()));
+    }
+    if let Some(bg) = style.bg {
+        rules.push(format!("background-color:{}", bg.to_hex()));
+    }
+    if style.bold {
+        rules.push("font-weight:bold".to_string());
+    }
+    if style.dim {
+        rules.push("opacity:0.6".to_string());
+    }
+    if style.italic {
+        rules.push("font-style:italic".to_string());
+    }
+    if style.underline {
+        rules.push("text-decoration:underline".to_string());
+    }
+    rules.join(";")
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn metadata() -> TranscriptMetadata {
+        TranscriptMetadata {
+            session: "agent<1>".to_string(),
+            agent: Some("claude".to_string()),
+            directory: PathBuf::from("/home/user/project"),
+            started_at: Utc.timestamp_opt(1_704_067_200, 0).unwrap(),
+            ended_at: Utc.timestamp_opt(1_704_070_800, 0).unwrap(),
+            git_branch: None,
+        }
+    }
+
+    #[test]
+    fn test_export_format_from_str() {
+        assert_eq!("md".parse(), Ok(ExportFormat::Markdown));
+        assert_eq!("markdown".parse(), Ok(ExportFormat::Markdown));
+        assert_eq!("html".parse(), Ok(ExportFormat::Html));
+        assert_eq!("txt".parse(), Ok(ExportFormat::Text));
+        assert_eq!("text".parse(), Ok(ExportFormat::Text));
+        assert!("pdf".parse::<ExportFormat>().is_err());
+    }
+
+    #[test]
+    fn test_export_format_display() {
+        assert_eq!(ExportFormat::Markdown.to_string(), "md");
+        assert_eq!(ExportFormat::Html.to_string(), "html");
+        assert_eq!(ExportFormat::Text.to_string(), "txt");
+    }
+
+    #[test]
+    fn test_render_text_strips_colors() {
+        let out = render(
+            &metadata(),
+            "\x1b[31mred\x1b[0m\nplain\n\n\n",
+            ExportFormat::Text,
+        );
+        assert!(out.starts_with("Session: agent<1>\nAgent: claude\n"));
+        assert!(out.contains("Git branch: -\n"));
+        assert!(out.contains("Started: 2024-01-01T00:00:00+00:00\n"));
+        assert!(out.ends_with("\nred\nplain\n"));
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let out = render(&metadata(), "\x1b[1mbold\x1b[0m", ExportFormat::Markdown);
+        assert!(out.starts_with("# Session transcript: agent<1>\n\n"));
+        assert!(out.contains("- **Directory:** /home/user/project\n"));
+        assert!(out.ends_with("```text\nbold\n```\n"));
+    }
+
+    #[test]
+    fn test_render_markdown_fence_longer_than_content() {
+        let out = render(&metadata(), "````rust", ExportFormat::Markdown);
+        assert!(out.ends_with("`````text\n````rust\n`````\n"));
+    }
+
+    #[test]
+    fn test_render_html_spans_and_escaping() {
+        let out = render(
+            &metadata(),
+            "\x1b[1;31;44m<err>\x1b[0m & \x1b[2;3;4mok",
+            ExportFormat::Html,
+        );
+        assert!(out.contains("<title>Session transcript: agent&lt;1&gt;</title>"));
+        assert!(out.contains(
+            "<span style=\"color:#cd0000;background-color:#0000ee;font-weight:bold\">&lt;err&gt;</span> &amp; "
+        ));
+        assert!(out.contains(
+            "<span style=\"opacity:0.6;font-style:italic;text-decoration:underline\">ok</span>\n</pre>"
+        ));
+    }
+}
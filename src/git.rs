@@ -0,0 +1,98 @@
+//! Git repository inspection without running git.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Returns the branch checked out in the repository containing `dir`, or
+/// the short commit hash when HEAD is detached
+///
+/// Returns `None` outside a repository or when HEAD cannot be read.
+#[must_use]
+pub fn branch(dir: &Path) -> Option<String> {
+    let head = fs::read_to_string(git_dir(dir)?.join("HEAD")).ok()?;
+    parse_head(&head)
+}
+
+/// Finds the git directory of the repository containing `dir`, following
+/// the `gitdir:` file of worktrees and submodules
+fn git_dir(dir: &Path) -> Option<PathBuf> {
+    for ancestor in dir.ancestors() {
+        let dot_git = ancestor.join(".git");
+        if dot_git.is_dir() {
+            return Some(dot_git);
+        }
+        if let Ok(contents) = fs::read_to_string(&dot_git) {
+            let path = contents.trim().strip_prefix("gitdir:")?.trim();
+            return Some(ancestor.join(path));
+        }
+    }
+    None
+}
+
+/// Extracts the branch name, or a short hash, from the contents of HEAD
+fn parse_head(head: &str) -> Option<String> {
+    let head = head.trim();
+    if let Some(reference) = head.strip_prefix("ref:") {
+        let reference = reference.trim();
+        return Some(
+            reference
+                .strip_prefix("refs/heads/")
+                .unwrap_or(reference)
+                .to_string(),
+        );
+    }
+    let hash = head.get(..7)?;
+    hash.chars()
+        .all(|c| c.is_ascii_hexdigit())
+        .then(|| hash.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ams-git-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_parse_head() {
+        assert_eq!(
+            parse_head("ref: refs/heads/feature/login\n").as_deref(),
+            Some("feature/login")
+        );
+        assert_eq!(
+            parse_head("3f57fc1a9b0c2d4e5f60718293a4b5c6d7e8f901\n").as_deref(),
+            Some("3f57fc1")
+        );
+        assert_eq!(parse_head(""), None);
+        assert_eq!(parse_head("not a head"), None);
+    }
+
+    #[test]
+    fn test_branch_from_subdirectory() {
+        let repo = temp_dir("repo");
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        fs::write(repo.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::create_dir_all(repo.join("src/bin")).unwrap();
+
+        assert_eq!(branch(&repo.join("src/bin")).as_deref(), Some("main"));
+        fs::remove_dir_all(&repo).unwrap();
+    }
+
+    #[test]
+    fn test_branch_of_worktree() {
+        let dir = temp_dir("worktree");
+        let git_dir = dir.join("main/.git/worktrees/fix");
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(git_dir.join("HEAD"), "ref: refs/heads/fix\n").unwrap();
+        fs::create_dir_all(dir.join("fix")).unwrap();
+        fs::write(dir.join("fix/.git"), "gitdir: ../main/.git/worktrees/fix\n").unwrap();
+
+        assert_eq!(branch(&dir.join("fix")).as_deref(), Some("fix"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -13,11 +13,43 @@
 #![allow(clippy::module_name_repetitions)] // Common pattern in Rust
 #![allow(clippy::multiple_crate_versions)] // Cannot control transitive dependencies
 
+pub mod ansi;
+pub mod cleanup;
+pub mod config;
+pub mod control;
+pub mod daemon;
+pub mod doctor;
+pub mod environment;
+pub mod export;
+pub mod git;
+pub mod metadata;
+pub mod notify;
+pub mod process;
+pub mod project;
+pub mod prompt;
+pub mod queue;
+pub mod recording;
+pub mod search;
 pub mod session;
+pub mod shell;
+pub mod template;
+pub mod text;
 pub mod tmux;
 pub mod tui;
+pub mod wait;
+pub mod watch;
 
-pub use session::{Session, SessionStatus};
+pub use config::{Config, ConfigError};
+pub use control::{ControlClient, ControlEvent};
+pub use export::{export_session, ExportFormat, TranscriptMetadata};
+pub use project::{Project, ProjectError};
+pub use recording::{RecordError, Recording};
+pub use search::{search_all, SearchMatch};
+pub use session::{AgentState, PaneTarget, Session, SessionStatus};
+pub use template::{Template, TemplateError};
 pub use tmux::{
-    attach_session, create_session, get_session, kill_session, list_sessions, TmuxError,
+    attach_session, capture_pane, capture_pane_with_escapes, capture_screen, create_session,
+    display_message, get_session, kill_session, list_pane_targets, list_sessions, pipe_pane,
+    send_keys, server_running, Feature, Host, TmuxError, TmuxVersion,
 };
+pub use watch::{SessionSnapshot, StateMatcher, TransitionDetector, WatchEvent};
@@ -1,6 +1,20 @@
-use ams::{list_sessions, SessionStatus};
+use ams::cleanup;
+use ams::daemon::{self, Client, Request, Response};
+use ams::doctor;
+use ams::environment;
+use ams::metadata::{self, Origin};
+use ams::project::{Project, ProjectError};
+use ams::queue::{self, Queue};
+use ams::text;
+use ams::tmux::{self, Aggregate, Host, Socket};
+use ams::wait::{Condition, WaitError};
+use ams::{list_sessions, ExportFormat, SearchMatch, SessionStatus};
 use chrono::Utc;
 use clap::{Parser, Subcommand};
+use regex::RegexBuilder;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 #[derive(Parser)]
 #[command(
@@ -11,6 +25,23 @@ use clap::{Parser, Subcommand};
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+    /// tmux socket name, as with `tmux -L`
+    #[arg(short = 'L', long, global = true, conflicts_with = "socket_path")]
+    socket_name: Option<String>,
+    /// tmux socket path, as with `tmux -S`
+    #[arg(short = 'S', long, global = true)]
+    socket_path: Option<PathBuf>,
+    /// List sessions of several tmux servers together (comma-separated
+    /// socket names or paths, `default` for the default server)
+    #[arg(long, global = true, value_delimiter = ',')]
+    servers: Vec<String>,
+    /// Run tmux on a remote host from `[hosts]` in the config, over SSH
+    #[arg(short = 'H', long, global = true)]
+    host: Option<String>,
+    /// List sessions of several hosts together (comma-separated host
+    /// names, `local` for this machine)
+    #[arg(long, global = true, value_delimiter = ',')]
+    hosts: Vec<String>,
 }
 
 #[derive(Subcommand)]
@@ -31,34 +62,340 @@ enum Commands {
         /// Working directory for the session
         #[arg(short, long)]
         directory: Option<String>,
+        /// Build the session's windows and panes from a template
+        /// (a name under ~/.config/ams/templates or a .toml path)
+        #[arg(short, long)]
+        template: Option<String>,
+        /// Agent profile to launch in the session (default: `default_agent`)
+        #[arg(short, long, conflicts_with = "template")]
+        agent: Option<String>,
+        /// Set an environment variable in the session (`KEY=VALUE`, or
+        /// `KEY` to pass on its current value); repeatable
+        #[arg(short, long = "env", value_name = "KEY[=VALUE]")]
+        env: Vec<String>,
+        /// Read environment variables from a dotenv-style file; repeatable
+        #[arg(long, value_name = "FILE")]
+        env_file: Vec<PathBuf>,
+        /// Paste this prompt into the agent once it is ready
+        #[arg(short, long)]
+        prompt: Option<String>,
+        /// Paste the contents of this file into the agent once it is ready
+        #[arg(long, value_name = "FILE", conflicts_with = "prompt")]
+        prompt_file: Option<PathBuf>,
+        /// How long to wait for the agent before giving up on the prompt
+        #[arg(long, value_parser = cleanup::parse_duration, default_value = "2m")]
+        ready_timeout: Duration,
+    },
+    /// Trust the nearest .ams.toml so `ams new` may run its commands
+    Trust {
+        /// Project config file, or a directory to search upward from
+        /// (default: the current directory)
+        path: Option<PathBuf>,
     },
     /// Kill a tmux session
     Kill {
         /// Name of the session to kill
         name: String,
     },
+    /// Type text into a session, followed by Enter
+    Send {
+        /// Session name or `session:window.pane` target to type into
+        name: String,
+        /// Text to type; several words are joined with spaces
+        #[arg(required = true)]
+        text: Vec<String>,
+        /// Do not press Enter after the text
+        #[arg(long)]
+        no_enter: bool,
+    },
+    /// Wait until a session's agent reaches a state; exits 3 on timeout
+    /// and 4 if the session disappears
+    Wait {
+        /// Name of the session to watch
+        name: String,
+        /// Condition: idle, waiting, exited or pattern:<regex>
+        #[arg(short, long, default_value = "idle")]
+        until: Condition,
+        /// Give up after this long, e.g. 20m (default: wait forever)
+        #[arg(short, long, value_parser = cleanup::parse_duration)]
+        timeout: Option<Duration>,
+    },
+    /// Show or change a session's tags
+    Tag {
+        /// Name of the session
+        name: String,
+        /// Tags to add, e.g. `ticket:ABC-12` or `frontend`
+        tags: Vec<String>,
+        /// Remove a tag; repeatable
+        #[arg(short, long, value_name = "TAG")]
+        remove: Vec<String>,
+        /// Remove all tags before adding any
+        #[arg(long)]
+        clear: bool,
+    },
+    /// Show or set a session's note
+    Note {
+        /// Name of the session
+        name: String,
+        /// Note text; several words are joined with spaces
+        text: Vec<String>,
+        /// Remove the note
+        #[arg(long, conflicts_with = "text")]
+        clear: bool,
+    },
+    /// Search the scrollback of all sessions
+    Grep {
+        /// Regular expression to search for
+        pattern: String,
+        /// Match case-insensitively
+        #[arg(short, long)]
+        ignore_case: bool,
+        /// Lines of context to show around each match
+        #[arg(short = 'C', long, default_value_t = 0)]
+        context: usize,
+    },
+    /// Export a session transcript
+    Export {
+        /// Session name or `session:window.pane` target to export
+        name: String,
+        /// Output format: md, html or txt
+        #[arg(short, long, default_value = "md")]
+        format: ExportFormat,
+        /// File to write the transcript to (defaults to stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Record a session to an asciicast v2 file without attaching
+    Record {
+        /// Session name or `session:window.pane` target to record
+        name: String,
+        /// Cast file to write (defaults to `<name>-<timestamp>.cast`)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Stop an ongoing recording instead of starting one
+        #[arg(long)]
+        stop: bool,
+    },
+    /// Play back an asciicast recording
+    Replay {
+        /// Cast file to play
+        file: PathBuf,
+        /// Playback speed multiplier
+        #[arg(short, long, default_value_t = 1.0)]
+        speed: f64,
+    },
+    /// Watch sessions and run notifications when agents need attention
+    Watch,
+    /// Run the background daemon serving session state over a Unix socket
+    Daemon {
+        /// Stop the running daemon
+        #[arg(long, conflicts_with = "status")]
+        stop: bool,
+        /// Show whether the daemon is running
+        #[arg(long)]
+        status: bool,
+    },
+    /// Check that tmux, the config and agent commands are set up correctly
+    Doctor,
+    /// Queue prompts for agents and run them within concurrency limits
+    Queue {
+        #[command(subcommand)]
+        command: QueueCommand,
+    },
+    /// Apply the `[cleanup]` policies to forgotten sessions
+    #[command(alias = "prune")]
+    Gc {
+        /// List what the policies would do, and why, without doing it
+        #[arg(long)]
+        dry_run: bool,
+        /// Keep applying the policies every `[cleanup] interval`
+        #[arg(long)]
+        watch: bool,
+    },
+    /// Receive piped pane output for `ams record` (used internally)
+    #[command(hide = true)]
+    RecordSink {
+        /// Cast file to append events to
+        file: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum QueueCommand {
+    /// Queue a prompt for an agent
+    Add {
+        /// Agent profile to run (default: `default_agent`)
+        #[arg(short, long)]
+        agent: Option<String>,
+        /// Working directory for the task's session (default: current
+        /// directory)
+        #[arg(short, long)]
+        dir: Option<PathBuf>,
+        /// Prompt sent once the agent is ready
+        #[arg(required = true)]
+        prompt: Vec<String>,
+    },
+    /// List queued, running and finished tasks
+    List,
+    /// Start queued tasks within the `[queue]` limits and track them
+    Run {
+        /// Check and start tasks once instead of every `[queue] interval`
+        #[arg(long)]
+        once: bool,
+    },
+    /// Remove a task from the queue (its session keeps running)
+    Remove {
+        /// Task id, as shown by `ams queue list`
+        id: u64,
+    },
 }
 
 fn main() {
     let cli = Cli::parse();
 
-    let result = match cli.command {
-        Some(Commands::Tui) | None => run_tui(),
-        Some(Commands::List) => run_list(),
+    let result = select_targets(&cli).and_then(|aggregate| match cli.command {
+        Some(Commands::Tui) | None => run_tui(&aggregate),
+        Some(Commands::List) => run_list(&aggregate),
         Some(Commands::Attach { name }) => run_attach(&name),
-        Some(Commands::New { name, directory }) => run_new(&name, directory.as_deref()),
+        Some(Commands::New {
+            name,
+            directory,
+            template,
+            agent,
+            env,
+            env_file,
+            prompt,
+            prompt_file,
+            ready_timeout,
+        }) => {
+            let prompt = match prompt_file {
+                Some(path) => Some(ams::prompt::read_file(&path)?),
+                None => prompt,
+            };
+            run_new(
+                &name,
+                directory.as_deref(),
+                template.as_deref(),
+                agent.as_deref(),
+                &env,
+                &env_file,
+                prompt.as_deref().map(|text| (text, ready_timeout)),
+            )
+        }
+        Some(Commands::Trust { path }) => run_trust(path.as_deref()),
         Some(Commands::Kill { name }) => run_kill(&name),
-    };
+        Some(Commands::Send {
+            name,
+            text,
+            no_enter,
+        }) => run_send(&name, &text.join(" "), !no_enter),
+        Some(Commands::Wait {
+            name,
+            until,
+            timeout,
+        }) => run_wait(&name, &until, timeout),
+        Some(Commands::Tag {
+            name,
+            tags,
+            remove,
+            clear,
+        }) => run_tag(&name, &tags, &remove, clear),
+        Some(Commands::Note { name, text, clear }) => run_note(&name, &text.join(" "), clear),
+        Some(Commands::Grep {
+            pattern,
+            ignore_case,
+            context,
+        }) => run_grep(&pattern, ignore_case, context),
+        Some(Commands::Export {
+            name,
+            format,
+            output,
+        }) => run_export(&name, format, output.as_deref()),
+        Some(Commands::Record { name, output, stop }) => run_record(&name, output, stop),
+        Some(Commands::Replay { file, speed }) => run_replay(&file, speed),
+        Some(Commands::RecordSink { file }) => run_record_sink(&file),
+        Some(Commands::Watch) => run_watch(),
+        Some(Commands::Daemon { stop, status }) => run_daemon(stop, status),
+        Some(Commands::Doctor) => run_doctor(),
+        Some(Commands::Gc { dry_run, watch }) => run_gc(dry_run, watch),
+        Some(Commands::Queue { command }) => run_queue(command),
+    });
 
     if let Err(e) = result {
         eprintln!("Error: {}", e);
-        std::process::exit(1);
+        let code = e
+            .downcast_ref::<WaitError>()
+            .map_or(1, WaitError::exit_code);
+        std::process::exit(code);
+    }
+}
+
+/// Selects the host and tmux server from the options and config, and
+/// returns the hosts and servers to list together
+fn select_targets(cli: &Cli) -> Result<Aggregate, Box<dyn std::error::Error>> {
+    // A broken config is reported by the commands that need it and by
+    // `ams doctor`; server selection falls back to the defaults.
+    let config = ams::Config::load();
+    let tmux_config = config
+        .as_ref()
+        .map(|config| config.tmux.clone())
+        .unwrap_or_default();
+    tmux::set_agent_commands(config.as_ref().map_or_else(
+        |_| ams::Config::default().agent_commands(),
+        |config| config.agent_commands(),
+    ));
+    let host = |name: &str| -> Result<Option<Host>, Box<dyn std::error::Error>> {
+        match &config {
+            Ok(config) => Ok(config.host(name)?),
+            Err(e) => Err(e.to_string().into()),
+        }
+    };
+
+    tmux::set_socket(match (&cli.socket_path, &cli.socket_name) {
+        (Some(path), _) => Socket::Path(path.clone()),
+        (None, Some(name)) => Socket::Name(name.clone()),
+        (None, None) => tmux_config.socket(),
+    });
+    if let Some(name) = &cli.host {
+        tmux::set_host(host(name)?);
     }
+
+    let servers = if cli.servers.is_empty() {
+        &tmux_config.servers
+    } else {
+        &cli.servers
+    };
+    let hosts = if cli.hosts.is_empty() {
+        &tmux_config.hosts
+    } else {
+        &cli.hosts
+    };
+    Ok(Aggregate {
+        hosts: hosts
+            .iter()
+            .map(|name| host(name))
+            .collect::<Result<_, _>>()?,
+        servers: servers
+            .iter()
+            .map(|label| Socket::from_label(label))
+            .collect(),
+    })
 }
 
-fn run_tui() -> Result<(), Box<dyn std::error::Error>> {
-    if let Some(session_name) = ams::tui::run()? {
-        ams::attach_session(&session_name)?;
+fn run_tui(aggregate: &Aggregate) -> Result<(), Box<dyn std::error::Error>> {
+    let config = ams::Config::load()?;
+    if let Some(selection) = ams::tui::run(
+        &config.tui,
+        ams::StateMatcher::new(&config.notifications.patterns)?,
+        aggregate,
+    )? {
+        if let Some(host) = selection.host {
+            tmux::set_host(config.host(&host)?);
+        }
+        if let Some(server) = selection.server {
+            tmux::set_socket(Socket::from_label(&server));
+        }
+        ams::attach_session(&selection.target)?;
     }
     Ok(())
 }
@@ -68,40 +405,683 @@ fn run_attach(name: &str) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-fn run_new(name: &str, directory: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
-    ams::create_session(name, directory)?;
+fn run_new(
+    name: &str,
+    directory: Option<&str>,
+    template: Option<&str>,
+    agent: Option<&str>,
+    env: &[String],
+    env_files: &[PathBuf],
+    prompt: Option<(&str, Duration)>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = ams::Config::load()?;
+    let mut applied: Vec<PathBuf> = ams::config::config_path()
+        .filter(|path| path.exists())
+        .into_iter()
+        .collect();
+
+    // A remote session's directory is not on this machine
+    if tmux::host().is_none() {
+        let start = directory.map_or_else(std::env::current_dir, |dir| Ok(PathBuf::from(dir)))?;
+        if let Some(project) = Project::discover(&start)? {
+            if !project.is_trusted() {
+                approve_project(&project)?;
+            }
+            project.apply(&mut config);
+            applied.push(project.path);
+        }
+    }
+    for path in &applied {
+        println!("Using config: {}", path.display());
+    }
+
+    let session = &config.session;
+    let name = session.session_name(name);
+    let template = template.map(ams::Template::load).transpose()?;
+
+    // The launched agent, or a template's first agent pane, gets the prompt
+    // and is recorded in the session's user options
+    let agent_name = match &template {
+        Some(template) => template
+            .windows
+            .iter()
+            .flat_map(|window| &window.panes)
+            .find_map(|pane| pane.agent.clone()),
+        None => agent.or(config.default_agent.as_deref()).map(String::from),
+    };
+    let agent_profile = agent_name
+        .as_deref()
+        .map(|agent| config.agent(agent))
+        .transpose()?;
+    let profile = agent_profile.filter(|_| template.is_none());
+    let origin = Origin::new("ams new", agent_name.as_deref().zip(agent_profile));
+
+    let ready_matcher = match prompt {
+        Some(_) if agent_profile.is_none() => {
+            return Err("--prompt needs an agent: pass --agent or set default_agent".into())
+        }
+        Some(_) => Some(ams::prompt::ready_matcher(
+            &ams::StateMatcher::new(&config.notifications.patterns)?,
+            agent_profile,
+        )?),
+        None => None,
+    };
+
+    // Later layers win: session defaults, the template or agent profile,
+    // env files, then --env
+    let mut vars = session.environment()?;
+    if let Some(template) = &template {
+        vars.extend(template.env.clone());
+    }
+    if let Some(profile) = profile {
+        vars.extend(environment::resolve(&profile.env)?);
+    }
+    for file in env_files {
+        vars.extend(environment::read_env_file(file)?);
+    }
+    for assignment in env {
+        let (key, value) = environment::parse_assignment(assignment)?;
+        vars.insert(key, value);
+    }
+
+    if let Some(setup) = &session.setup {
+        run_setup(setup, directory)?;
+    }
+    if let Some(mut template) = template {
+        template.env = vars;
+        template.create_session(&name, directory.map(Path::new), &config.agents)?;
+    } else {
+        tmux::create_session_with_env(&name, directory, &vars)?;
+        if let Some(profile) = profile {
+            ams::send_keys(&name, &profile.command_line(), true)?;
+        }
+    }
+    origin.record(&name)?;
+    daemon::notify_changed();
     println!("Created session: {}", name);
+
+    if let (Some((text, timeout)), Some(matcher)) = (prompt, ready_matcher) {
+        println!("Waiting for the agent to be ready...");
+        ams::prompt::deliver(&name, text, &matcher, timeout)?;
+        println!("Sent prompt to {name}");
+    }
+    Ok(())
+}
+
+/// Runs the configured setup command in the session directory, failing if
+/// it fails
+fn run_setup(setup: &str, directory: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let script = directory.map_or_else(
+        || setup.to_string(),
+        |dir| format!("cd {} && {setup}", ams::shell::quote(dir)),
+    );
+    let status = tmux::shell_command(&script).status()?;
+    if !status.success() {
+        return Err(format!("Setup command failed ({status}): {setup}").into());
+    }
+    Ok(())
+}
+
+/// Asks on the terminal whether to trust a project config that runs
+/// commands, recording the answer
+fn approve_project(project: &Project) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::{BufRead, IsTerminal, Write};
+
+    let untrusted = || ProjectError::Untrusted(project.path.clone());
+    if !std::io::stdin().is_terminal() {
+        return Err(untrusted().into());
+    }
+
+    let commands = project.commands();
+    if !commands.is_empty() {
+        eprintln!("{} wants to run:", project.path.display());
+        for command in commands {
+            eprintln!("  {command}");
+        }
+    }
+    let env = project.env_names();
+    if !env.is_empty() {
+        eprintln!("{} wants to set:", project.path.display());
+        for name in env {
+            eprintln!("  {name}");
+        }
+    }
+    eprint!("Trust this file? [y/N] ");
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    if !matches!(answer.trim(), "y" | "Y" | "yes") {
+        return Err(untrusted().into());
+    }
+    project.trust()?;
+    Ok(())
+}
+
+fn run_trust(path: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+    let start = path.map_or_else(std::env::current_dir, |path| Ok(path.to_path_buf()))?;
+    let project = if start.is_file() {
+        Project::load(&start)?
+    } else {
+        Project::discover(&start)?.ok_or_else(|| {
+            format!(
+                "No {} in {} or its parents",
+                ams::project::PROJECT_FILE,
+                start.display()
+            )
+        })?
+    };
+
+    project.trust()?;
+    println!("Trusted {}", project.path.display());
+    for command in project.commands() {
+        println!("  {command}");
+    }
+    for name in project.env_names() {
+        println!("  sets {name}");
+    }
     Ok(())
 }
 
 fn run_kill(name: &str) -> Result<(), Box<dyn std::error::Error>> {
     ams::kill_session(name)?;
+    daemon::notify_changed();
     println!("Killed session: {}", name);
     Ok(())
 }
 
-fn run_list() -> Result<(), Box<dyn std::error::Error>> {
+fn run_send(name: &str, text: &str, enter: bool) -> Result<(), Box<dyn std::error::Error>> {
+    ams::send_keys(name, text, enter)?;
+    Ok(())
+}
+
+fn run_wait(
+    name: &str,
+    condition: &Condition,
+    timeout: Option<Duration>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = ams::Config::load()?;
+    let matcher = ams::StateMatcher::new(&config.notifications.patterns)?;
+    ams::wait::wait_for(name, condition, &matcher, timeout)?;
+    Ok(())
+}
+
+fn run_tag(
+    name: &str,
+    add: &[String],
+    remove: &[String],
+    clear: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let session = ams::get_session(name)?;
+    if add.is_empty() && remove.is_empty() && !clear {
+        for tag in &session.tags {
+            println!("{tag}");
+        }
+        return Ok(());
+    }
+
+    let mut tags = if clear { Vec::new() } else { session.tags };
+    tags.retain(|tag| !remove.contains(tag));
+    for tag in add {
+        if !tags.contains(tag) {
+            tags.push(tag.clone());
+        }
+    }
+    metadata::set_tags(name, &tags)?;
+    daemon::notify_changed();
+    if tags.is_empty() {
+        println!("Removed the tags of {name}");
+    } else {
+        println!("Tags of {name}: {}", tags.join(", "));
+    }
+    Ok(())
+}
+
+fn run_note(name: &str, text: &str, clear: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if text.is_empty() && !clear {
+        let session = ams::get_session(name)?;
+        if let Some(note) = session.note {
+            println!("{note}");
+        }
+        return Ok(());
+    }
+
+    metadata::set_note(name, Some(text).filter(|_| !clear))?;
+    daemon::notify_changed();
+    if clear {
+        println!("Removed the note of {name}");
+    } else {
+        println!("Set the note of {name}");
+    }
+    Ok(())
+}
+
+fn run_grep(
+    pattern: &str,
+    ignore_case: bool,
+    context: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pattern = RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .build()?;
+    let matches = ams::search_all(&pattern, context)?;
+
+    if matches.is_empty() {
+        println!("No matches found.");
+        return Ok(());
+    }
+
+    for (i, m) in matches.iter().enumerate() {
+        if context > 0 && i > 0 {
+            println!("--");
+        }
+        print_match(m);
+    }
+
+    Ok(())
+}
+
+fn print_match(m: &SearchMatch) {
+    let first_line = m.line_number - m.before.len();
+    for (offset, line) in m.before.iter().enumerate() {
+        println!("{}:{}-{}", m.pane, first_line + offset, line);
+    }
+    println!("{}:{}:{}", m.pane, m.line_number, m.line);
+    for (offset, line) in m.after.iter().enumerate() {
+        println!("{}:{}-{}", m.pane, m.line_number + 1 + offset, line);
+    }
+}
+
+fn run_export(
+    name: &str,
+    format: ExportFormat,
+    output: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let transcript = ams::export_session(name, format)?;
+
+    match output {
+        Some(path) => {
+            std::fs::write(path, transcript)?;
+            println!("Exported {} to {}", name, path.display());
+        }
+        None => print!("{transcript}"),
+    }
+
+    Ok(())
+}
+
+fn run_record(
+    name: &str,
+    output: Option<PathBuf>,
+    stop: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if stop {
+        ams::recording::stop_recording(name)?;
+        println!("Stopped recording: {}", name);
+        return Ok(());
+    }
+
+    let output = output.unwrap_or_else(|| {
+        let stamp = Utc::now().format("%Y%m%d-%H%M%S");
+        PathBuf::from(format!("{}-{stamp}.cast", name.replace([':', '.'], "-")))
+    });
+    let output = std::env::current_dir()?.join(output);
+
+    let exe = std::env::current_exe()?;
+    let sink = format!(
+        "{} record-sink {}",
+        ams::shell::quote(&exe.to_string_lossy()),
+        ams::shell::quote(&output.to_string_lossy())
+    );
+
+    ams::recording::start_recording(name, &output, &sink)?;
+    println!("Recording {} to {}", name, output.display());
+    println!("Stop with: ams record --stop {}", name);
+    Ok(())
+}
+
+fn run_record_sink(file: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    ams::recording::run_sink(std::io::stdin().lock(), file)?;
+    Ok(())
+}
+
+fn run_replay(file: &Path, speed: f64) -> Result<(), Box<dyn std::error::Error>> {
+    if !(speed.is_finite() && speed > 0.0) {
+        return Err("speed must be a positive number".into());
+    }
+    let recording = ams::Recording::load(file)?;
+    ams::tui::replay(&recording, speed)?;
+    Ok(())
+}
+
+fn run_watch() -> Result<(), Box<dyn std::error::Error>> {
+    let config = ams::Config::load()?;
+    let notifications = config.notifications;
+    let matcher = ams::StateMatcher::new(&notifications.patterns)?;
+    let mut detector = ams::TransitionDetector::new();
+    let interval = std::time::Duration::from_millis(notifications.poll_interval_ms);
+
+    println!("Watching sessions (Ctrl-C to stop)");
+    loop {
+        match list_sessions()
+            .and_then(|sessions| ams::watch::snapshot_sessions(&sessions, &matcher))
+        {
+            Ok(snapshots) => {
+                for event in detector.update(snapshots) {
+                    println!(
+                        "{} {}",
+                        chrono::Local::now().format("%H:%M:%S"),
+                        ams::notify::message(&event)
+                    );
+                    for failure in ams::notify::dispatch(&notifications.rules, &event) {
+                        eprintln!("Notification failed: {}", failure);
+                    }
+                }
+            }
+            Err(e) => eprintln!("Error: {}", e),
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+fn run_queue(command: QueueCommand) -> Result<(), Box<dyn std::error::Error>> {
+    let path = queue::queue_path();
+
+    match command {
+        QueueCommand::Add { agent, dir, prompt } => {
+            let config = ams::Config::load()?;
+            let agent = agent
+                .or(config.default_agent.clone())
+                .ok_or("No agent given and no default_agent configured")?;
+            config.agent(&agent)?;
+            let dir = match dir {
+                // A remote directory cannot be checked here
+                Some(dir) if tmux::host().is_some() => dir,
+                Some(dir) => dir.canonicalize()?,
+                None => std::env::current_dir()?,
+            };
+            let host = tmux::host().map(|host| host.name);
+            let id = Queue::update(&path, |queue| {
+                queue.add(&agent, &dir, &prompt.join(" "), host.as_deref(), Utc::now())
+            })?;
+            println!("Queued task {id} for {agent}");
+        }
+        QueueCommand::List => {
+            let queue = Queue::load(&path)?;
+            if queue.tasks.is_empty() {
+                println!("No tasks queued.");
+                return Ok(());
+            }
+            println!(
+                "{:<5} {:<10} {:<12} {:<12} {:<14} PROMPT",
+                "ID", "STATUS", "AGENT", "HOST", "SESSION"
+            );
+            for task in &queue.tasks {
+                let prompt = task.error.as_ref().map_or_else(
+                    || task.prompt.lines().next().unwrap_or_default().to_string(),
+                    |error| format!("({error})"),
+                );
+                println!(
+                    "{:<5} {:<10} {} {} {} {}",
+                    task.id,
+                    task.status.to_string(),
+                    text::pad(&task.agent, 12),
+                    text::pad(task.host.as_deref().unwrap_or(Host::LOCAL), 12),
+                    text::pad(task.session.as_deref().unwrap_or("-"), 14),
+                    text::truncate(&prompt, 50)
+                );
+            }
+        }
+        QueueCommand::Run { once } => {
+            let config = ams::Config::load()?;
+            let matcher = ams::StateMatcher::new(&config.notifications.patterns)?;
+            if !once {
+                println!("Running queued tasks (Ctrl-C to stop)");
+            }
+            loop {
+                match queue::tick(&path, &config, &matcher, Utc::now()) {
+                    Ok(events) => {
+                        if !events.is_empty() {
+                            daemon::notify_changed();
+                        }
+                        for event in events {
+                            println!("{} {}", chrono::Local::now().format("%H:%M:%S"), event);
+                        }
+                    }
+                    Err(e) if !once => eprintln!("Error: {}", e),
+                    Err(e) => return Err(e.into()),
+                }
+                if once {
+                    return Ok(());
+                }
+                std::thread::sleep(config.queue.interval);
+            }
+        }
+        QueueCommand::Remove { id } => {
+            let task = Queue::update(&path, |queue| queue.remove(id))??;
+            println!("Removed task {}", task.id);
+        }
+    }
+    Ok(())
+}
+
+fn run_gc(dry_run: bool, watch: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let config = ams::Config::load()?;
+    let policy = &config.cleanup;
+    if policy.is_empty() {
+        println!("No cleanup policies configured (see [cleanup] in the config)");
+        return Ok(());
+    }
+    let matcher = ams::StateMatcher::new(&config.notifications.patterns)?;
+
+    if watch {
+        println!(
+            "Applying cleanup policies every {} (Ctrl-C to stop)",
+            cleanup::format_duration(policy.interval)
+        );
+    }
+    loop {
+        match cleanup_actions(policy, &config.agent_commands(), &matcher) {
+            Ok(actions) if actions.is_empty() && !watch => println!("Nothing to clean up"),
+            Ok(actions) => apply_cleanup(&actions, policy, dry_run, watch),
+            Err(e) if watch => eprintln!("Error: {}", e),
+            Err(e) => return Err(e.into()),
+        }
+        if !watch {
+            return Ok(());
+        }
+        std::thread::sleep(policy.interval);
+    }
+}
+
+/// Evaluates the cleanup policies against the current sessions
+fn cleanup_actions(
+    policy: &ams::config::CleanupConfig,
+    agent_commands: &[String],
+    matcher: &ams::StateMatcher,
+) -> Result<Vec<cleanup::Action>, ams::TmuxError> {
     let sessions = list_sessions()?;
+    // Only attached sessions can be detached, so only they need a state
+    let states = if policy.detach_waiting_after.is_some() {
+        let attached: Vec<_> = sessions
+            .iter()
+            .filter(|session| session.status == SessionStatus::Active)
+            .cloned()
+            .collect();
+        ams::watch::snapshot_sessions(&attached, matcher)?
+            .into_iter()
+            .map(|snapshot| (snapshot.name, snapshot.state))
+            .collect()
+    } else {
+        HashMap::new()
+    };
+    Ok(cleanup::plan(
+        policy,
+        &sessions,
+        agent_commands,
+        &states,
+        Utc::now(),
+        cleanup::repo_root,
+    ))
+}
+
+fn apply_cleanup(
+    actions: &[cleanup::Action],
+    policy: &ams::config::CleanupConfig,
+    dry_run: bool,
+    watch: bool,
+) {
+    let width = actions
+        .iter()
+        .map(|action| text::width(&action.session))
+        .max()
+        .unwrap_or(0);
+    let time = if watch {
+        chrono::Local::now().format("%H:%M:%S ").to_string()
+    } else {
+        String::new()
+    };
+
+    for action in actions {
+        let done = match action.kind {
+            cleanup::ActionKind::Kill => "killed",
+            cleanup::ActionKind::Detach => "detached",
+        };
+        if dry_run {
+            println!(
+                "{time}would {:<6}  {}  {}",
+                action.kind,
+                text::pad(&action.session, width),
+                action.reason
+            );
+            continue;
+        }
+        match action.apply() {
+            Ok(()) => {
+                println!(
+                    "{time}{done:<10}  {}  {}",
+                    text::pad(&action.session, width),
+                    action.reason
+                );
+                if policy.notify_send {
+                    let body = format!("{done} ({})", action.reason);
+                    if let Err(e) = ams::notify::notify_send(&action.session, &body) {
+                        eprintln!("Notification failed: {}", e);
+                    }
+                }
+            }
+            Err(e) => eprintln!("Failed to {} {}: {}", action.kind, action.session, e),
+        }
+    }
+    if !dry_run && !actions.is_empty() {
+        daemon::notify_changed();
+    }
+}
+
+fn run_daemon(stop: bool, status: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let path = daemon::socket_path();
+
+    if stop || status {
+        let Some(client) = Client::connect_to(&path) else {
+            println!("Daemon not running");
+            return Ok(());
+        };
+        if stop {
+            client.request(&Request::Shutdown)?;
+            println!("Daemon stopped");
+        } else if let Response::Pong {
+            daemon_version,
+            uptime_secs,
+        } = client.request(&Request::Ping)?
+        {
+            println!(
+                "Daemon {} running on {} (up {}s)",
+                daemon_version,
+                path.display(),
+                uptime_secs
+            );
+        }
+        return Ok(());
+    }
+
+    let server = daemon::Daemon::new(ams::Config::load()?)?;
+    println!("Daemon listening on {}", path.display());
+    server.run(&path)?;
+    Ok(())
+}
+
+fn run_doctor() -> Result<(), Box<dyn std::error::Error>> {
+    let checks = doctor::run_checks();
+    let width = checks
+        .iter()
+        .map(|check| check.name.len())
+        .max()
+        .unwrap_or(0);
+
+    for check in &checks {
+        let label = match check.status {
+            doctor::Status::Pass => "ok",
+            doctor::Status::Warn => "warn",
+            doctor::Status::Fail => "FAIL",
+        };
+        println!("[{label:<4}] {:<width$}  {}", check.name, check.summary);
+        if let Some(remediation) = &check.remediation {
+            println!("       {:<width$}  -> {remediation}", "");
+        }
+    }
+
+    let failed = checks
+        .iter()
+        .filter(|check| check.status == doctor::Status::Fail)
+        .count();
+    if failed > 0 {
+        return Err(format!("{failed} check(s) failed").into());
+    }
+    Ok(())
+}
+
+fn run_list(aggregate: &Aggregate) -> Result<(), Box<dyn std::error::Error>> {
+    let sessions = if aggregate.is_empty() {
+        daemon::list_sessions()?
+    } else {
+        let listing = tmux::list_sessions_on(aggregate)?;
+        for (source, error) in &listing.failures {
+            eprintln!(
+                "Failed to list sessions on {source}: {}",
+                error.to_string().trim_end()
+            );
+        }
+        listing.sessions
+    };
 
     if sessions.is_empty() {
         println!("No tmux sessions found.");
         return Ok(());
     }
 
+    let column = |shown: bool, value: &str| {
+        if shown {
+            format!("{} ", text::pad(value, 12))
+        } else {
+            String::new()
+        }
+    };
+    let host_column = |host: &str| column(!aggregate.hosts.is_empty(), host);
+    let server_column = |server: &str| column(!aggregate.servers.is_empty(), server);
+
     // Print header
     println!(
-        "{:<20} {:<8} {:<35} LAST ACTIVITY",
-        "NAME", "STATUS", "WORKING DIR"
+        "{}{}{:<20} {:<8} {:<35} LAST ACTIVITY",
+        host_column("HOST"),
+        server_column("SERVER"),
+        "NAME",
+        "STATUS",
+        "WORKING DIR"
     );
 
     // Print sessions
+    let home = std::env::var_os("HOME").map(PathBuf::from);
     for session in sessions {
-        let working_dir = session
-            .working_directory
-            .to_string_lossy()
-            .chars()
-            .take(35)
-            .collect::<String>();
+        let working_dir = text::abbreviate_path(&session.working_directory, home.as_deref(), 35);
 
         let last_activity = format_relative_time(session.last_activity);
 
@@ -112,10 +1092,12 @@ fn run_list() -> Result<(), Box<dyn std::error::Error>> {
         };
 
         println!(
-            "{:<20} {:<8} {:<35} {}",
-            truncate(&session.name, 20),
+            "{}{}{} {:<8} {} {}",
+            host_column(session.host.as_deref().unwrap_or_default()),
+            server_column(session.server.as_deref().unwrap_or_default()),
+            text::pad(&session.name, 20),
             status_str,
-            working_dir,
+            text::pad(&working_dir, 35),
             last_activity
         );
     }
@@ -123,14 +1105,6 @@ fn run_list() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-fn truncate(s: &str, max_len: usize) -> String {
-    if s.len() <= max_len {
-        s.to_string()
-    } else {
-        format!("{}...", &s[..max_len - 3])
-    }
-}
-
 fn format_relative_time(dt: chrono::DateTime<Utc>) -> String {
     let now = Utc::now();
     let duration = now.signed_duration_since(dt);
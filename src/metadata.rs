@@ -0,0 +1,166 @@
+//! Session metadata kept in tmux user options, so it lives with the session
+//! and survives restarts of ams and its daemon.
+
+use crate::config::AgentProfile;
+use crate::tmux::{self, TmuxError};
+use thiserror::Error;
+
+/// User option holding a session's comma-separated tags
+pub const TAGS_OPTION: &str = "@ams-tags";
+
+/// User option holding a session's note
+pub const NOTE_OPTION: &str = "@ams-note";
+
+/// User option holding the agent command ams launched in a session
+pub const AGENT_OPTION: &str = "@ams-agent";
+
+/// User option holding the agent profile ams launched in a session
+pub const PROFILE_OPTION: &str = "@ams-profile";
+
+/// User option holding the ams command that created a session
+pub const CREATED_BY_OPTION: &str = "@ams-created-by";
+
+/// User option holding the id of the queued task a session runs
+pub const TASK_OPTION: &str = "@ams-task";
+
+/// What ams records about a session it creates
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Origin {
+    /// Agent command, e.g. `claude`
+    pub agent: Option<String>,
+    /// Agent profile from `[agents]`, e.g. `claude-code`
+    pub profile: Option<String>,
+    /// The creating command, e.g. `ams new`
+    pub created_by: String,
+    /// Queued task id
+    pub task: Option<u64>,
+}
+
+impl Origin {
+    /// An origin for a session created by `command` running `profile`
+    #[must_use]
+    pub fn new(created_by: &str, profile: Option<(&str, &AgentProfile)>) -> Self {
+        Self {
+            agent: profile.map(|(_, profile)| profile.command.clone()),
+            profile: profile.map(|(name, _)| name.to_string()),
+            created_by: created_by.to_string(),
+            task: None,
+        }
+    }
+
+    /// Stores the origin in the session's user options
+    pub fn record(&self, session: &str) -> Result<(), TmuxError> {
+        let task = self.task.map(|task| task.to_string());
+        tmux::set_session_options(
+            session,
+            &[
+                (AGENT_OPTION, self.agent.as_deref()),
+                (PROFILE_OPTION, self.profile.as_deref()),
+                (CREATED_BY_OPTION, Some(&self.created_by)),
+                (TASK_OPTION, task.as_deref()),
+            ],
+        )
+    }
+}
+
+/// Errors that can occur while changing session metadata.
+#[derive(Error, Debug)]
+pub enum MetadataError {
+    /// A tag that is empty or contains a comma or whitespace.
+    #[error("Invalid tag {0:?}: tags cannot be empty or contain commas or whitespace")]
+    InvalidTag(String),
+
+    /// A tmux command failed.
+    #[error(transparent)]
+    Tmux(#[from] TmuxError),
+}
+
+/// Splits the value of [`TAGS_OPTION`] into tags
+#[must_use]
+pub fn parse_tags(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .filter(|tag| !tag.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Checks that a tag survives being stored in [`TAGS_OPTION`]
+pub fn check_tag(tag: &str) -> Result<(), MetadataError> {
+    if tag.is_empty() || tag.contains(',') || tag.contains(char::is_whitespace) {
+        return Err(MetadataError::InvalidTag(tag.to_string()));
+    }
+    Ok(())
+}
+
+/// Stores a session's tags, removing the option when there are none
+pub fn set_tags(session: &str, tags: &[String]) -> Result<(), MetadataError> {
+    for tag in tags {
+        check_tag(tag)?;
+    }
+    let value = tags.join(",");
+    let value = Some(value.as_str()).filter(|value| !value.is_empty());
+    tmux::set_session_option(session, TAGS_OPTION, value)?;
+    Ok(())
+}
+
+/// Stores a session's note on one line, removing the option when `note`
+/// is `None` or blank
+pub fn set_note(session: &str, note: Option<&str>) -> Result<(), MetadataError> {
+    let note = note.map(clean_note).filter(|note| !note.is_empty());
+    tmux::set_session_option(session, NOTE_OPTION, note.as_deref())?;
+    Ok(())
+}
+
+/// Joins the words of a note with single spaces, since tmux lists
+/// sessions one per line
+fn clean_note(note: &str) -> String {
+    note.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tags() {
+        assert_eq!(
+            parse_tags("ticket:ABC-12,frontend"),
+            vec!["ticket:ABC-12", "frontend"]
+        );
+        assert!(parse_tags("").is_empty());
+        assert_eq!(parse_tags(",a,,b,"), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_check_tag() {
+        assert!(check_tag("ticket:ABC-12").is_ok());
+        for invalid in ["", "a,b", "two words", "tab\there"] {
+            assert!(
+                matches!(check_tag(invalid), Err(MetadataError::InvalidTag(_))),
+                "{invalid:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_origin_new() {
+        let profile = AgentProfile {
+            command: "claude".to_string(),
+            ..AgentProfile::default()
+        };
+        let origin = Origin::new("ams new", Some(("claude-code", &profile)));
+        assert_eq!(origin.agent.as_deref(), Some("claude"));
+        assert_eq!(origin.profile.as_deref(), Some("claude-code"));
+        assert_eq!(origin.created_by, "ams new");
+        assert_eq!(Origin::new("ams tui", None).profile, None);
+    }
+
+    #[test]
+    fn test_clean_note() {
+        assert_eq!(
+            clean_note("  waiting on\nreview\tfrom Sam "),
+            "waiting on review from Sam"
+        );
+    }
+}
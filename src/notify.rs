@@ -0,0 +1,180 @@
+//! Notification actions fired by session state transitions.
+
+use crate::config::NotificationRule;
+use crate::watch::WatchEvent;
+use std::io::Write;
+use std::process::Command;
+
+/// Runs every rule that matches the event
+///
+/// Failures of individual actions are returned as messages so that one
+/// broken command does not stop the others.
+#[must_use]
+pub fn dispatch(rules: &[NotificationRule], event: &WatchEvent) -> Vec<String> {
+    let mut failures = Vec::new();
+
+    for rule in rules.iter().filter(|rule| rule.on.contains(&event.trigger)) {
+        if rule.bell {
+            let mut stdout = std::io::stdout();
+            if let Err(e) = stdout.write_all(b"\x07").and_then(|()| stdout.flush()) {
+                failures.push(format!("bell: {e}"));
+            }
+        }
+
+        for mut command in commands(rule, event) {
+            let program = command.get_program().to_string_lossy().into_owned();
+            match command.status() {
+                Ok(status) if status.success() => {}
+                Ok(status) => failures.push(format!("{program}: exited with {status}")),
+                Err(e) => failures.push(format!("{program}: {e}")),
+            }
+        }
+    }
+
+    failures
+}
+
+/// Shows a desktop notification titled `ams: <session>`
+pub fn notify_send(session: &str, body: &str) -> Result<(), String> {
+    match Command::new("notify-send")
+        .arg(format!("ams: {session}"))
+        .arg(body)
+        .status()
+    {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("notify-send: exited with {status}")),
+        Err(e) => Err(format!("notify-send: {e}")),
+    }
+}
+
+/// Builds the external commands a rule runs for an event
+fn commands(rule: &NotificationRule, event: &WatchEvent) -> Vec<Command> {
+    let mut commands = Vec::new();
+
+    if rule.notify_send {
+        let mut command = Command::new("notify-send");
+        command
+            .arg(format!("ams: {}", event.session))
+            .arg(message(event));
+        commands.push(command);
+    }
+
+    if let Some(script) = &rule.command {
+        let mut command = Command::new("sh");
+        command
+            .args(["-c", script])
+            .env("AMS_SESSION", &event.session)
+            .env("AMS_EVENT", event.trigger.to_string())
+            .env("AMS_STATE", event.state.to_string())
+            .env("AMS_PREVIOUS_STATE", event.previous.to_string())
+            .env("AMS_DIRECTORY", &event.directory);
+        commands.push(command);
+    }
+
+    commands
+}
+
+/// Describes an event in one human-readable line
+#[must_use]
+pub fn message(event: &WatchEvent) -> String {
+    use crate::config::Trigger;
+
+    match event.trigger {
+        Trigger::Bell => format!("{} rang the bell", event.session),
+        Trigger::Activity => format!("{} has new activity", event.session),
+        _ => format!(
+            "{} is {} (was {})",
+            event.session, event.state, event.previous
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Trigger;
+    use crate::session::AgentState;
+    use std::ffi::OsStr;
+    use std::path::PathBuf;
+
+    fn event(trigger: Trigger) -> WatchEvent {
+        WatchEvent {
+            trigger,
+            session: "agent".to_string(),
+            directory: PathBuf::from("/repo"),
+            previous: AgentState::Working,
+            state: AgentState::WaitingForInput,
+        }
+    }
+
+    fn rule(on: Vec<Trigger>) -> NotificationRule {
+        NotificationRule {
+            on,
+            command: None,
+            notify_send: false,
+            bell: false,
+        }
+    }
+
+    #[test]
+    fn test_message() {
+        assert_eq!(
+            message(&event(Trigger::Waiting)),
+            "agent is waiting (was working)"
+        );
+        assert_eq!(message(&event(Trigger::Bell)), "agent rang the bell");
+        assert_eq!(message(&event(Trigger::Activity)), "agent has new activity");
+    }
+
+    #[test]
+    fn test_commands_notify_send() {
+        let mut rule = rule(vec![Trigger::Waiting]);
+        rule.notify_send = true;
+        let commands = commands(&rule, &event(Trigger::Waiting));
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].get_program(), "notify-send");
+        let args: Vec<&OsStr> = commands[0].get_args().collect();
+        assert_eq!(args, vec!["ams: agent", "agent is waiting (was working)"]);
+    }
+
+    #[test]
+    fn test_commands_user_command_env() {
+        let mut rule = rule(vec![Trigger::Waiting]);
+        rule.command = Some("echo $AMS_SESSION".to_string());
+        let commands = commands(&rule, &event(Trigger::Waiting));
+        assert_eq!(commands[0].get_program(), "sh");
+        let envs: Vec<(&OsStr, Option<&OsStr>)> = commands[0].get_envs().collect();
+        assert!(envs.contains(&(OsStr::new("AMS_SESSION"), Some(OsStr::new("agent")))));
+        assert!(envs.contains(&(OsStr::new("AMS_EVENT"), Some(OsStr::new("waiting")))));
+        assert!(envs.contains(&(OsStr::new("AMS_STATE"), Some(OsStr::new("waiting")))));
+        assert!(envs.contains(&(
+            OsStr::new("AMS_PREVIOUS_STATE"),
+            Some(OsStr::new("working"))
+        )));
+        assert!(envs.contains(&(OsStr::new("AMS_DIRECTORY"), Some(OsStr::new("/repo")))));
+    }
+
+    #[test]
+    fn test_dispatch_runs_matching_rules_only() {
+        let marker = std::env::temp_dir().join(format!("ams-notify-{}", std::process::id()));
+        let mut matching = rule(vec![Trigger::Waiting]);
+        matching.command = Some(format!("echo \"$AMS_EVENT\" > '{}'", marker.display()));
+        let mut other = rule(vec![Trigger::Bell]);
+        other.command = Some("exit 3".to_string());
+
+        let failures = dispatch(&[matching, other], &event(Trigger::Waiting));
+        assert!(failures.is_empty());
+        let written = std::fs::read_to_string(&marker).unwrap();
+        std::fs::remove_file(&marker).unwrap();
+        assert_eq!(written, "waiting\n");
+    }
+
+    #[test]
+    fn test_dispatch_reports_failures() {
+        let mut failing = rule(vec![Trigger::Bell]);
+        failing.command = Some("exit 3".to_string());
+        let failures = dispatch(&[failing], &event(Trigger::Bell));
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].starts_with("sh: exited with"));
+    }
+}
@@ -0,0 +1,131 @@
+//! Resource use of the processes running in tmux panes.
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::process::Command;
+
+/// CPU and memory use of a group of processes
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    /// CPU use in percent of one core, as reported by `ps`
+    pub cpu: f64,
+    /// Resident memory in kilobytes
+    pub memory_kb: u64,
+}
+
+/// A snapshot of the processes on the local machine
+#[derive(Debug, Clone, Default)]
+pub struct ProcessTable {
+    children: HashMap<u32, Vec<u32>>,
+    usage: HashMap<u32, Usage>,
+}
+
+impl ProcessTable {
+    /// Lists every process with `ps`
+    pub fn read() -> io::Result<Self> {
+        let output = Command::new("ps")
+            .args(["-A", "-o", "pid=,ppid=,pcpu=,rss="])
+            .output()?;
+        if !output.status.success() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ));
+        }
+        Ok(Self::parse(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    /// Parses `ps -o pid=,ppid=,pcpu=,rss=` output, skipping malformed lines
+    #[must_use]
+    pub fn parse(output: &str) -> Self {
+        let mut table = Self::default();
+        for line in output.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [pid, parent, cpu, rss] = fields[..] else {
+                continue;
+            };
+            let (Ok(pid), Ok(parent), Ok(cpu), Ok(memory_kb)) =
+                (pid.parse(), parent.parse(), cpu.parse(), rss.parse())
+            else {
+                continue;
+            };
+            table.children.entry(parent).or_default().push(pid);
+            table.usage.insert(pid, Usage { cpu, memory_kb });
+        }
+        table
+    }
+
+    /// Sums the use of the given processes and all their descendants,
+    /// counting each process once
+    #[must_use]
+    pub fn tree_usage(&self, roots: &[u32]) -> Usage {
+        let mut total = Usage::default();
+        let mut seen = HashSet::new();
+        let mut pending = roots.to_vec();
+        while let Some(pid) = pending.pop() {
+            if !seen.insert(pid) {
+                continue;
+            }
+            if let Some(usage) = self.usage.get(&pid) {
+                total.cpu += usage.cpu;
+                total.memory_kb += usage.memory_kb;
+            }
+            pending.extend(self.children.get(&pid).into_iter().flatten());
+        }
+        total
+    }
+}
+
+/// Formats a memory size in kilobytes with a binary unit, e.g. `1.5G`
+#[must_use]
+pub fn format_memory(kb: u64) -> String {
+    #[allow(clippy::cast_precision_loss)] // Sizes are shown to one decimal
+    let mut size = kb as f64;
+    for unit in ["K", "M", "G"] {
+        if size < 1024.0 {
+            return if unit == "K" {
+                format!("{kb}{unit}")
+            } else {
+                format!("{size:.1}{unit}")
+            };
+        }
+        size /= 1024.0;
+    }
+    format!("{size:.1}T")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PS_OUTPUT: &str = "    1     0  0.0  1200
+  100     1  1.5  2048
+  101   100 10.0 10240
+  102   101  2.5  4096
+  200     1  50.0 999
+garbage line
+";
+
+    #[test]
+    fn test_tree_usage_sums_descendants() {
+        let table = ProcessTable::parse(PS_OUTPUT);
+        let usage = table.tree_usage(&[100]);
+        assert!((usage.cpu - 14.0).abs() < 1e-9);
+        assert_eq!(usage.memory_kb, 2048 + 10240 + 4096);
+    }
+
+    #[test]
+    fn test_tree_usage_counts_shared_processes_once() {
+        let table = ProcessTable::parse(PS_OUTPUT);
+        let usage = table.tree_usage(&[100, 101, 404]);
+        assert_eq!(usage.memory_kb, 2048 + 10240 + 4096);
+        assert_eq!(table.tree_usage(&[]), Usage::default());
+    }
+
+    #[test]
+    fn test_format_memory() {
+        assert_eq!(format_memory(512), "512K");
+        assert_eq!(format_memory(1536), "1.5M");
+        assert_eq!(format_memory(3 * 1024 * 1024), "3.0G");
+    }
+}
@@ -0,0 +1,346 @@
+//! Per-project configuration from `.ams.toml` files checked into repositories.
+
+use crate::config::{self, AgentProfile, Config, SessionConfig};
+use crate::environment::EnvValue;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// File name of a project configuration
+pub const PROJECT_FILE: &str = ".ams.toml";
+
+/// Errors that can occur while loading or trusting a project configuration.
+#[derive(Error, Debug)]
+pub enum ProjectError {
+    /// Failed to read or write a file.
+    #[error("Failed to access {0}: {1}")]
+    Io(PathBuf, #[source] std::io::Error),
+
+    /// The project configuration is not valid TOML or has invalid values.
+    #[error("Invalid project config {0}: {1}")]
+    Parse(PathBuf, #[source] toml::de::Error),
+
+    /// The project configuration runs commands or sets environment
+    /// variables the user has not approved.
+    #[error(
+        "{0} runs commands or sets environment variables but is not trusted; review it, then run `ams trust {0}`"
+    )]
+    Untrusted(PathBuf),
+
+    /// There is no config directory to keep approvals in.
+    #[error("No config directory for trusted projects; set XDG_CONFIG_HOME or HOME")]
+    NoTrustStore,
+}
+
+/// Settings a project may override, merged over the user configuration
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectConfig {
+    /// Agent to spawn when none is given
+    pub default_agent: Option<String>,
+    /// Agent command definitions, added to or replacing the user's
+    pub agents: BTreeMap<String, AgentProfile>,
+    /// Session defaults; set values replace the user's, env is merged
+    pub session: SessionConfig,
+}
+
+/// A project configuration file and its contents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Project {
+    /// Absolute path of the `.ams.toml` file
+    pub path: PathBuf,
+    /// Parsed settings
+    pub config: ProjectConfig,
+    contents: String,
+}
+
+impl Project {
+    /// Finds the nearest `.ams.toml` in `start` or one of its parents
+    pub fn discover(start: &Path) -> Result<Option<Self>, ProjectError> {
+        let start = start
+            .canonicalize()
+            .map_err(|e| ProjectError::Io(start.to_path_buf(), e))?;
+        start
+            .ancestors()
+            .map(|dir| dir.join(PROJECT_FILE))
+            .find(|path| path.is_file())
+            .map(|path| Self::load(&path))
+            .transpose()
+    }
+
+    /// Loads a project configuration file
+    pub fn load(path: &Path) -> Result<Self, ProjectError> {
+        let path = path
+            .canonicalize()
+            .map_err(|e| ProjectError::Io(path.to_path_buf(), e))?;
+        let contents =
+            std::fs::read_to_string(&path).map_err(|e| ProjectError::Io(path.clone(), e))?;
+        let config = toml::from_str(&contents).map_err(|e| ProjectError::Parse(path.clone(), e))?;
+        Ok(Self {
+            path,
+            config,
+            contents,
+        })
+    }
+
+    /// Returns the shell commands the project would run: its setup command,
+    /// the commands of its agent profiles and its secret commands
+    #[must_use]
+    pub fn commands(&self) -> Vec<String> {
+        let agents = self.config.agents.values().map(|agent| {
+            std::iter::once(agent.command.as_str())
+                .chain(agent.args.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join(" ")
+        });
+        let secrets = self
+            .config
+            .agents
+            .values()
+            .flat_map(|agent| agent.env.values())
+            .chain(self.config.session.env.values())
+            .filter_map(EnvValue::command)
+            .map(String::from);
+        self.config
+            .session
+            .setup
+            .iter()
+            .cloned()
+            .chain(agents)
+            .chain(secrets)
+            .collect()
+    }
+
+    /// Returns the environment variables the project sets in sessions
+    ///
+    /// Any of them can run code (`PROMPT_COMMAND`, `BASH_ENV`, `LD_PRELOAD`,
+    /// `PATH`, ...), so they need approval like commands do.
+    #[must_use]
+    pub fn env_names(&self) -> Vec<String> {
+        let names: std::collections::BTreeSet<&String> = self
+            .config
+            .agents
+            .values()
+            .flat_map(|agent| agent.env.keys())
+            .chain(self.config.session.env.keys())
+            .collect();
+        names.into_iter().cloned().collect()
+    }
+
+    /// Whether the project runs nothing and sets no environment, or the
+    /// user approved this exact file content
+    #[must_use]
+    pub fn is_trusted(&self) -> bool {
+        (self.commands().is_empty() && self.env_names().is_empty())
+            || config::trust_path().is_some_and(|store| self.is_trusted_in(&store))
+    }
+
+    /// Records that the user trusts the file's current content
+    pub fn trust(&self) -> Result<(), ProjectError> {
+        // Never fall back to the working directory: that is usually the
+        // untrusted repository, which could ship its own approvals.
+        let store = config::trust_path().ok_or(ProjectError::NoTrustStore)?;
+        self.trust_in(&store)
+    }
+
+    fn is_trusted_in(&self, store: &Path) -> bool {
+        read_trusted(store)
+            .ok()
+            .and_then(|mut trusted| trusted.remove(&self.path))
+            .is_some_and(|contents| contents == self.contents)
+    }
+
+    fn trust_in(&self, store: &Path) -> Result<(), ProjectError> {
+        let mut trusted = read_trusted(store)?;
+        trusted.insert(self.path.clone(), self.contents.clone());
+        let text = toml::to_string(&trusted).map_err(|e| {
+            ProjectError::Io(
+                store.to_path_buf(),
+                std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+            )
+        })?;
+        if let Some(dir) = store.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| ProjectError::Io(dir.to_path_buf(), e))?;
+        }
+        std::fs::write(store, text).map_err(|e| ProjectError::Io(store.to_path_buf(), e))
+    }
+
+    /// Merges the project's settings over a configuration
+    ///
+    /// A relative `worktree_root` is resolved against the project directory.
+    pub fn apply(&self, config: &mut Config) {
+        let project = &self.config;
+        if project.default_agent.is_some() {
+            config.default_agent.clone_from(&project.default_agent);
+        }
+        config.agents.extend(project.agents.clone());
+
+        let session = &project.session;
+        if session.prefix.is_some() {
+            config.session.prefix.clone_from(&session.prefix);
+        }
+        if session.setup.is_some() {
+            config.session.setup.clone_from(&session.setup);
+        }
+        if let Some(root) = &session.worktree_root {
+            let dir = self.path.parent().unwrap_or_else(|| Path::new("/"));
+            config.session.worktree_root = Some(dir.join(root));
+        }
+        config.session.env.extend(session.env.clone());
+    }
+}
+
+/// Reads the trusted project files and the content approved for each
+fn read_trusted(store: &Path) -> Result<BTreeMap<PathBuf, String>, ProjectError> {
+    match std::fs::read_to_string(store) {
+        Ok(text) => toml::from_str(&text).map_err(|e| ProjectError::Parse(store.to_path_buf(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(ProjectError::Io(store.to_path_buf(), e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project_dir(name: &str, contents: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ams-project-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(dir.join("src/deep")).unwrap();
+        std::fs::write(dir.join(PROJECT_FILE), contents).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_discover_walks_up() {
+        let dir = project_dir("discover", "default_agent = \"codex\"");
+        let project = Project::discover(&dir.join("src/deep")).unwrap().unwrap();
+        assert_eq!(project.path, dir.canonicalize().unwrap().join(PROJECT_FILE));
+        assert_eq!(project.config.default_agent.as_deref(), Some("codex"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_discover_rejects_unknown_keys() {
+        let dir = project_dir("unknown", "[tmux]\nsocket_name = \"x\"");
+        let err = Project::discover(&dir).unwrap_err();
+        assert!(matches!(err, ProjectError::Parse(_, _)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_apply_merges_over_user_config() {
+        let dir = project_dir(
+            "apply",
+            r#"
+            default_agent = "repo-agent"
+
+            [agents.repo-agent]
+            command = "claude"
+            args = ["--model", "opus"]
+
+            [session]
+            prefix = "web-"
+            worktree_root = "../worktrees"
+            env = { NODE_ENV = "development" }
+            "#,
+        );
+        let project = Project::discover(&dir).unwrap().unwrap();
+        let mut config = Config::parse(
+            r#"
+            default_agent = "claude-code"
+            [agents.claude-code]
+            command = "claude"
+            [session]
+            setup = "true"
+            env = { NODE_ENV = "production", EDITOR = "vim" }
+            "#,
+        )
+        .unwrap();
+
+        project.apply(&mut config);
+        assert_eq!(config.default_agent.as_deref(), Some("repo-agent"));
+        assert_eq!(config.agents.len(), 2);
+        assert_eq!(config.session.prefix.as_deref(), Some("web-"));
+        assert_eq!(config.session.setup.as_deref(), Some("true"));
+        assert_eq!(
+            config.session.env["NODE_ENV"],
+            EnvValue::from("development")
+        );
+        assert_eq!(config.session.env["EDITOR"], EnvValue::from("vim"));
+        assert_eq!(
+            config.session.worktree_root,
+            Some(dir.canonicalize().unwrap().join("../worktrees"))
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_commands() {
+        let dir = project_dir(
+            "commands",
+            r#"
+            [agents.a]
+            command = "claude"
+            args = ["-c"]
+            env = { KEY = { command = "pass show key" } }
+            [session]
+            setup = "make"
+            env = { PLAIN = "not a command" }
+            "#,
+        );
+        let project = Project::discover(&dir).unwrap().unwrap();
+        assert_eq!(
+            project.commands(),
+            vec!["make", "claude -c", "pass show key"]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let harmless = Project {
+            path: PathBuf::from("/repo/.ams.toml"),
+            config: ProjectConfig::default(),
+            contents: String::new(),
+        };
+        assert!(harmless.commands().is_empty());
+        assert!(harmless.is_trusted());
+    }
+
+    #[test]
+    fn test_env_needs_trust() {
+        let dir = project_dir(
+            "env",
+            "[session]\nenv = { PROMPT_COMMAND = \"touch /tmp/pwned\" }",
+        );
+        let project = Project::discover(&dir).unwrap().unwrap();
+        assert!(project.commands().is_empty());
+        assert_eq!(project.env_names(), vec!["PROMPT_COMMAND"]);
+        let store = dir.join("trusted.toml");
+        assert!(!project.is_trusted_in(&store));
+        // Even with a trust store, an unapproved file is not trusted.
+        let other = project_dir("env-other", "[session]\nsetup = \"make\"");
+        Project::discover(&other)
+            .unwrap()
+            .unwrap()
+            .trust_in(&store)
+            .unwrap();
+        assert!(!project.is_trusted_in(&store));
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_dir_all(&other).unwrap();
+    }
+
+    #[test]
+    fn test_trust_is_tied_to_content() {
+        let dir = project_dir("trust", "[session]\nsetup = \"make\"");
+        let store = dir.join("trusted.toml");
+        let project = Project::discover(&dir).unwrap().unwrap();
+        assert!(!project.is_trusted_in(&store));
+
+        project.trust_in(&store).unwrap();
+        assert!(project.is_trusted_in(&store));
+
+        std::fs::write(dir.join(PROJECT_FILE), "[session]\nsetup = \"rm -rf ~\"").unwrap();
+        let changed = Project::discover(&dir).unwrap().unwrap();
+        assert!(!changed.is_trusted_in(&store));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,163 @@
+//! Initial prompts, typed into a new session once its agent is ready.
+//!
+//! Keys sent right after a session is created reach the shell or an agent
+//! that is still starting up and get lost, so the prompt waits until the
+//! agent's input prompt shows on screen and is then pasted in one piece.
+
+use crate::config::AgentProfile;
+use crate::session::AgentState;
+use crate::tmux::{self, TmuxError};
+use crate::watch::{self, StateMatcher};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// How often the screen is checked while waiting for the agent
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Pause between pasting and pressing Enter, so the agent has taken in the
+/// paste before it is submitted
+const SUBMIT_DELAY: Duration = Duration::from_millis(200);
+
+/// Errors that can occur while delivering an initial prompt.
+#[derive(Error, Debug)]
+pub enum PromptError {
+    /// Failed to read a prompt file.
+    #[error("Failed to read prompt file {0}: {1}")]
+    Io(PathBuf, #[source] std::io::Error),
+
+    /// An agent profile has an invalid `ready_patterns` entry.
+    #[error("Invalid ready pattern: {0}")]
+    Pattern(#[from] regex::Error),
+
+    /// The session ended while waiting for the agent.
+    #[error("Session {0} ended before the agent was ready")]
+    Gone(String),
+
+    /// The agent's input prompt did not show in time.
+    #[error("Agent in {session} not ready after {}; the prompt was not sent", crate::cleanup::format_duration(*.timeout))]
+    NotReady {
+        /// Session name
+        session: String,
+        /// How long was waited
+        timeout: Duration,
+    },
+
+    /// A tmux command failed.
+    #[error(transparent)]
+    Tmux(#[from] TmuxError),
+}
+
+/// Reads a prompt file, dropping trailing line breaks
+pub fn read_file(path: &Path) -> Result<String, PromptError> {
+    let text = std::fs::read_to_string(path).map_err(|e| PromptError::Io(path.to_path_buf(), e))?;
+    Ok(text.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Returns the matcher that tells when an agent is ready: the profile's
+/// `ready_patterns` replace the configured input prompt patterns
+pub fn ready_matcher(
+    matcher: &StateMatcher,
+    profile: Option<&AgentProfile>,
+) -> Result<StateMatcher, PromptError> {
+    let patterns = profile.map_or(&[][..], |profile| &profile.ready_patterns);
+    Ok(matcher.with_waiting(patterns)?)
+}
+
+/// Waits for the agent in `session` to show its input prompt, then pastes
+/// `text` and presses Enter
+pub fn deliver(
+    session: &str,
+    text: &str,
+    matcher: &StateMatcher,
+    timeout: Duration,
+) -> Result<(), PromptError> {
+    wait_until_ready(session, matcher, timeout)?;
+    send(session, text)?;
+    Ok(())
+}
+
+/// Polls the session until its agent waits for input
+///
+/// The pane shows a shell until the agent has launched, so any other state
+/// only counts against the timeout.
+pub fn wait_until_ready(
+    session: &str,
+    matcher: &StateMatcher,
+    timeout: Duration,
+) -> Result<(), PromptError> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match watch::agent_state(session, matcher)? {
+            None => return Err(PromptError::Gone(session.to_string())),
+            Some(AgentState::WaitingForInput) => return Ok(()),
+            Some(_) if Instant::now() >= deadline => {
+                return Err(PromptError::NotReady {
+                    session: session.to_string(),
+                    timeout,
+                })
+            }
+            Some(_) => std::thread::sleep(POLL_INTERVAL),
+        }
+    }
+}
+
+/// Pastes `text` into the agent pane of `session` with bracketed paste and
+/// submits it, so a multi-line prompt arrives as one message
+///
+/// The agent pane is the one whose state was watched; the session's active
+/// pane may be a shell or dev server split next to it.
+pub fn send(session: &str, text: &str) -> Result<(), TmuxError> {
+    let target = tmux::get_session(session)?.agent_target();
+    tmux::paste_text(&target, text)?;
+    std::thread::sleep(SUBMIT_DELAY);
+    tmux::send_keys(&target, "", true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StatePatterns;
+
+    #[test]
+    fn test_read_file_trims_trailing_newlines() {
+        let path = std::env::temp_dir().join(format!("ams-prompt-{}.md", std::process::id()));
+        std::fs::write(&path, "Fix the bug.\n\nThen run the tests.\n\n").unwrap();
+        assert_eq!(
+            read_file(&path).unwrap(),
+            "Fix the bug.\n\nThen run the tests."
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            read_file(Path::new("/nonexistent/prompt.md")),
+            Err(PromptError::Io(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_ready_matcher_uses_profile_patterns() {
+        let matcher = StateMatcher::new(&StatePatterns::default()).unwrap();
+        let profile = AgentProfile {
+            ready_patterns: vec!["(".to_string()],
+            ..AgentProfile::default()
+        };
+        assert!(matches!(
+            ready_matcher(&matcher, Some(&profile)),
+            Err(PromptError::Pattern(_))
+        ));
+        assert!(ready_matcher(&matcher, None).is_ok());
+    }
+
+    #[test]
+    fn test_not_ready_message() {
+        let err = PromptError::NotReady {
+            session: "api".to_string(),
+            timeout: Duration::from_secs(120),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Agent in api not ready after 2m; the prompt was not sent"
+        );
+    }
+}
@@ -0,0 +1,736 @@
+//! Task queue: prompts waiting for an agent session, started within
+//! concurrency limits by `ams queue run`.
+
+use crate::config::{self, Config, ConfigError, QueueConfig};
+use crate::environment::{self, EnvError};
+use crate::metadata::Origin;
+use crate::prompt::{self, PromptError};
+use crate::session::AgentState;
+use crate::tmux::{self, Host, TmuxError};
+use crate::watch::{self, StateMatcher};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+/// How long to wait for another process to release the queue
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Age after which a lock file is assumed to belong to a crashed process
+const STALE_LOCK: Duration = Duration::from_secs(60);
+
+/// Errors that can occur while managing the task queue.
+#[derive(Error, Debug)]
+pub enum QueueError {
+    /// Failed to read or write the queue file.
+    #[error("Failed to access queue file {0}: {1}")]
+    Io(PathBuf, #[source] std::io::Error),
+
+    /// The queue file is corrupt.
+    #[error("Invalid queue file {0}: {1}")]
+    Parse(PathBuf, #[source] serde_json::Error),
+
+    /// Another process kept the queue locked.
+    #[error("Queue is locked by another process (remove {0} if none is running)")]
+    Locked(PathBuf),
+
+    /// No task has the given id.
+    #[error("Task not found: {0}")]
+    NotFound(u64),
+
+    /// The task's agent or host is not configured.
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    /// The session environment could not be built.
+    #[error(transparent)]
+    Env(#[from] EnvError),
+
+    /// The agent's ready patterns are invalid.
+    #[error(transparent)]
+    Prompt(#[from] PromptError),
+
+    /// A tmux command failed.
+    #[error(transparent)]
+    Tmux(#[from] TmuxError),
+}
+
+/// Where a task is in its life
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    /// Waiting for a free slot
+    Queued,
+    /// Session created, waiting for the agent's prompt
+    Starting,
+    /// Prompt sent, agent at work
+    Running,
+    /// The agent finished the task
+    Completed,
+    /// The session could not start, or the agent errored
+    Failed,
+}
+
+impl TaskStatus {
+    /// Whether the task occupies a slot
+    #[must_use]
+    pub const fn is_active(self) -> bool {
+        matches!(self, Self::Starting | Self::Running)
+    }
+}
+
+impl std::fmt::Display for TaskStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Queued => write!(f, "queued"),
+            Self::Starting => write!(f, "starting"),
+            Self::Running => write!(f, "running"),
+            Self::Completed => write!(f, "completed"),
+            Self::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// A prompt for an agent, run in its own session
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Task {
+    /// Queue-unique id
+    pub id: u64,
+    /// Agent profile to launch
+    pub agent: String,
+    /// Working directory of the session
+    pub directory: PathBuf,
+    /// Initial prompt, sent once the agent is ready
+    pub prompt: String,
+    /// Host from `[hosts]`, or `None` for this machine
+    #[serde(default)]
+    pub host: Option<String>,
+    /// Current status
+    pub status: TaskStatus,
+    /// Session running the task, once started
+    #[serde(default)]
+    pub session: Option<String>,
+    /// When the task was queued
+    pub created_at: DateTime<Utc>,
+    /// When its session was created
+    #[serde(default)]
+    pub started_at: Option<DateTime<Utc>>,
+    /// When it completed or failed
+    #[serde(default)]
+    pub finished_at: Option<DateTime<Utc>>,
+    /// The agent has been seen in a state other than waiting for input
+    /// since the prompt was sent
+    #[serde(default)]
+    pub worked: bool,
+    /// Why the task failed
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl Task {
+    fn host_label(&self) -> &str {
+        self.host.as_deref().unwrap_or(Host::LOCAL)
+    }
+
+    fn finish(&mut self, status: TaskStatus, error: Option<String>, now: DateTime<Utc>) {
+        self.status = status;
+        self.error = error;
+        self.finished_at = Some(now);
+    }
+}
+
+/// The persisted list of tasks
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Queue {
+    /// Id for the next task
+    #[serde(default)]
+    pub next_id: u64,
+    /// Tasks in the order they were queued
+    #[serde(default)]
+    pub tasks: Vec<Task>,
+}
+
+impl Queue {
+    /// Loads the queue; a missing file is an empty queue
+    pub fn load(path: &Path) -> Result<Self, QueueError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => {
+                serde_json::from_str(&text).map_err(|e| QueueError::Parse(path.to_path_buf(), e))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(QueueError::Io(path.to_path_buf(), e)),
+        }
+    }
+
+    /// Saves the queue, replacing the file atomically
+    pub fn save(&self, path: &Path) -> Result<(), QueueError> {
+        let io = |e| QueueError::Io(path.to_path_buf(), e);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(io)?;
+        }
+        let text = serde_json::to_string_pretty(self)
+            .map_err(|e| QueueError::Parse(path.to_path_buf(), e))?;
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, text).map_err(io)?;
+        std::fs::rename(&temp, path).map_err(io)
+    }
+
+    /// Loads the queue, changes it and saves it, holding a lock so that
+    /// concurrent `ams queue` commands do not lose each other's changes
+    pub fn update<T>(path: &Path, change: impl FnOnce(&mut Self) -> T) -> Result<T, QueueError> {
+        let _lock = Lock::acquire(&path.with_extension("lock"))?;
+        let mut queue = Self::load(path)?;
+        let result = change(&mut queue);
+        queue.save(path)?;
+        Ok(result)
+    }
+
+    /// Queues a task, returning its id
+    pub fn add(
+        &mut self,
+        agent: &str,
+        directory: &Path,
+        prompt: &str,
+        host: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> u64 {
+        self.next_id += 1;
+        self.tasks.push(Task {
+            id: self.next_id,
+            agent: agent.to_string(),
+            directory: directory.to_path_buf(),
+            prompt: prompt.to_string(),
+            host: host.map(String::from),
+            status: TaskStatus::Queued,
+            session: None,
+            created_at: now,
+            started_at: None,
+            finished_at: None,
+            worked: false,
+            error: None,
+        });
+        self.next_id
+    }
+
+    /// Removes a task; its session, if any, keeps running
+    pub fn remove(&mut self, id: u64) -> Result<Task, QueueError> {
+        let index = self
+            .tasks
+            .iter()
+            .position(|task| task.id == id)
+            .ok_or(QueueError::NotFound(id))?;
+        Ok(self.tasks.remove(index))
+    }
+
+    /// Takes over the tasks that changed between `before` and `after`,
+    /// skipping those removed in the meantime; tasks queued in the meantime
+    /// are kept
+    fn merge(&mut self, before: &Self, after: &Self) {
+        let changed = after
+            .tasks
+            .iter()
+            .zip(&before.tasks)
+            .filter(|(task, old)| task != old);
+        for (task, _) in changed {
+            if let Some(current) = self.tasks.iter_mut().find(|current| current.id == task.id) {
+                current.clone_from(task);
+            }
+        }
+    }
+
+    /// Returns the ids of queued tasks that fit within the limits, oldest
+    /// first
+    #[must_use]
+    pub fn startable(&self, limits: &QueueConfig) -> Vec<u64> {
+        let mut per_agent: HashMap<&str, usize> = HashMap::new();
+        let mut per_host: HashMap<&str, usize> = HashMap::new();
+        for task in self.tasks.iter().filter(|task| task.status.is_active()) {
+            *per_agent.entry(&task.agent).or_default() += 1;
+            *per_host.entry(task.host_label()).or_default() += 1;
+        }
+
+        let mut startable = Vec::new();
+        for task in self
+            .tasks
+            .iter()
+            .filter(|task| task.status == TaskStatus::Queued)
+        {
+            let agent = per_agent.entry(&task.agent).or_default();
+            let agent_limit = limits
+                .agents
+                .get(&task.agent)
+                .copied()
+                .unwrap_or(limits.max_per_agent);
+            let host = per_host.entry(task.host_label()).or_default();
+            if *agent < agent_limit && limits.max_per_host.map_or(true, |limit| *host < limit) {
+                *agent += 1;
+                *host += 1;
+                startable.push(task.id);
+            }
+        }
+        startable
+    }
+}
+
+/// What a session showed when its task was checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Observation {
+    /// The session no longer exists
+    Gone,
+    /// The agent is in this state
+    State(AgentState),
+}
+
+/// Moves a started task along after observing its session, returning a
+/// description of the change; `send_prompt` types the prompt in
+fn advance(
+    task: &mut Task,
+    observation: Observation,
+    ready_timeout: Duration,
+    now: DateTime<Utc>,
+    send_prompt: impl FnOnce(&Task) -> Result<(), TmuxError>,
+) -> Option<String> {
+    use AgentState::{Errored, Finished, WaitingForInput};
+
+    match (task.status, observation) {
+        (TaskStatus::Starting, Observation::State(WaitingForInput)) => {
+            if let Err(e) = send_prompt(task) {
+                task.finish(TaskStatus::Failed, Some(e.to_string()), now);
+                return Some(format!("failed: {e}"));
+            }
+            task.status = TaskStatus::Running;
+            Some("sent prompt".to_string())
+        }
+        (TaskStatus::Starting, Observation::Gone) => {
+            let error = "session ended before the agent was ready".to_string();
+            task.finish(TaskStatus::Failed, Some(error.clone()), now);
+            Some(format!("failed: {error}"))
+        }
+        // The shell shows until the agent has launched, so only the
+        // timeout tells a broken agent command apart
+        (TaskStatus::Starting, _) => {
+            let waited = task
+                .started_at
+                .and_then(|started| (now - started).to_std().ok())
+                .unwrap_or_default();
+            (waited >= ready_timeout).then(|| {
+                let error = format!(
+                    "agent not ready after {}",
+                    crate::cleanup::format_duration(ready_timeout)
+                );
+                task.finish(TaskStatus::Failed, Some(error.clone()), now);
+                format!("failed: {error}")
+            })
+        }
+        (TaskStatus::Running, Observation::Gone) => {
+            let error = "session ended before the agent finished".to_string();
+            task.finish(TaskStatus::Failed, Some(error.clone()), now);
+            Some(format!("failed: {error}"))
+        }
+        (TaskStatus::Running, Observation::State(Errored)) => {
+            task.finish(TaskStatus::Failed, Some("agent errored".to_string()), now);
+            Some("failed: agent errored".to_string())
+        }
+        // Still showing the prompt it was just given
+        (TaskStatus::Running, Observation::State(WaitingForInput)) if !task.worked => None,
+        (TaskStatus::Running, Observation::State(WaitingForInput | Finished)) => {
+            task.finish(TaskStatus::Completed, None, now);
+            Some("completed".to_string())
+        }
+        (TaskStatus::Running, Observation::State(_)) => {
+            task.worked = true;
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Checks started tasks and starts queued ones, returning one line per
+/// change
+///
+/// The tmux work runs on a copy of the queue: the queue file is locked only
+/// to save the changes, so `ams queue add` need not wait for it. Runners
+/// take turns through a lock of their own, refreshed as they go.
+pub fn tick(
+    path: &Path,
+    config: &Config,
+    matcher: &StateMatcher,
+    now: DateTime<Utc>,
+) -> Result<Vec<String>, QueueError> {
+    let limits = &config.queue;
+    let runner = Lock::acquire(&path.with_extension("run.lock"))?;
+    let before = Queue::load(path)?;
+    let mut queue = before.clone();
+    let mut events = Vec::new();
+
+    for task in queue
+        .tasks
+        .iter_mut()
+        .filter(|task| task.status.is_active())
+    {
+        runner.refresh();
+        let host = task.host.clone();
+        let result = on_host(config, host.as_deref(), || {
+            let matcher = prompt::ready_matcher(matcher, config.agents.get(&task.agent))?;
+            let observation = watch::agent_state(session_of(task), &matcher)?
+                .map_or(Observation::Gone, Observation::State);
+            Ok(advance(
+                task,
+                observation,
+                limits.ready_timeout,
+                now,
+                |task| prompt::send(session_of(task), &task.prompt),
+            ))
+        });
+        match result {
+            Ok(Some(event)) => events.push(format!("task {}: {event}", task.id)),
+            Ok(None) => {}
+            Err(e) => events.push(format!("task {}: check failed: {e}", task.id)),
+        }
+    }
+
+    for id in queue.startable(limits) {
+        let Some(task) = queue.tasks.iter_mut().find(|task| task.id == id) else {
+            continue;
+        };
+        runner.refresh();
+        match on_host(config, task.host.as_deref(), || start(task, config)) {
+            Ok(session) => {
+                events.push(format!("task {}: started in {session}", task.id));
+                task.session = Some(session);
+                task.status = TaskStatus::Starting;
+                task.started_at = Some(now);
+            }
+            Err(e) => {
+                task.finish(TaskStatus::Failed, Some(e.to_string()), now);
+                events.push(format!("task {}: failed: {e}", task.id));
+            }
+        }
+    }
+
+    Queue::update(path, |current| current.merge(&before, &queue))?;
+    Ok(events)
+}
+
+/// Runs tmux commands for a task on its host
+fn on_host<T>(
+    config: &Config,
+    host: Option<&str>,
+    run: impl FnOnce() -> Result<T, QueueError>,
+) -> Result<T, QueueError> {
+    let previous = tmux::host();
+    tmux::set_host(host.map(|name| config.host(name)).transpose()?.flatten());
+    let result = run();
+    tmux::set_host(previous);
+    result
+}
+
+fn session_of(task: &Task) -> &str {
+    task.session.as_deref().unwrap_or_default()
+}
+
+/// Creates a task's session and launches its agent, returning the session
+/// name
+fn start(task: &Task, config: &Config) -> Result<String, QueueError> {
+    let profile = config.agent(&task.agent)?;
+    let name = config.session.session_name(&format!("task-{}", task.id));
+    let mut env = config.session.environment()?;
+    env.extend(environment::resolve(&profile.env)?);
+
+    tmux::create_session_with_env(&name, Some(&task.directory.to_string_lossy()), &env)?;
+    // A session left behind by a failed start would run untracked, outside
+    // the concurrency limits
+    Origin {
+        task: Some(task.id),
+        ..Origin::new("ams queue", Some((&task.agent, profile)))
+    }
+    .record(&name)
+    .and_then(|()| tmux::send_keys(&name, &profile.command_line(), true))
+    .map_err(|e| {
+        let _ = tmux::kill_session(&name);
+        e
+    })?;
+    Ok(name)
+}
+
+/// Returns the path of the queue file
+#[must_use]
+pub fn queue_path() -> PathBuf {
+    config::state_dir().join("queue.json")
+}
+
+/// An exclusive lock held by creating a file, removed on drop
+struct Lock(PathBuf);
+
+impl Lock {
+    fn acquire(path: &Path) -> Result<Self, QueueError> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| QueueError::Io(dir.to_path_buf(), e))?;
+        }
+        let deadline = std::time::Instant::now() + LOCK_TIMEOUT;
+        loop {
+            match std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+            {
+                Ok(_) => return Ok(Self(path.to_path_buf())),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(QueueError::Io(path.to_path_buf(), e)),
+            }
+
+            let stale = std::fs::metadata(path)
+                .and_then(|meta| meta.modified())
+                .ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                .is_some_and(|age| age > STALE_LOCK);
+            if stale {
+                let _ = std::fs::remove_file(path);
+            } else if std::time::Instant::now() >= deadline {
+                return Err(QueueError::Locked(path.to_path_buf()));
+            } else {
+                std::thread::sleep(Duration::from_millis(50));
+            }
+        }
+    }
+}
+
+impl Lock {
+    /// Touches the lock file, so it is not taken for stale while its holder
+    /// is still at work
+    fn refresh(&self) {
+        let _ = std::fs::write(&self.0, b"");
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap()
+    }
+
+    fn queue_of(tasks: &[(&str, Option<&str>, TaskStatus)]) -> Queue {
+        let mut queue = Queue::default();
+        for (agent, host, status) in tasks {
+            queue.add(agent, Path::new("/repo"), "fix it", *host, now());
+            queue.tasks.last_mut().unwrap().status = *status;
+        }
+        queue
+    }
+
+    fn started(status: TaskStatus) -> Task {
+        let mut queue = queue_of(&[("codex", None, status)]);
+        let mut task = queue.tasks.remove(0);
+        task.session = Some("task-1".to_string());
+        task.started_at = Some(now());
+        task
+    }
+
+    #[test]
+    fn test_add_and_remove() {
+        let mut queue = Queue::default();
+        assert_eq!(queue.add("codex", Path::new("/a"), "one", None, now()), 1);
+        assert_eq!(
+            queue.add("codex", Path::new("/b"), "two", Some("devbox"), now()),
+            2
+        );
+        assert_eq!(queue.remove(1).unwrap().prompt, "one");
+        assert!(matches!(queue.remove(1), Err(QueueError::NotFound(1))));
+        assert_eq!(queue.add("codex", Path::new("/c"), "three", None, now()), 3);
+    }
+
+    #[test]
+    fn test_startable_respects_agent_limits() {
+        let queue = queue_of(&[
+            ("codex", None, TaskStatus::Running),
+            ("codex", None, TaskStatus::Queued),
+            ("codex", None, TaskStatus::Queued),
+            ("claude", None, TaskStatus::Queued),
+            ("claude", None, TaskStatus::Completed),
+            ("aider", None, TaskStatus::Queued),
+        ]);
+        let limits = Config::parse("[queue]\nmax_per_agent = 2\nagents = { aider = 0 }")
+            .unwrap()
+            .queue;
+        assert_eq!(queue.startable(&limits), vec![2, 4]);
+    }
+
+    #[test]
+    fn test_startable_respects_host_limits() {
+        let queue = queue_of(&[
+            ("codex", Some("devbox"), TaskStatus::Starting),
+            ("claude", Some("devbox"), TaskStatus::Queued),
+            ("claude", None, TaskStatus::Queued),
+            ("aider", None, TaskStatus::Queued),
+        ]);
+        let limits = Config::parse("[queue]\nmax_per_host = 1").unwrap().queue;
+        assert_eq!(queue.startable(&limits), vec![3]);
+    }
+
+    #[test]
+    fn test_advance_sends_prompt_when_ready() {
+        let mut task = started(TaskStatus::Starting);
+        let timeout = Duration::from_secs(120);
+
+        for state in [AgentState::Finished, AgentState::Working] {
+            let event = advance(&mut task, Observation::State(state), timeout, now(), |_| {
+                panic!("not ready yet")
+            });
+            assert_eq!((event, task.status), (None, TaskStatus::Starting));
+        }
+
+        let mut sent = String::new();
+        let event = advance(
+            &mut task,
+            Observation::State(AgentState::WaitingForInput),
+            timeout,
+            now(),
+            |task| {
+                sent.clone_from(&task.prompt);
+                Ok(())
+            },
+        );
+        assert_eq!(event.as_deref(), Some("sent prompt"));
+        assert_eq!(task.status, TaskStatus::Running);
+        assert_eq!(sent, "fix it");
+    }
+
+    #[test]
+    fn test_advance_times_out_waiting_for_ready() {
+        let mut task = started(TaskStatus::Starting);
+        let later = now() + chrono::Duration::minutes(3);
+        let event = advance(
+            &mut task,
+            Observation::State(AgentState::Working),
+            Duration::from_secs(120),
+            later,
+            |_| Ok(()),
+        );
+        assert_eq!(event.as_deref(), Some("failed: agent not ready after 2m"));
+        assert_eq!(task.status, TaskStatus::Failed);
+        assert_eq!(task.finished_at, Some(later));
+    }
+
+    #[test]
+    fn test_advance_completes_after_work() {
+        let mut task = started(TaskStatus::Running);
+        let timeout = Duration::from_secs(120);
+        let step = |task: &mut Task, state| {
+            advance(task, Observation::State(state), timeout, now(), |_| Ok(()))
+        };
+
+        // Still showing the prompt it was just given
+        assert_eq!(step(&mut task, AgentState::WaitingForInput), None);
+        assert_eq!(step(&mut task, AgentState::Working), None);
+        assert!(task.worked);
+        assert_eq!(
+            step(&mut task, AgentState::WaitingForInput).as_deref(),
+            Some("completed")
+        );
+        assert_eq!(task.status, TaskStatus::Completed);
+
+        // Any state other than waiting counts as having taken the prompt
+        let mut task = started(TaskStatus::Running);
+        assert_eq!(step(&mut task, AgentState::NeedsApproval), None);
+        assert_eq!(
+            step(&mut task, AgentState::WaitingForInput).as_deref(),
+            Some("completed")
+        );
+
+        let mut task = started(TaskStatus::Running);
+        assert_eq!(
+            step(&mut task, AgentState::Finished).as_deref(),
+            Some("completed")
+        );
+    }
+
+    #[test]
+    fn test_advance_session_gone_or_errored() {
+        let mut task = started(TaskStatus::Running);
+        let event = advance(&mut task, Observation::Gone, Duration::ZERO, now(), |_| {
+            Ok(())
+        });
+        assert_eq!(
+            event.as_deref(),
+            Some("failed: session ended before the agent finished")
+        );
+        assert_eq!(task.status, TaskStatus::Failed);
+
+        let mut task = started(TaskStatus::Running);
+        advance(
+            &mut task,
+            Observation::State(AgentState::Errored),
+            Duration::ZERO,
+            now(),
+            |_| Ok(()),
+        );
+        assert_eq!(task.status, TaskStatus::Failed);
+        assert_eq!(task.error.as_deref(), Some("agent errored"));
+    }
+
+    #[test]
+    fn test_update_persists() {
+        let dir = std::env::temp_dir().join(format!("ams-queue-{}", std::process::id()));
+        let path = dir.join("queue.json");
+        let id = Queue::update(&path, |queue| {
+            queue.add("codex", Path::new("/repo"), "fix issue 123", None, now())
+        })
+        .unwrap();
+        assert_eq!(id, 1);
+        assert!(!path.with_extension("lock").exists());
+
+        let queue = Queue::load(&path).unwrap();
+        assert_eq!(queue.tasks[0].prompt, "fix issue 123");
+        assert_eq!(queue.tasks[0].status, TaskStatus::Queued);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_merge_keeps_concurrent_changes() {
+        let before = queue_of(&[
+            ("codex", None, TaskStatus::Queued),
+            ("codex", None, TaskStatus::Queued),
+        ]);
+        let mut after = before.clone();
+        after.tasks[0].status = TaskStatus::Starting;
+        after.tasks[1].status = TaskStatus::Starting;
+
+        // Meanwhile task 1 was removed and task 3 added
+        let mut current = before.clone();
+        current.remove(1).unwrap();
+        current.add("claude", Path::new("/repo"), "new", None, now());
+
+        current.merge(&before, &after);
+        let statuses: Vec<_> = current
+            .tasks
+            .iter()
+            .map(|task| (task.id, task.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![(2, TaskStatus::Starting), (3, TaskStatus::Queued)]
+        );
+    }
+
+    #[test]
+    fn test_lock_is_exclusive() {
+        let dir = std::env::temp_dir().join(format!("ams-queue-lock-{}", std::process::id()));
+        let path = dir.join("queue.lock");
+        let lock = Lock::acquire(&path).unwrap();
+        assert!(path.exists());
+        drop(lock);
+        assert!(!path.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,362 @@
+//! asciicast v2 recording of tmux panes.
+//!
+//! Recording uses `pipe-pane`: tmux feeds everything the pane prints to a
+//! sink process (`ams record-sink`), which timestamps each chunk and appends
+//! it to the cast file. No client has to be attached to the session.
+
+use crate::tmux::{display_message, pipe_pane, TmuxError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::Instant;
+use thiserror::Error;
+
+/// Errors that can occur while recording or loading a cast file.
+#[derive(Error, Debug)]
+pub enum RecordError {
+    /// A tmux command failed.
+    #[error(transparent)]
+    Tmux(#[from] TmuxError),
+
+    /// Reading or writing the cast file failed.
+    #[error("Failed to access cast file: {0}")]
+    Io(#[from] io::Error),
+
+    /// The cast file is not valid asciicast v2.
+    #[error("Invalid cast file: {0}")]
+    InvalidCast(String),
+
+    /// The pane is already piped to another command.
+    #[error("Pane is already being recorded: {0}")]
+    AlreadyRecording(String),
+}
+
+impl From<serde_json::Error> for RecordError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::InvalidCast(e.to_string())
+    }
+}
+
+/// The first line of an asciicast v2 file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CastHeader {
+    /// Format version, always 2
+    pub version: u8,
+    /// Terminal width in columns
+    pub width: u16,
+    /// Terminal height in rows
+    pub height: u16,
+    /// Unix timestamp of the start of the recording
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<i64>,
+    /// Maximum pause between events during playback, in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_time_limit: Option<f64>,
+    /// Title of the recording
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Captured environment variables
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+}
+
+/// A single timestamped output event
+#[derive(Debug, Clone, PartialEq)]
+pub struct CastEvent {
+    /// Seconds since the start of the recording
+    pub time: f64,
+    /// Event type code, `o` for output
+    pub kind: String,
+    /// Event payload
+    pub data: String,
+}
+
+/// A parsed asciicast v2 recording
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    /// Recording header
+    pub header: CastHeader,
+    /// Events in chronological order
+    pub events: Vec<CastEvent>,
+}
+
+impl Recording {
+    /// Loads a recording from a cast file
+    pub fn load(path: &Path) -> Result<Self, RecordError> {
+        Self::parse(BufReader::new(File::open(path)?))
+    }
+
+    /// Parses a recording from asciicast v2 lines
+    pub fn parse(reader: impl BufRead) -> Result<Self, RecordError> {
+        let mut lines = reader.lines();
+        let header_line = lines
+            .next()
+            .ok_or_else(|| RecordError::InvalidCast("missing header".to_string()))??;
+        let header: CastHeader = serde_json::from_str(&header_line)?;
+        if header.version != 2 {
+            return Err(RecordError::InvalidCast(format!(
+                "unsupported version {}",
+                header.version
+            )));
+        }
+
+        let mut events = Vec::new();
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (time, kind, data): (f64, String, String) = serde_json::from_str(&line)?;
+            events.push(CastEvent { time, kind, data });
+        }
+
+        Ok(Self { header, events })
+    }
+
+    /// Returns the output events only
+    pub fn output(&self) -> impl Iterator<Item = &CastEvent> {
+        self.events.iter().filter(|event| event.kind == "o")
+    }
+}
+
+/// Starts recording a pane into a cast file
+///
+/// The header is written immediately; `sink_command` is the shell command
+/// tmux runs to receive the pane output, normally `ams record-sink <path>`.
+pub fn start_recording(target: &str, path: &Path, sink_command: &str) -> Result<(), RecordError> {
+    if display_message(target, "#{pane_pipe}")? == "1" {
+        return Err(RecordError::AlreadyRecording(target.to_string()));
+    }
+
+    let size = display_message(target, "#{window_width}\t#{window_height}")?;
+    let (width, height) = parse_size(&size)?;
+
+    let header = CastHeader {
+        version: 2,
+        width,
+        height,
+        timestamp: Some(chrono::Utc::now().timestamp()),
+        idle_time_limit: None,
+        title: Some(target.to_string()),
+        env: ["SHELL", "TERM"]
+            .into_iter()
+            .filter_map(|key| Some((key.to_string(), std::env::var(key).ok()?)))
+            .collect(),
+    };
+
+    let mut file = File::create(path)?;
+    serde_json::to_writer(&mut file, &header)?;
+    file.write_all(b"\n")?;
+
+    pipe_pane(target, Some(sink_command))?;
+    Ok(())
+}
+
+/// Stops recording a pane
+pub fn stop_recording(target: &str) -> Result<(), RecordError> {
+    pipe_pane(target, None)?;
+    Ok(())
+}
+
+/// Appends output events read from `input` to the cast file at `path`
+///
+/// Runs until `input` reaches end of file, which happens when the recording
+/// is stopped or the pane is closed.
+pub fn run_sink(input: impl Read, path: &Path) -> Result<(), RecordError> {
+    let file = OpenOptions::new().append(true).open(path)?;
+    record_stream(input, BufWriter::new(file), Instant::now())
+}
+
+/// Timestamps chunks from `input` relative to `start` and writes them as
+/// output events
+///
+/// Multi-byte UTF-8 characters split across reads are held back until
+/// complete; invalid bytes are replaced.
+pub fn record_stream(
+    mut input: impl Read,
+    mut output: impl Write,
+    start: Instant,
+) -> Result<(), RecordError> {
+    let mut buf = [0u8; 4096];
+    let mut pending: Vec<u8> = Vec::new();
+
+    loop {
+        let n = input.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        pending.extend_from_slice(&buf[..n]);
+
+        let complete = complete_utf8_len(&pending);
+        if complete == 0 {
+            continue;
+        }
+        let data = String::from_utf8_lossy(&pending[..complete]).into_owned();
+        pending.drain(..complete);
+
+        write_event(&mut output, start.elapsed().as_secs_f64(), &data)?;
+    }
+
+    if !pending.is_empty() {
+        let data = String::from_utf8_lossy(&pending).into_owned();
+        write_event(&mut output, start.elapsed().as_secs_f64(), &data)?;
+    }
+
+    output.flush()?;
+    Ok(())
+}
+
+fn write_event(output: &mut impl Write, time: f64, data: &str) -> Result<(), RecordError> {
+    // Round to microseconds like asciinema does
+    let time = (time * 1_000_000.0).round() / 1_000_000.0;
+    serde_json::to_writer(&mut *output, &(time, "o", data))?;
+    output.write_all(b"\n")?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Returns how many leading bytes can be decoded without cutting a character
+const fn complete_utf8_len(bytes: &[u8]) -> usize {
+    match std::str::from_utf8(bytes) {
+        // An incomplete sequence at the end has no error length
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        _ => bytes.len(),
+    }
+}
+
+fn parse_size(output: &str) -> Result<(u16, u16), RecordError> {
+    let invalid = || TmuxError::ParseError(format!("Invalid window size: {output}"));
+    let (width, height) = output.split_once('\t').ok_or_else(invalid)?;
+    Ok((
+        width.parse().map_err(|_| invalid())?,
+        height.parse().map_err(|_| invalid())?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAST: &str = r#"{"version":2,"width":80,"height":24,"timestamp":1704067200}
+[0.5,"o","hello "]
+[1.25,"i","x"]
+
+[2.0,"o","\u001b[31mworld\u001b[0m"]
+"#;
+
+    #[test]
+    fn test_parse_recording() {
+        let recording = Recording::parse(CAST.as_bytes()).unwrap();
+        assert_eq!(recording.header.width, 80);
+        assert_eq!(recording.header.height, 24);
+        assert_eq!(recording.header.timestamp, Some(1_704_067_200));
+        assert_eq!(recording.events.len(), 3);
+        assert_eq!(recording.events[1].kind, "i");
+
+        let output: Vec<&str> = recording.output().map(|e| e.data.as_str()).collect();
+        assert_eq!(output, vec!["hello ", "\x1b[31mworld\x1b[0m"]);
+    }
+
+    #[test]
+    fn test_parse_recording_errors() {
+        assert!(matches!(
+            Recording::parse(&b""[..]),
+            Err(RecordError::InvalidCast(_))
+        ));
+        assert!(matches!(
+            Recording::parse(r#"{"version":1,"width":80,"height":24}"#.as_bytes()),
+            Err(RecordError::InvalidCast(_))
+        ));
+        assert!(matches!(
+            Recording::parse("{\"version\":2,\"width\":80,\"height\":24}\n[1]".as_bytes()),
+            Err(RecordError::InvalidCast(_))
+        ));
+    }
+
+    #[test]
+    fn test_record_stream_writes_events() {
+        let mut out = Vec::new();
+        record_stream(&b"hi\r\n"[..], &mut out, Instant::now()).unwrap();
+        let line = String::from_utf8(out).unwrap();
+        let (time, kind, data): (f64, String, String) =
+            serde_json::from_str(line.trim_end()).unwrap();
+        assert!(time >= 0.0);
+        assert_eq!(kind, "o");
+        assert_eq!(data, "hi\r\n");
+    }
+
+    #[test]
+    fn test_record_stream_keeps_split_characters_whole() {
+        // Reads one byte at a time, splitting the two-byte "é"
+        struct Trickle<'a>(&'a [u8]);
+        impl Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                match self.0.split_first() {
+                    Some((byte, rest)) => {
+                        buf[0] = *byte;
+                        self.0 = rest;
+                        Ok(1)
+                    }
+                    None => Ok(0),
+                }
+            }
+        }
+
+        let mut out = Vec::new();
+        record_stream(Trickle("é".as_bytes()), &mut out, Instant::now()).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.lines().count(), 1);
+        assert!(text.contains("\"é\""));
+    }
+
+    #[test]
+    fn test_record_stream_flushes_truncated_character() {
+        let mut out = Vec::new();
+        record_stream(&[b'a', 0xc3][..], &mut out, Instant::now()).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.lines().count(), 2);
+        assert!(text.lines().nth(1).unwrap().contains('\u{fffd}'));
+    }
+
+    #[test]
+    fn test_complete_utf8_len() {
+        assert_eq!(complete_utf8_len(b"abc"), 3);
+        assert_eq!(complete_utf8_len(&[b'a', 0xc3]), 1);
+        assert_eq!(complete_utf8_len(&[b'a', 0xff, b'b']), 3);
+    }
+
+    #[test]
+    fn test_header_roundtrip() {
+        let header = CastHeader {
+            version: 2,
+            width: 120,
+            height: 40,
+            timestamp: None,
+            idle_time_limit: Some(2.0),
+            title: Some("agent".to_string()),
+            env: HashMap::new(),
+        };
+        let json = serde_json::to_string(&header).unwrap();
+        assert!(!json.contains("timestamp"));
+        assert!(!json.contains("env"));
+        assert_eq!(serde_json::from_str::<CastHeader>(&json).unwrap(), header);
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("80\t24").unwrap(), (80, 24));
+        assert!(parse_size("80").is_err());
+        assert!(parse_size("x\t24").is_err());
+        assert!(parse_size("80\ty").is_err());
+    }
+
+    #[test]
+    fn test_record_error_display() {
+        let err = RecordError::AlreadyRecording("agent".to_string());
+        assert_eq!(err.to_string(), "Pane is already being recorded: agent");
+        let err = RecordError::from(TmuxError::SessionNotFound("x".to_string()));
+        assert_eq!(err.to_string(), "Session not found: x");
+    }
+}
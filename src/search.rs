@@ -0,0 +1,128 @@
+//! Scrollback search across all tmux panes.
+
+use crate::session::PaneTarget;
+use crate::tmux::{capture_pane, list_pane_targets, TmuxError};
+use regex::Regex;
+
+/// A single line of pane scrollback matching a search pattern
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchMatch {
+    /// Pane the match was found in
+    pub pane: PaneTarget,
+    /// 1-based line number within the captured scrollback
+    pub line_number: usize,
+    /// The matching line
+    pub line: String,
+    /// Lines preceding the match
+    pub before: Vec<String>,
+    /// Lines following the match
+    pub after: Vec<String>,
+}
+
+/// Searches the scrollback of every pane of every session
+///
+/// Panes that disappear between listing and capturing are skipped.
+pub fn search_all(pattern: &Regex, context: usize) -> Result<Vec<SearchMatch>, TmuxError> {
+    let mut matches = Vec::new();
+
+    for pane in list_pane_targets()? {
+        let text = match capture_pane(&pane.to_string()) {
+            Ok(text) => text,
+            Err(TmuxError::SessionNotFound(_)) => continue,
+            Err(e) => return Err(e),
+        };
+        matches.extend(search_text(&pane, &text, pattern, context));
+    }
+
+    Ok(matches)
+}
+
+/// Searches captured pane text for lines matching a pattern
+#[must_use]
+pub fn search_text(
+    pane: &PaneTarget,
+    text: &str,
+    pattern: &Regex,
+    context: usize,
+) -> Vec<SearchMatch> {
+    let mut lines: Vec<&str> = text.lines().collect();
+    // capture-pane pads the visible area with blank lines
+    while lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
+
+    lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| pattern.is_match(line))
+        .map(|(i, line)| {
+            let start = i.saturating_sub(context);
+            let end = (i + 1 + context).min(lines.len());
+            SearchMatch {
+                pane: pane.clone(),
+                line_number: i + 1,
+                line: (*line).to_string(),
+                before: lines[start..i].iter().map(ToString::to_string).collect(),
+                after: lines[i + 1..end].iter().map(ToString::to_string).collect(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pane() -> PaneTarget {
+        PaneTarget {
+            session: "agent".to_string(),
+            window_index: 0,
+            pane_index: 1,
+        }
+    }
+
+    #[test]
+    fn test_search_text_no_matches() {
+        let pattern = Regex::new("err(or)?").unwrap();
+        assert!(search_text(&pane(), "all good\nfine", &pattern, 2).is_empty());
+    }
+
+    #[test]
+    fn test_search_text_line_numbers() {
+        let pattern = Regex::new("err(or)?").unwrap();
+        let matches = search_text(&pane(), "ok\nerror: one\nok\nerror: two", &pattern, 0);
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].line_number, 2);
+        assert_eq!(matches[0].line, "error: one");
+        assert_eq!(matches[1].line_number, 4);
+        assert_eq!(matches[1].pane, pane());
+        assert!(matches[0].before.is_empty());
+        assert!(matches[0].after.is_empty());
+    }
+
+    #[test]
+    fn test_search_text_context() {
+        let pattern = Regex::new("^[c]+$").unwrap();
+        let matches = search_text(&pane(), "a\nb\nc\nd\ne", &pattern, 1);
+        assert_eq!(matches[0].before, vec!["b"]);
+        assert_eq!(matches[0].after, vec!["d"]);
+    }
+
+    #[test]
+    fn test_search_text_context_clamped() {
+        let pattern = Regex::new("a|e").unwrap();
+        let matches = search_text(&pane(), "a\nb\nc\nd\ne", &pattern, 10);
+        assert!(matches[0].before.is_empty());
+        assert_eq!(matches[0].after.len(), 4);
+        assert_eq!(matches[1].before.len(), 4);
+        assert!(matches[1].after.is_empty());
+    }
+
+    #[test]
+    fn test_search_text_ignores_trailing_blank_lines() {
+        let pattern = Regex::new(r"^\s*$").unwrap();
+        let matches = search_text(&pane(), "a\n\nb\n\n\n   \n", &pattern, 0);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].line_number, 2);
+    }
+}
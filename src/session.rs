@@ -1,14 +1,16 @@
 //! Session types and data structures.
 
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
 /// Represents the current status of a tmux session
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SessionStatus {
     /// Has at least one attached client
     Active,
     /// Running but no clients attached
+    #[default]
     Idle,
     /// Session no longer exists
     Dead,
@@ -24,27 +26,321 @@ impl std::fmt::Display for SessionStatus {
     }
 }
 
+/// What the agent running in a session is doing, as inferred from its screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentState {
+    /// Producing output or thinking
+    Working,
+    /// Idle at its input prompt
+    WaitingForInput,
+    /// Asking for permission to continue
+    NeedsApproval,
+    /// Exited normally
+    Finished,
+    /// Exited with an error or showing an error
+    Errored,
+}
+
+impl std::fmt::Display for AgentState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Working => write!(f, "working"),
+            Self::WaitingForInput => write!(f, "waiting"),
+            Self::NeedsApproval => write!(f, "needs approval"),
+            Self::Finished => write!(f, "finished"),
+            Self::Errored => write!(f, "errored"),
+        }
+    }
+}
+
 /// Represents a tmux session with its metadata
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Session {
     /// Session name
     pub name: String,
     /// Current status of the session
     pub status: SessionStatus,
-    /// Working directory of the session
+    /// Working directory of the session's agent pane
     pub working_directory: PathBuf,
-    /// Timestamp of last activity
+    /// Timestamp of the last activity in the agent pane's window
     pub last_activity: DateTime<Utc>,
     /// Timestamp when session was created
     pub created_at: DateTime<Utc>,
     /// Number of windows in the session
     pub window_count: u32,
+    /// Host the session runs on, when sessions of several hosts are listed
+    /// together
+    #[serde(default)]
+    pub host: Option<String>,
+    /// Server the session lives on, when sessions of several servers are
+    /// listed together
+    #[serde(default)]
+    pub server: Option<String>,
+    /// Every pane of every window, in window and pane order
+    #[serde(default)]
+    pub panes: Vec<Pane>,
+    /// Index into `panes` of the pane the agent runs in
+    #[serde(default)]
+    pub agent_pane_index: Option<usize>,
+    /// Tags from the `@ams-tags` user option, e.g. `ticket:ABC-12`
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Free-text note from the `@ams-note` user option
+    #[serde(default)]
+    pub note: Option<String>,
+    /// Agent command ams launched in the session (`@ams-agent`)
+    #[serde(default)]
+    pub agent: Option<String>,
+    /// Agent profile ams launched in the session (`@ams-profile`)
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Which ams command created the session (`@ams-created-by`)
+    #[serde(default)]
+    pub created_by: Option<String>,
+    /// Id of the queued task the session runs (`@ams-task`)
+    #[serde(default)]
+    pub task: Option<u64>,
+}
+
+impl Session {
+    /// Picks the pane the agent runs in and takes the session's working
+    /// directory and last activity from it
+    ///
+    /// That is the first pane whose foreground command is one of
+    /// `agent_commands`, falling back to the session's current pane, so a
+    /// dev server split next to the agent does not stand in for it.
+    pub fn select_agent_pane(&mut self, agent_commands: &[String]) {
+        self.agent_pane_index = self
+            .panes
+            .iter()
+            .position(|pane| agent_commands.contains(&pane.current_command))
+            .or_else(|| {
+                self.panes
+                    .iter()
+                    .position(|pane| pane.window_active && pane.active)
+            })
+            .or_else(|| (!self.panes.is_empty()).then_some(0));
+
+        if let Some(pane) = self.agent_pane_index.and_then(|i| self.panes.get(i)) {
+            self.working_directory.clone_from(&pane.current_path);
+            self.last_activity = pane.last_activity;
+        }
+    }
+
+    /// Returns whether ams manages the session: an ams command created it,
+    /// or one of its panes runs one of `agent_commands`
+    #[must_use]
+    pub fn is_managed(&self, agent_commands: &[String]) -> bool {
+        self.created_by.is_some()
+            || self
+                .panes
+                .iter()
+                .any(|pane| agent_commands.contains(&pane.current_command))
+    }
+
+    /// Returns the pane the agent runs in
+    #[must_use]
+    pub fn agent_pane(&self) -> Option<&Pane> {
+        self.panes.get(self.agent_pane_index?)
+    }
+
+    /// Returns the target of the agent pane, or the session name when the
+    /// panes are unknown
+    #[must_use]
+    pub fn agent_target(&self) -> String {
+        self.agent_pane()
+            .map_or_else(|| self.name.clone(), |pane| pane.target().to_string())
+    }
+}
+
+/// Identifies a single pane inside a tmux session
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaneTarget {
+    /// Name of the session owning the pane
+    pub session: String,
+    /// Index of the window within the session
+    pub window_index: u32,
+    /// Index of the pane within the window
+    pub pane_index: u32,
+}
+
+impl std::fmt::Display for PaneTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}.{}",
+            self.session, self.window_index, self.pane_index
+        )
+    }
+}
+
+/// A window inside a tmux session
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Window {
+    /// Name of the session owning the window
+    pub session: String,
+    /// Index of the window within the session
+    pub index: u32,
+    /// Window name
+    pub name: String,
+    /// Whether this is the session's current window
+    pub active: bool,
+    /// Number of panes in the window
+    pub pane_count: u32,
+    /// Width in columns
+    pub width: u32,
+    /// Height in rows
+    pub height: u32,
+}
+
+impl Window {
+    /// Returns the `session:window` target for the window
+    #[must_use]
+    pub fn target(&self) -> String {
+        format!("{}:{}", self.session, self.index)
+    }
+}
+
+/// A pane inside a tmux window
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pane {
+    /// Name of the session owning the pane
+    pub session: String,
+    /// Index of the window owning the pane
+    pub window_index: u32,
+    /// Index of the pane within the window
+    pub index: u32,
+    /// Whether this is the window's current pane
+    pub active: bool,
+    /// Whether the pane's window is the session's current window
+    pub window_active: bool,
+    /// Command running in the foreground of the pane
+    pub current_command: String,
+    /// Process ID of the pane's initial process
+    pub pid: u32,
+    /// Width in columns
+    pub width: u32,
+    /// Height in rows
+    pub height: u32,
+    /// Current working directory of the pane
+    pub current_path: PathBuf,
+    /// Timestamp of the last activity in the pane's window; tmux does not
+    /// track activity per pane
+    pub last_activity: DateTime<Utc>,
+}
+
+impl Pane {
+    /// Returns the `session:window.pane` target for the pane
+    #[must_use]
+    pub fn target(&self) -> PaneTarget {
+        PaneTarget {
+            session: self.session.clone(),
+            window_index: self.window_index,
+            pane_index: self.index,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_agent_state_display() {
+        assert_eq!(AgentState::Working.to_string(), "working");
+        assert_eq!(AgentState::WaitingForInput.to_string(), "waiting");
+        assert_eq!(AgentState::NeedsApproval.to_string(), "needs approval");
+        assert_eq!(AgentState::Finished.to_string(), "finished");
+        assert_eq!(AgentState::Errored.to_string(), "errored");
+    }
+
+    #[test]
+    fn test_pane_target_display() {
+        let target = PaneTarget {
+            session: "agent".to_string(),
+            window_index: 1,
+            pane_index: 2,
+        };
+        assert_eq!(target.to_string(), "agent:1.2");
+    }
+
+    #[test]
+    fn test_window_and_pane_targets() {
+        let window = Window {
+            session: "agent".to_string(),
+            index: 1,
+            name: "editor".to_string(),
+            active: true,
+            pane_count: 2,
+            width: 80,
+            height: 24,
+        };
+        assert_eq!(window.target(), "agent:1");
+
+        let pane = create_pane(1, 2, "npm", "/src/app");
+        assert_eq!(pane.target().to_string(), "agent:1.2");
+    }
+
+    fn create_pane(window_index: u32, index: u32, command: &str, path: &str) -> Pane {
+        Pane {
+            session: "agent".to_string(),
+            window_index,
+            index,
+            active: false,
+            window_active: false,
+            current_command: command.to_string(),
+            pid: 4242,
+            width: 40,
+            height: 24,
+            current_path: PathBuf::from(path),
+            last_activity: DateTime::from_timestamp(1_704_067_200 + i64::from(index), 0).unwrap(),
+        }
+    }
+
+    fn create_session(panes: Vec<Pane>) -> Session {
+        Session {
+            name: "agent".to_string(),
+            window_count: 1,
+            panes,
+            ..Session::default()
+        }
+    }
+
+    #[test]
+    fn test_select_agent_pane_by_command() {
+        let mut dev_server = create_pane(0, 0, "npm", "/src/app/web");
+        dev_server.active = true;
+        dev_server.window_active = true;
+        let agent = create_pane(0, 1, "claude", "/src/app");
+        let mut session = create_session(vec![dev_server, agent.clone()]);
+
+        session.select_agent_pane(&["claude".to_string()]);
+
+        assert_eq!(session.agent_pane(), Some(&agent));
+        assert_eq!(session.agent_target(), "agent:0.1");
+        assert_eq!(session.working_directory, PathBuf::from("/src/app"));
+        assert_eq!(session.last_activity, agent.last_activity);
+    }
+
+    #[test]
+    fn test_select_agent_pane_falls_back_to_current_pane() {
+        let mut current = create_pane(1, 1, "bash", "/src/b");
+        current.active = true;
+        current.window_active = true;
+        let mut session =
+            create_session(vec![create_pane(0, 0, "bash", "/src/a"), current.clone()]);
+
+        session.select_agent_pane(&["claude".to_string()]);
+        assert_eq!(session.agent_pane(), Some(&current));
+        assert_eq!(session.working_directory, PathBuf::from("/src/b"));
+
+        let mut session = create_session(Vec::new());
+        session.select_agent_pane(&[]);
+        assert!(session.agent_pane().is_none());
+        assert_eq!(session.agent_target(), "agent");
+    }
+
     #[test]
     fn test_session_status_display_active() {
         assert_eq!(SessionStatus::Active.to_string(), "Active");
@@ -0,0 +1,39 @@
+//! Quoting for POSIX shell command lines.
+
+/// Quotes a string for use as a single POSIX shell word
+#[must_use]
+pub fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// Quotes a word for the shell unless it is made of safe characters only
+#[must_use]
+pub fn word(word: &str) -> String {
+    let safe = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:@%+,".contains(c));
+    if safe {
+        word.to_string()
+    } else {
+        quote(word)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("/tmp/a b.cast"), "'/tmp/a b.cast'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+    }
+
+    #[test]
+    fn test_word() {
+        assert_eq!(word("--model"), "--model");
+        assert_eq!(word("my model"), "'my model'");
+        assert_eq!(word(""), "''");
+    }
+}
@@ -0,0 +1,528 @@
+//! Session templates: windows and panes built from a TOML description.
+
+use crate::config::{self, AgentProfile};
+use crate::environment::{self, EnvError};
+use crate::tmux::{self, Feature, TmuxError, TmuxVersion};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Errors that can occur while loading or building a template.
+#[derive(Error, Debug)]
+pub enum TemplateError {
+    /// No template file exists for the given name.
+    #[error("Template not found: {0} (looked for {1})")]
+    NotFound(String, PathBuf),
+
+    /// Failed to read the template file.
+    #[error("Failed to read template {0}: {1}")]
+    Io(PathBuf, #[source] std::io::Error),
+
+    /// The template file is not valid TOML or has invalid values.
+    #[error("Invalid template {0}: {1}")]
+    Parse(PathBuf, #[source] toml::de::Error),
+
+    /// The template is well-formed but cannot be built.
+    #[error("Invalid template: {0}")]
+    Invalid(String),
+
+    /// An agent's environment could not be resolved.
+    #[error(transparent)]
+    Env(#[from] EnvError),
+
+    /// A tmux command failed while building the session.
+    #[error(transparent)]
+    Tmux(#[from] TmuxError),
+}
+
+/// A session layout: windows, their panes and what runs in them
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Template {
+    /// Environment variables for every pane
+    pub env: BTreeMap<String, String>,
+    /// Windows in order; without any, the session gets one shell
+    pub windows: Vec<WindowTemplate>,
+}
+
+/// A window of a template
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowTemplate {
+    /// Window name
+    pub name: Option<String>,
+    /// tmux layout applied once all panes exist, e.g. `main-vertical`
+    pub layout: Option<String>,
+    /// Environment variables for the window's panes
+    pub env: BTreeMap<String, String>,
+    /// Panes in order; the first is the window's initial pane and each
+    /// other one is split from the pane before it
+    pub panes: Vec<PaneTemplate>,
+}
+
+/// A pane of a template window
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PaneTemplate {
+    /// Agent profile from `[agents]` to run in the pane
+    pub agent: Option<String>,
+    /// Shell command typed into the pane
+    pub command: Option<String>,
+    /// Working directory, relative to the session directory
+    pub directory: Option<PathBuf>,
+    /// How the pane is split from the one before it
+    pub split: Split,
+    /// Size in lines or columns, or a percentage such as `30%`
+    pub size: Option<String>,
+    /// Environment variables for the pane
+    pub env: BTreeMap<String, String>,
+    /// Select this pane once the session is built
+    pub focus: bool,
+}
+
+/// Direction of a pane split
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Split {
+    /// Above and below each other (`split-window -v`)
+    #[default]
+    Vertical,
+    /// Side by side (`split-window -h`)
+    Horizontal,
+}
+
+/// Format printed by `new-session`, `new-window` and `split-window -P`
+const IDS_FORMAT: &str = "#{window_id} #{pane_id}";
+
+/// A pane with its command, directory and options resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PanePlan {
+    command: Option<String>,
+    options: Vec<String>,
+    focus: bool,
+}
+
+impl Template {
+    /// Loads a template by name from the templates directory, or from a
+    /// file when `name` is a path
+    pub fn load(name: &str) -> Result<Self, TemplateError> {
+        let path = if name.contains('/') || Path::new(name).extension().is_some() {
+            PathBuf::from(name)
+        } else {
+            config::templates_dir()
+                .unwrap_or_default()
+                .join(format!("{name}.toml"))
+        };
+        if !path.exists() {
+            return Err(TemplateError::NotFound(name.to_string(), path));
+        }
+        Self::load_from(&path)
+    }
+
+    /// Loads a template from a specific file
+    pub fn load_from(path: &Path) -> Result<Self, TemplateError> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| TemplateError::Io(path.to_path_buf(), e))?;
+        Self::parse(&contents).map_err(|e| TemplateError::Parse(path.to_path_buf(), e))
+    }
+
+    /// Parses a template from TOML text
+    pub fn parse(contents: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(contents)
+    }
+
+    /// Creates a detached session laid out as described
+    ///
+    /// The template is checked before anything is created, and a session
+    /// that fails halfway is killed again.
+    pub fn create_session(
+        &self,
+        name: &str,
+        directory: Option<&Path>,
+        agents: &BTreeMap<String, AgentProfile>,
+    ) -> Result<(), TemplateError> {
+        let directory = directory
+            .map(Path::to_path_buf)
+            .or_else(|| std::env::current_dir().ok());
+        let plan = self.plan(directory.as_deref(), agents, TmuxVersion::detect()?)?;
+
+        let mut created = false;
+        Self::build(name, &plan, &mut created).map_err(|e| {
+            if created {
+                let _ = tmux::kill_session(name);
+            }
+            e
+        })
+    }
+
+    /// Resolves every pane of every window, failing on unknown agents,
+    /// invalid sizes, and environment variables on tmux releases that
+    /// cannot set them
+    ///
+    /// An agent pane gets its profile's environment, running its secret
+    /// commands; the template's, window's and pane's own variables override
+    /// it.
+    fn plan(
+        &self,
+        directory: Option<&Path>,
+        agents: &BTreeMap<String, AgentProfile>,
+        version: TmuxVersion,
+    ) -> Result<Vec<(WindowTemplate, Vec<PanePlan>)>, TemplateError> {
+        let mut windows = self.windows.clone();
+        if windows.is_empty() {
+            windows.push(WindowTemplate::default());
+        }
+
+        windows
+            .into_iter()
+            .map(|mut window| {
+                if window.panes.is_empty() {
+                    window.panes.push(PaneTemplate::default());
+                }
+                let panes = window
+                    .panes
+                    .iter()
+                    .enumerate()
+                    .map(|(index, pane)| {
+                        let mut options = Vec::new();
+                        if index > 0 {
+                            options.push(
+                                match pane.split {
+                                    Split::Vertical => "-v",
+                                    Split::Horizontal => "-h",
+                                }
+                                .to_string(),
+                            );
+                            if let Some(size) = &pane.size {
+                                options.extend(size_args(size, version)?);
+                            }
+                        }
+                        if let Some(dir) = pane_directory(directory, pane.directory.as_deref()) {
+                            options.extend(["-c".to_string(), dir.to_string_lossy().into_owned()]);
+                        }
+                        let agent_env = match pane.agent.as_ref().and_then(|a| agents.get(a)) {
+                            Some(profile) => environment::resolve(&profile.env)?,
+                            None => BTreeMap::new(),
+                        };
+                        let env = agent_env
+                            .iter()
+                            .chain(&self.env)
+                            .chain(&window.env)
+                            .chain(&pane.env);
+                        // Later scopes override earlier ones
+                        let env: BTreeMap<_, _> = env.collect();
+                        let feature = Feature::NewSessionEnvironment;
+                        if !env.is_empty() && !version.supports(feature) {
+                            return Err(TmuxError::Unsupported {
+                                feature,
+                                required_version: feature.required_version(),
+                            }
+                            .into());
+                        }
+                        for (key, value) in env {
+                            options.extend(["-e".to_string(), format!("{key}={value}")]);
+                        }
+                        Ok(PanePlan {
+                            command: pane_command(pane, agents)?,
+                            options,
+                            focus: pane.focus,
+                        })
+                    })
+                    .collect::<Result<_, TemplateError>>()?;
+                Ok((window, panes))
+            })
+            .collect()
+    }
+
+    /// Creates the session, then its windows and panes in order
+    fn build(
+        name: &str,
+        plan: &[(WindowTemplate, Vec<PanePlan>)],
+        created: &mut bool,
+    ) -> Result<(), TemplateError> {
+        let mut last_window = String::new();
+        let mut focus = None;
+
+        for (window, panes) in plan {
+            let mut last_pane = String::new();
+            for (index, pane) in panes.iter().enumerate() {
+                let mut args: Vec<String> = if index == 0 {
+                    let mut args = if last_window.is_empty() {
+                        vec!["new-session", "-d", "-s", name]
+                    } else {
+                        vec!["new-window", "-d", "-a", "-t", &last_window]
+                    };
+                    if let Some(window_name) = &window.name {
+                        args.extend(["-n", window_name]);
+                    }
+                    args.into_iter().map(String::from).collect()
+                } else {
+                    ["split-window", "-d", "-t", &last_pane]
+                        .map(String::from)
+                        .to_vec()
+                };
+                args.extend(pane.options.iter().cloned());
+                args.extend(["-P", "-F", IDS_FORMAT].map(String::from));
+
+                // Environment values may be secrets, kept off the command line
+                let ids = if pane.options.iter().any(|option| option == "-e") {
+                    tmux::run_from_stdin(&args)?
+                } else {
+                    tmux::run(&args)?
+                };
+                *created = true;
+                let (window_id, pane_id) = ids.split_once(' ').ok_or_else(|| {
+                    TmuxError::ParseError(format!("Expected window and pane ids: {ids}"))
+                })?;
+                if index == 0 {
+                    last_window = window_id.to_string();
+                }
+                last_pane = pane_id.to_string();
+
+                if let Some(command) = &pane.command {
+                    tmux::send_keys(pane_id, command, true)?;
+                }
+                if pane.focus || focus.is_none() {
+                    focus = Some((window_id.to_string(), pane_id.to_string()));
+                }
+            }
+            if let Some(layout) = &window.layout {
+                tmux::run(&["select-layout", "-t", &last_window, layout])?;
+            }
+        }
+
+        if let Some((window, pane)) = focus {
+            tmux::run(&["select-window", "-t", &window])?;
+            tmux::run(&["select-pane", "-t", &pane])?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the command line for an agent pane, or the pane's own command
+fn pane_command(
+    pane: &PaneTemplate,
+    agents: &BTreeMap<String, AgentProfile>,
+) -> Result<Option<String>, TemplateError> {
+    match (&pane.agent, &pane.command) {
+        (Some(agent), Some(_)) => Err(TemplateError::Invalid(format!(
+            "pane running agent '{agent}' also sets a command"
+        ))),
+        (Some(agent), None) => {
+            let profile = agents.get(agent).ok_or_else(|| {
+                TemplateError::Invalid(format!(
+                    "unknown agent '{agent}' (define it under [agents.{agent}])"
+                ))
+            })?;
+            Ok(Some(profile.command_line()))
+        }
+        (None, command) => Ok(command.clone()),
+    }
+}
+
+/// Returns the `split-window` options for a pane size
+///
+/// Percentages use `-l N%`, or `-p N` on tmux releases without it.
+fn size_args(size: &str, version: TmuxVersion) -> Result<Vec<String>, TemplateError> {
+    let invalid = || {
+        TemplateError::Invalid(format!(
+            "invalid pane size '{size}' (expected lines, columns or a percentage like 30%)"
+        ))
+    };
+
+    if let Some(percent) = size.strip_suffix('%') {
+        let percent: u32 = percent.parse().map_err(|_| invalid())?;
+        if !(1..100).contains(&percent) {
+            return Err(invalid());
+        }
+        return Ok(if version.supports(Feature::PercentageSize) {
+            vec!["-l".to_string(), size.to_string()]
+        } else {
+            vec!["-p".to_string(), percent.to_string()]
+        });
+    }
+
+    size.parse::<u32>().map_err(|_| invalid())?;
+    Ok(vec!["-l".to_string(), size.to_string()])
+}
+
+/// Resolves a pane directory against the session directory
+fn pane_directory(session: Option<&Path>, pane: Option<&Path>) -> Option<PathBuf> {
+    match (session, pane) {
+        (Some(session), Some(pane)) => Some(session.join(pane)),
+        (None, Some(pane)) => Some(pane.to_path_buf()),
+        (session, None) => session.map(Path::to_path_buf),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::EnvValue;
+
+    const FULLSTACK: &str = r#"
+        env = { RUST_LOG = "info" }
+
+        [[windows]]
+        name = "agent"
+        layout = "main-vertical"
+
+        [[windows.panes]]
+        agent = "claude-code"
+        focus = true
+
+        [[windows.panes]]
+        command = "cargo watch -x test"
+        split = "horizontal"
+        size = "30%"
+        env = { RUST_LOG = "debug" }
+
+        [[windows]]
+        name = "logs"
+
+        [[windows.panes]]
+        command = "tail -f log/development.log"
+        directory = "server"
+    "#;
+
+    fn agents() -> BTreeMap<String, AgentProfile> {
+        let mut agents = BTreeMap::new();
+        agents.insert(
+            "claude-code".to_string(),
+            AgentProfile {
+                command: "claude".to_string(),
+                args: vec!["--model".to_string(), "my model".to_string()],
+                env: BTreeMap::from([
+                    (
+                        "API_KEY".to_string(),
+                        EnvValue::Secret {
+                            command: "printf s3cret".to_string(),
+                        },
+                    ),
+                    ("RUST_LOG".to_string(), EnvValue::from("warn")),
+                ]),
+                ..AgentProfile::default()
+            },
+        );
+        agents
+    }
+
+    #[test]
+    fn test_parse_template() {
+        let template = Template::parse(FULLSTACK).unwrap();
+        assert_eq!(template.windows.len(), 2);
+        assert_eq!(template.windows[0].panes[1].split, Split::Horizontal);
+        assert_eq!(template.windows[0].panes[1].size.as_deref(), Some("30%"));
+        assert_eq!(template.windows[1].panes[0].split, Split::Vertical);
+        assert!(Template::parse("[[windows]]\nnmae = \"typo\"").is_err());
+    }
+
+    #[test]
+    fn test_plan() {
+        let template = Template::parse(FULLSTACK).unwrap();
+        let plan = template
+            .plan(Some(Path::new("/src")), &agents(), TmuxVersion::new(3, 3))
+            .unwrap();
+
+        let agent = &plan[0].1[0];
+        assert_eq!(agent.command.as_deref(), Some("claude --model 'my model'"));
+        assert_eq!(
+            agent.options,
+            vec!["-c", "/src", "-e", "API_KEY=s3cret", "-e", "RUST_LOG=info"]
+        );
+        assert!(agent.focus);
+
+        let watcher = &plan[0].1[1];
+        assert_eq!(
+            watcher.options,
+            vec!["-h", "-l", "30%", "-c", "/src", "-e", "RUST_LOG=debug"]
+        );
+
+        let logs = &plan[1].1[0];
+        assert_eq!(&logs.options[..2], ["-c", "/src/server"]);
+        assert!(!logs.focus);
+    }
+
+    #[test]
+    fn test_plan_defaults_to_one_shell() {
+        let plan = Template::default()
+            .plan(None, &BTreeMap::new(), TmuxVersion::new(3, 3))
+            .unwrap();
+        assert_eq!(plan.len(), 1);
+        assert_eq!(
+            plan[0].1,
+            vec![PanePlan {
+                command: None,
+                options: Vec::new(),
+                focus: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_plan_rejects_bad_panes() {
+        let unknown = Template::parse("[[windows]]\n[[windows.panes]]\nagent = \"nope\"").unwrap();
+        let err = unknown
+            .plan(None, &agents(), TmuxVersion::new(3, 3))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid template: unknown agent 'nope' (define it under [agents.nope])"
+        );
+
+        let both = Template::parse(
+            "[[windows]]\n[[windows.panes]]\nagent = \"claude-code\"\ncommand = \"ls\"",
+        )
+        .unwrap();
+        assert!(both.plan(None, &agents(), TmuxVersion::new(3, 3)).is_err());
+    }
+
+    #[test]
+    fn test_plan_requires_environment_support_for_any_pane() {
+        let template = Template::parse(
+            "[[windows]]\n[[windows.panes]]\n[[windows]]\n[[windows.panes]]\nenv = { A = \"1\" }",
+        )
+        .unwrap();
+        assert!(template
+            .plan(None, &BTreeMap::new(), TmuxVersion::new(3, 2))
+            .is_ok());
+        assert!(matches!(
+            template.plan(None, &BTreeMap::new(), TmuxVersion::new(3, 1)),
+            Err(TemplateError::Tmux(TmuxError::Unsupported { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_size_args() {
+        let v30 = TmuxVersion::new(3, 0);
+        let v31 = TmuxVersion::new(3, 1);
+        assert_eq!(size_args("30%", v31).unwrap(), vec!["-l", "30%"]);
+        assert_eq!(size_args("30%", v30).unwrap(), vec!["-p", "30"]);
+        assert_eq!(size_args("12", v30).unwrap(), vec!["-l", "12"]);
+        for invalid in ["", "abc", "0%", "100%", "-3"] {
+            assert!(size_args(invalid, v31).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_pane_directory() {
+        let session = Some(Path::new("/src"));
+        assert_eq!(pane_directory(session, None), Some(PathBuf::from("/src")));
+        assert_eq!(
+            pane_directory(session, Some(Path::new("web"))),
+            Some(PathBuf::from("/src/web"))
+        );
+        assert_eq!(
+            pane_directory(session, Some(Path::new("/var/log"))),
+            Some(PathBuf::from("/var/log"))
+        );
+        assert_eq!(pane_directory(None, None), None);
+    }
+
+    #[test]
+    fn test_load_missing_template() {
+        let err = Template::load("/nonexistent/fullstack.toml").unwrap_err();
+        assert!(matches!(err, TemplateError::NotFound(_, _)));
+    }
+}
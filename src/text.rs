@@ -0,0 +1,283 @@
+//! Text layout by terminal display width.
+//!
+//! Widths count terminal columns: East Asian wide characters and most emoji
+//! take two, combining marks none. Text is only ever cut between grapheme
+//! clusters, so a cut never splits a character or detaches its accents.
+
+use std::path::Path;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Marks where text was cut
+pub const ELLIPSIS: &str = "…";
+
+/// Returns the number of terminal columns `text` takes up
+#[must_use]
+pub fn width(text: &str) -> usize {
+    text.graphemes(true).map(UnicodeWidthStr::width).sum()
+}
+
+/// Returns the longest start of `text` that fits in `max` columns
+fn prefix(text: &str, max: usize) -> &str {
+    let mut used = 0;
+    let mut end = 0;
+    for (i, grapheme) in text.grapheme_indices(true) {
+        used += grapheme.width();
+        if used > max {
+            break;
+        }
+        end = i + grapheme.len();
+    }
+    &text[..end]
+}
+
+/// Returns the longest end of `text` that fits in `max` columns
+fn suffix(text: &str, max: usize) -> &str {
+    let mut used = 0;
+    let mut start = text.len();
+    for (i, grapheme) in text.grapheme_indices(true).rev() {
+        used += grapheme.width();
+        if used > max {
+            break;
+        }
+        start = i;
+    }
+    &text[start..]
+}
+
+/// Cuts `text` to at most `max` columns, ending it with `…` if it was cut
+#[must_use]
+pub fn truncate(text: &str, max: usize) -> String {
+    if width(text) <= max {
+        return text.to_string();
+    }
+    if max == 0 {
+        return String::new();
+    }
+    format!("{}{ELLIPSIS}", prefix(text, max - 1))
+}
+
+/// Cuts `text` to at most `max` columns by dropping its start, beginning it
+/// with `…` if it was cut
+#[must_use]
+pub fn truncate_start(text: &str, max: usize) -> String {
+    if width(text) <= max {
+        return text.to_string();
+    }
+    if max == 0 {
+        return String::new();
+    }
+    format!("{ELLIPSIS}{}", suffix(text, max - 1))
+}
+
+/// Cuts `text` to at most `max` columns by replacing its middle with `…`
+#[must_use]
+pub fn truncate_middle(text: &str, max: usize) -> String {
+    if width(text) <= max {
+        return text.to_string();
+    }
+    if max == 0 {
+        return String::new();
+    }
+    let budget = max - 1;
+    format!(
+        "{}{ELLIPSIS}{}",
+        prefix(text, budget - budget / 2),
+        suffix(text, budget / 2)
+    )
+}
+
+/// Cuts `text` to `columns` and pads it with spaces to exactly that width,
+/// for aligned table output
+#[must_use]
+pub fn pad(text: &str, columns: usize) -> String {
+    let mut text = truncate(text, columns);
+    let fill = columns - width(&text);
+    text.extend(std::iter::repeat(' ').take(fill));
+    text
+}
+
+/// Shortens a path to at most `max` columns
+///
+/// The home directory becomes `~`. A path that is still too long loses
+/// directories from its middle (`~/…/repo/subdir`), then its start as well
+/// (`…/repo/subdir`), keeping as many trailing directories as fit; a last
+/// component that does not fit on its own is cut in the middle.
+#[must_use]
+pub fn abbreviate_path(path: &Path, home: Option<&Path>, max: usize) -> String {
+    let home_relative = home
+        .filter(|home| home.parent().is_some())
+        .and_then(|home| path.strip_prefix(home).ok());
+    let text = match home_relative {
+        Some(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Some(rest) => format!("~/{}", rest.to_string_lossy()),
+        None => path.to_string_lossy().into_owned(),
+    };
+    if width(&text) <= max {
+        return text;
+    }
+
+    let parts: Vec<&str> = text.split('/').collect();
+    let head = parts[0];
+    for keep in (1..parts.len().saturating_sub(1)).rev() {
+        let tail = parts[parts.len() - keep..].join("/");
+        for candidate in [
+            format!("{head}/{ELLIPSIS}/{tail}"),
+            format!("{ELLIPSIS}/{tail}"),
+        ] {
+            if width(&candidate) <= max {
+                return candidate;
+            }
+        }
+    }
+    truncate_middle(parts[parts.len() - 1], max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Text mixing ASCII, accents, combining marks, CJK, emoji and
+    /// separators
+    const TEXT: &str = "[a-zé日本語🦀👩‍💻\u{301} ./_-]{0,40}";
+
+    #[test]
+    fn test_width() {
+        assert_eq!(width("agent"), 5);
+        assert_eq!(width("日本"), 4);
+        assert_eq!(width("e\u{301}"), 1);
+        assert_eq!(width(""), 0);
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("session", 10), "session");
+        assert_eq!(truncate("long-session-name", 8), "long-se…");
+        assert_eq!(truncate("日本語のセッション", 7), "日本語…");
+        // A wide glyph that would straddle the limit is left out.
+        assert_eq!(truncate("a日本語", 5), "a日…");
+        assert_eq!(truncate("cafe\u{301}-au-lait", 5), "cafe\u{301}…");
+        assert_eq!(truncate("anything", 0), "");
+    }
+
+    #[test]
+    fn test_truncate_start_and_middle() {
+        assert_eq!(truncate_start("long-session-name", 8), "…on-name");
+        assert_eq!(truncate_middle("long-session-name", 9), "long…name");
+        assert_eq!(truncate_middle("日本語のセッション", 9), "日本…ョン");
+    }
+
+    #[test]
+    fn test_pad() {
+        assert_eq!(pad("ab", 4), "ab  ");
+        assert_eq!(pad("日本", 5), "日本 ");
+        assert_eq!(pad("日本語", 4), "日… ");
+    }
+
+    #[test]
+    fn test_abbreviate_path_home() {
+        let home = Path::new("/home/me");
+        assert_eq!(abbreviate_path(Path::new("/home/me"), Some(home), 40), "~");
+        assert_eq!(
+            abbreviate_path(Path::new("/home/me/src/ams"), Some(home), 40),
+            "~/src/ams"
+        );
+        assert_eq!(
+            abbreviate_path(Path::new("/home/metoo/src"), Some(home), 40),
+            "/home/metoo/src"
+        );
+        assert_eq!(
+            abbreviate_path(Path::new("/srv"), Some(Path::new("/")), 40),
+            "/srv"
+        );
+    }
+
+    #[test]
+    fn test_abbreviate_path_drops_middle_directories() {
+        let home = Path::new("/home/me");
+        let path = Path::new("/home/me/work/clients/acme/repo/subdir");
+        assert_eq!(
+            abbreviate_path(path, Some(home), 22),
+            "~/…/acme/repo/subdir"
+        );
+        assert_eq!(abbreviate_path(path, Some(home), 16), "~/…/repo/subdir");
+        assert_eq!(abbreviate_path(path, Some(home), 14), "…/repo/subdir");
+        assert_eq!(
+            abbreviate_path(Path::new("/var/lib/projects/repo"), None, 12),
+            "/…/repo"
+        );
+    }
+
+    #[test]
+    fn test_abbreviate_path_cuts_long_last_component() {
+        let path = Path::new("/src/a-very-long-repository-name");
+        assert_eq!(abbreviate_path(path, None, 9), "a-ve…name");
+        assert_eq!(
+            abbreviate_path(Path::new("/プロジェクト/日本語のリポジトリ"), None, 9),
+            "日本…トリ"
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_truncate_fits_and_keeps_prefix(text in TEXT, max in 0usize..50) {
+            let cut = truncate(&text, max);
+            prop_assert!(width(&cut) <= max);
+            if width(&text) <= max {
+                prop_assert_eq!(&cut, &text);
+            } else {
+                let kept = cut.strip_suffix(ELLIPSIS).unwrap_or_default();
+                prop_assert!(text.starts_with(kept));
+            }
+        }
+
+        #[test]
+        fn test_truncate_start_fits_and_keeps_suffix(text in TEXT, max in 0usize..50) {
+            let cut = truncate_start(&text, max);
+            prop_assert!(width(&cut) <= max);
+            let kept = cut.strip_prefix(ELLIPSIS).unwrap_or(&cut);
+            prop_assert!(text.ends_with(kept));
+        }
+
+        #[test]
+        fn test_truncate_middle_fits(text in TEXT, max in 0usize..50) {
+            let cut = truncate_middle(&text, max);
+            prop_assert!(width(&cut) <= max);
+            if let Some((start, end)) = cut.split_once(ELLIPSIS) {
+                prop_assert!(text.starts_with(start));
+                prop_assert!(text.ends_with(end));
+            } else {
+                prop_assert!(cut.is_empty() || cut == text);
+            }
+        }
+
+        #[test]
+        fn test_truncate_cuts_between_graphemes(text in TEXT, max in 0usize..50) {
+            let cut = truncate(&text, max);
+            let kept = cut.strip_suffix(ELLIPSIS).unwrap_or(&cut);
+            let graphemes: Vec<&str> = text.graphemes(true).collect();
+            let kept_graphemes: Vec<&str> = kept.graphemes(true).collect();
+            prop_assert_eq!(&graphemes[..kept_graphemes.len()], &kept_graphemes[..]);
+        }
+
+        #[test]
+        fn test_pad_is_exact(text in TEXT, columns in 0usize..50) {
+            prop_assert_eq!(width(&pad(&text, columns)), columns);
+        }
+
+        #[test]
+        fn test_abbreviate_path_fits_and_keeps_last_component(
+            parts in prop::collection::vec("[a-z日本🦀_-]{1,12}", 1..8),
+            max in 0usize..60,
+        ) {
+            let path = format!("/home/me/{}", parts.join("/"));
+            let short = abbreviate_path(Path::new(&path), Some(Path::new("/home/me")), max);
+            prop_assert!(width(&short) <= max);
+            let last = format!("/{}", parts[parts.len() - 1]);
+            if width(&last) < max {
+                prop_assert!(short.ends_with(&last));
+            }
+        }
+    }
+}
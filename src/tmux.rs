@@ -1,9 +1,16 @@
 //! Tmux command wrapper and session management.
 
-use crate::session::{Session, SessionStatus};
-use chrono::{TimeZone, Utc};
+use crate::control::CONTROL_SESSION;
+use crate::metadata;
+use crate::session::{Pane, PaneTarget, Session, SessionStatus, Window};
+use crate::shell;
+use chrono::{DateTime, TimeZone, Utc};
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::io::Write;
 use std::path::PathBuf;
-use std::process::Command;
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::sync::{OnceLock, PoisonError, RwLock};
 use thiserror::Error;
 
 /// Errors that can occur when interacting with tmux.
@@ -28,21 +35,560 @@ pub enum TmuxError {
     /// Session with the given name already exists.
     #[error("Session already exists: {0}")]
     SessionExists(String),
+
+    /// The control-mode connection failed or was closed.
+    #[error("Tmux control mode error: {0}")]
+    ControlMode(String),
+
+    /// The installed tmux is too old for a feature.
+    #[error("{feature} requires tmux {required_version} or newer")]
+    Unsupported {
+        /// The missing feature
+        feature: Feature,
+        /// Oldest tmux release that has it
+        required_version: TmuxVersion,
+    },
+}
+
+/// A tmux release, ignoring patch letters (`3.3a` is 3.3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TmuxVersion {
+    /// Major version
+    pub major: u32,
+    /// Minor version
+    pub minor: u32,
+}
+
+impl TmuxVersion {
+    /// Oldest tmux release ams supports
+    pub const MINIMUM: Self = Self::new(3, 0);
+
+    /// Assumed for builds that do not report a release number, such as
+    /// `tmux master`
+    pub const DEVELOPMENT: Self = Self::new(u32::MAX, 0);
+
+    /// Creates a version from its parts
+    #[must_use]
+    pub const fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+
+    /// Parses `tmux -V` output such as `tmux 3.3a` or `tmux next-3.4`
+    #[must_use]
+    pub fn parse(output: &str) -> Option<Self> {
+        let version = output.trim().strip_prefix("tmux ")?;
+        let version = version.strip_prefix("next-").unwrap_or(version);
+        let (major, rest) = version.split_once('.')?;
+        let minor: String = rest.chars().take_while(char::is_ascii_digit).collect();
+        Some(Self::new(major.parse().ok()?, minor.parse().ok()?))
+    }
+
+    /// Returns the installed tmux version, probing it once per process
+    pub fn detect() -> Result<Self, TmuxError> {
+        static DETECTED: OnceLock<TmuxVersion> = OnceLock::new();
+
+        Self::cached(&DETECTED, || {
+            let output = tmux_command().arg("-V").output()?;
+            if !output.status.success() {
+                return Err(TmuxError::ParseError(
+                    String::from_utf8_lossy(&output.stderr).to_string(),
+                ));
+            }
+            Ok(Self::parse(&String::from_utf8_lossy(&output.stdout)).unwrap_or(Self::DEVELOPMENT))
+        })
+    }
+
+    /// Returns the version in `cache`, filling it from `probe` if it is
+    /// empty; a failed probe is not cached and is retried on the next call
+    fn cached(
+        cache: &OnceLock<Self>,
+        probe: impl FnOnce() -> Result<Self, TmuxError>,
+    ) -> Result<Self, TmuxError> {
+        if let Some(version) = cache.get() {
+            return Ok(*version);
+        }
+        let version = probe()?;
+        Ok(*cache.get_or_init(|| version))
+    }
+
+    /// Whether this release has a feature
+    #[must_use]
+    pub fn supports(self, feature: Feature) -> bool {
+        self >= feature.required_version()
+    }
+}
+
+impl std::fmt::Display for TmuxVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if *self == Self::DEVELOPMENT {
+            write!(f, "development build")
+        } else {
+            write!(f, "{}.{}", self.major, self.minor)
+        }
+    }
+}
+
+/// Optional tmux features that depend on the release
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Feature {
+    /// `-f` filters on `list-sessions` and friends
+    ListFilter,
+    /// `-e` environment variables on `new-session`
+    NewSessionEnvironment,
+    /// Percentages in `split-window -l`
+    PercentageSize,
+}
+
+impl Feature {
+    /// Every feature, for reporting
+    pub const ALL: [Self; 3] = [
+        Self::ListFilter,
+        Self::NewSessionEnvironment,
+        Self::PercentageSize,
+    ];
+
+    /// Oldest tmux release that has the feature
+    #[must_use]
+    pub const fn required_version(self) -> TmuxVersion {
+        match self {
+            Self::ListFilter | Self::PercentageSize => TmuxVersion::new(3, 1),
+            Self::NewSessionEnvironment => TmuxVersion::new(3, 2),
+        }
+    }
+}
+
+impl std::fmt::Display for Feature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ListFilter => write!(f, "list filters (-f)"),
+            Self::NewSessionEnvironment => write!(f, "new-session -e"),
+            Self::PercentageSize => write!(f, "split-window -l <percent>%"),
+        }
+    }
+}
+
+/// Fails with `TmuxError::Unsupported` when the installed tmux lacks a
+/// feature that has no fallback
+pub fn require(feature: Feature) -> Result<(), TmuxError> {
+    if TmuxVersion::detect()?.supports(feature) {
+        Ok(())
+    } else {
+        Err(TmuxError::Unsupported {
+            feature,
+            required_version: feature.required_version(),
+        })
+    }
+}
+
+/// Which tmux server to talk to
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum Socket {
+    /// The default server
+    #[default]
+    Default,
+    /// A named socket in the tmux socket directory (`tmux -L`)
+    Name(String),
+    /// A socket at an explicit path (`tmux -S`)
+    Path(PathBuf),
+}
+
+impl Socket {
+    /// Parses a server label: `default`, a path if it contains `/`, or
+    /// otherwise a socket name
+    #[must_use]
+    pub fn from_label(label: &str) -> Self {
+        if label.is_empty() || label == "default" {
+            Self::Default
+        } else if label.contains('/') {
+            Self::Path(PathBuf::from(label))
+        } else {
+            Self::Name(label.to_string())
+        }
+    }
+
+    fn args(&self) -> Vec<OsString> {
+        match self {
+            Self::Default => Vec::new(),
+            Self::Name(name) => vec!["-L".into(), name.into()],
+            Self::Path(path) => vec!["-S".into(), path.into()],
+        }
+    }
+}
+
+impl std::fmt::Display for Socket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::Name(name) => write!(f, "{name}"),
+            Self::Path(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// A remote machine whose tmux is reached over SSH
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Host {
+    /// Name the host is configured under
+    pub name: String,
+    /// SSH destination, such as `me@devbox`
+    pub destination: String,
+    /// Program used to reach the host, normally `ssh`
+    pub command: String,
+    /// Arguments passed to the program before the destination
+    pub args: Vec<String>,
+}
+
+impl Host {
+    /// Label for sessions on the local machine in aggregated listings
+    pub const LOCAL: &'static str = "local";
+
+    /// Creates a host reached with plain `ssh <destination>`
+    #[must_use]
+    pub fn new(name: &str, destination: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            destination: destination.to_string(),
+            command: "ssh".to_string(),
+            args: Vec::new(),
+        }
+    }
+}
+
+static SOCKET: RwLock<Socket> = RwLock::new(Socket::Default);
+static HOST: RwLock<Option<Host>> = RwLock::new(None);
+static AGENT_COMMANDS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Sets the foreground commands that identify the pane an agent runs in
+/// when sessions are listed
+pub fn set_agent_commands(commands: Vec<String>) {
+    *AGENT_COMMANDS
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = commands;
+}
+
+fn agent_commands() -> Vec<String> {
+    AGENT_COMMANDS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// Selects the server every tmux command in this process talks to
+pub fn set_socket(socket: Socket) {
+    *SOCKET.write().unwrap_or_else(PoisonError::into_inner) = socket;
+}
+
+/// Returns the server tmux commands talk to
+#[must_use]
+pub fn socket() -> Socket {
+    SOCKET
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// Selects the host every tmux command in this process runs on; `None` is
+/// the local machine
+pub fn set_host(host: Option<Host>) {
+    *HOST.write().unwrap_or_else(PoisonError::into_inner) = host;
+}
+
+/// Returns the host tmux commands run on, if not the local machine
+#[must_use]
+pub fn host() -> Option<Host> {
+    HOST.read().unwrap_or_else(PoisonError::into_inner).clone()
+}
+
+/// A tmux invocation, run locally or through SSH on a remote host
+#[derive(Debug, Clone)]
+pub(crate) struct TmuxCommand {
+    host: Option<Host>,
+    interactive: bool,
+    args: Vec<OsString>,
 }
 
+impl TmuxCommand {
+    fn new(host: Option<Host>, socket: &Socket, interactive: bool) -> Self {
+        // `-u` keeps tmux from replacing the tabs in `-F` output with `_`
+        // when the locale is not UTF-8; attached clients keep the terminal's
+        let mut args = if interactive {
+            Vec::new()
+        } else {
+            vec![OsString::from("-u")]
+        };
+        args.extend(socket.args());
+        Self {
+            host,
+            interactive,
+            args,
+        }
+    }
+
+    /// Adds an argument
+    pub(crate) fn arg(&mut self, arg: impl AsRef<OsStr>) -> &mut Self {
+        self.args.push(arg.as_ref().to_os_string());
+        self
+    }
+
+    /// Adds several arguments
+    pub(crate) fn args<I, S>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        for arg in args {
+            self.arg(arg);
+        }
+        self
+    }
+
+    /// Builds the process to run: `tmux ...` locally, or
+    /// `ssh [-t] <destination> 'tmux ...'` for a remote host
+    pub(crate) fn command(&self) -> Command {
+        let Some(host) = &self.host else {
+            let mut command = Command::new("tmux");
+            command.args(&self.args);
+            return command;
+        };
+
+        // The remote shell splits the command line again, so every
+        // argument is quoted
+        let remote = std::iter::once("tmux".to_string())
+            .chain(
+                self.args
+                    .iter()
+                    .map(|arg| shell::quote(&arg.to_string_lossy())),
+            )
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let mut command = Command::new(&host.command);
+        command.args(&host.args);
+        if self.interactive {
+            command.arg("-t");
+        }
+        command.arg(&host.destination).arg(remote);
+        command
+    }
+
+    /// Runs the command and collects its output
+    pub(crate) fn output(&self) -> std::io::Result<Output> {
+        self.command().output()
+    }
+
+    /// Runs the command with `input` on its standard input and collects its
+    /// output
+    pub(crate) fn output_with_input(&self, input: &str) -> std::io::Result<Output> {
+        let mut child = self
+            .command()
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(input.as_bytes())?;
+        }
+        child.wait_with_output()
+    }
+
+    /// Runs the command attached to the terminal
+    pub(crate) fn status(&self) -> std::io::Result<ExitStatus> {
+        self.command().status()
+    }
+}
+
+/// Builds a tmux command for non-interactive use on the selected host and
+/// server
+pub(crate) fn tmux_command() -> TmuxCommand {
+    TmuxCommand::new(host(), &socket(), false)
+}
+
+/// Builds a tmux command for non-interactive use on the selected host,
+/// talking to `socket` instead of the selected server
+pub(crate) fn tmux_command_on(socket: &Socket) -> TmuxCommand {
+    TmuxCommand::new(host(), socket, false)
+}
+
+/// Builds a process running a shell script on the selected host: `sh -c`
+/// locally, or `ssh <destination> <script>` for a remote host
+#[must_use]
+pub fn shell_command(script: &str) -> Command {
+    match host() {
+        None => {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(script);
+            command
+        }
+        Some(host) => {
+            let mut command = Command::new(&host.command);
+            command.args(&host.args).arg(&host.destination).arg(script);
+            command
+        }
+    }
+}
+
+/// Pane fields shared by `PANE_FORMAT` and `SESSION_FORMAT`, path last so
+/// it may contain tabs
+macro_rules! pane_fields {
+    () => {
+        "#{session_name}\t#{window_index}\t#{window_active}\t#{pane_index}\t#{pane_active}\t#{pane_pid}\t#{pane_width}\t#{pane_height}\t#{window_activity}\t#{pane_current_command}\t#{pane_current_path}"
+    };
+}
+
+/// Number of fields in `pane_fields!`
+const PANE_FIELDS: usize = 11;
+
+/// Format string whose output `parse_panes` understands
+const PANE_FORMAT: &str = pane_fields!();
+
+/// Format string for `LIST_SESSIONS` whose output `parse_sessions`
+/// understands: one line per pane, led by its session's fields and the ams
+/// user options
+pub(crate) const SESSION_FORMAT: &str = concat!(
+    "#{session_attached}\t#{session_created}\t#{session_windows}\t#{@ams-tags}\t#{@ams-note}\t",
+    "#{@ams-agent}\t#{@ams-profile}\t#{@ams-created-by}\t#{@ams-task}\t",
+    pane_fields!()
+);
+
+/// Number of session fields leading each `SESSION_FORMAT` line
+const SESSION_FIELDS: usize = 9;
+
+/// Command that lists sessions with `SESSION_FORMAT`; sessions are read
+/// from their panes so each pane's directory and activity is known
+pub(crate) const LIST_SESSIONS: [&str; 3] = ["list-panes", "-a", "-F"];
+
 /// Lists all tmux sessions with their metadata
 pub fn list_sessions() -> Result<Vec<Session>, TmuxError> {
-    let output = Command::new("tmux")
-        .args([
-            "list-sessions",
-            "-F",
-            "#{session_name}\t#{session_attached}\t#{session_activity}\t#{session_created}\t#{pane_current_path}\t#{session_windows}",
-        ])
-        .output()?;
+    list_sessions_for(&socket())
+}
+
+/// Hosts and servers whose sessions are listed together
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Aggregate {
+    /// Hosts to query, `None` being the local machine; empty means only
+    /// the selected host
+    pub hosts: Vec<Option<Host>>,
+    /// Servers to query on each host; empty means only the selected server
+    pub servers: Vec<Socket>,
+}
+
+impl Aggregate {
+    /// Whether nothing beyond the selected host and server is listed
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty() && self.servers.is_empty()
+    }
+
+    /// Lists the windows of a session returned by `list_sessions_on`, on
+    /// the host and server it was listed from
+    pub fn list_windows(&self, session: &Session) -> Result<Vec<Window>, TmuxError> {
+        list_windows_with(self.command_for(session), &session.name)
+    }
+
+    /// Lists the panes of a session returned by `list_sessions_on`, on the
+    /// host and server it was listed from
+    pub fn list_panes(&self, session: &Session) -> Result<Vec<Pane>, TmuxError> {
+        list_panes_with(self.command_for(session), &session.name)
+    }
+
+    fn command_for(&self, session: &Session) -> TmuxCommand {
+        let host = session
+            .host
+            .as_deref()
+            .and_then(|label| {
+                self.hosts
+                    .iter()
+                    .find(|host| host.as_ref().map_or(Host::LOCAL, |host| &host.name) == label)
+            })
+            .cloned()
+            .unwrap_or_else(host);
+        let socket = session
+            .server
+            .as_deref()
+            .map_or_else(socket, Socket::from_label);
+        TmuxCommand::new(host, &socket, false)
+    }
+}
+
+/// Sessions of several hosts and servers, with those that could not be
+/// listed
+#[derive(Debug, Default)]
+pub struct Listing {
+    /// Sessions of every host and server that answered
+    pub sessions: Vec<Session>,
+    /// Hosts and servers that failed, labelled like `devbox`, `agents` or
+    /// `agents on devbox`, with their error
+    pub failures: Vec<(String, TmuxError)>,
+}
+
+/// Lists the sessions of several hosts and servers, labelling each with
+/// the host and server it runs on
+///
+/// A host or server that fails does not hide the sessions of the others: it
+/// is reported in the listing's failures. Only when every one fails is the
+/// first error returned.
+pub fn list_sessions_on(aggregate: &Aggregate) -> Result<Listing, TmuxError> {
+    let hosts = if aggregate.hosts.is_empty() {
+        vec![host()]
+    } else {
+        aggregate.hosts.clone()
+    };
+    let servers = if aggregate.servers.is_empty() {
+        vec![socket()]
+    } else {
+        aggregate.servers.clone()
+    };
+
+    let mut listing = Listing::default();
+    let mut answered = false;
+    for host in &hosts {
+        let host_label = (!aggregate.hosts.is_empty()).then(|| {
+            host.as_ref()
+                .map_or(Host::LOCAL, |host| &host.name)
+                .to_string()
+        });
+        for socket in &servers {
+            let server_label = (!aggregate.servers.is_empty()).then(|| socket.to_string());
+            let command = TmuxCommand::new(host.clone(), socket, false);
+            match list_sessions_with(command) {
+                Ok(sessions) => {
+                    answered = true;
+                    listing
+                        .sessions
+                        .extend(sessions.into_iter().map(|mut session| {
+                            session.host.clone_from(&host_label);
+                            session.server.clone_from(&server_label);
+                            session
+                        }));
+                }
+                Err(e) => {
+                    let label = match (&server_label, &host_label) {
+                        (Some(server), Some(host)) => format!("{server} on {host}"),
+                        (Some(label), None) | (None, Some(label)) => label.clone(),
+                        (None, None) => socket.to_string(),
+                    };
+                    listing.failures.push((label, e));
+                }
+            }
+        }
+    }
+
+    if !answered && !listing.failures.is_empty() {
+        return Err(listing.failures.remove(0).1);
+    }
+    Ok(listing)
+}
+
+fn list_sessions_for(socket: &Socket) -> Result<Vec<Session>, TmuxError> {
+    list_sessions_with(TmuxCommand::new(host(), socket, false))
+}
+
+fn list_sessions_with(mut command: TmuxCommand) -> Result<Vec<Session>, TmuxError> {
+    let output = command.args(LIST_SESSIONS).arg(SESSION_FORMAT).output()?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        if stderr.contains("no server running") || stderr.contains("no sessions") {
+        if no_server(&stderr) || stderr.contains("no sessions") {
             return Ok(Vec::new());
         }
         return Err(TmuxError::ParseError(stderr.to_string()));
@@ -52,79 +598,129 @@ pub fn list_sessions() -> Result<Vec<Session>, TmuxError> {
     parse_sessions(&stdout)
 }
 
-fn parse_sessions(output: &str) -> Result<Vec<Session>, TmuxError> {
-    let mut sessions = Vec::new();
+/// Parses `SESSION_FORMAT` output, hiding the control-mode helper session
+///
+/// Each session takes its working directory and last activity from its
+/// agent pane, see `Session::select_agent_pane`.
+pub(crate) fn parse_sessions(output: &str) -> Result<Vec<Session>, TmuxError> {
+    let mut sessions: Vec<Session> = Vec::new();
 
     for line in output.lines() {
         if line.trim().is_empty() {
             continue;
         }
 
-        let parts: Vec<&str> = line.split('\t').collect();
-        if parts.len() < 6 {
-            return Err(TmuxError::ParseError(format!(
-                "Expected 6 fields, got {}: {}",
-                parts.len(),
-                line
-            )));
+        let parts = split_fields(line, SESSION_FIELDS + PANE_FIELDS)?;
+        let pane = parse_pane(&parts[SESSION_FIELDS..])?;
+        if pane.session == CONTROL_SESSION {
+            continue;
         }
 
-        let name = parts[0].to_string();
-
-        let attached_count: u32 = parts[1]
-            .parse()
-            .map_err(|_| TmuxError::ParseError(format!("Invalid attached count: {}", parts[1])))?;
+        if let Some(session) = sessions
+            .iter_mut()
+            .find(|session| session.name == pane.session)
+        {
+            session.panes.push(pane);
+            continue;
+        }
 
+        let attached_count = parse_number(parts[0], "attached count")?;
         let status = if attached_count > 0 {
             SessionStatus::Active
         } else {
             SessionStatus::Idle
         };
 
-        let activity_epoch: i64 = parts[2].parse().map_err(|_| {
-            TmuxError::ParseError(format!("Invalid activity timestamp: {}", parts[2]))
-        })?;
+        sessions.push(Session {
+            name: pane.session.clone(),
+            status,
+            working_directory: pane.current_path.clone(),
+            last_activity: pane.last_activity,
+            created_at: parse_epoch(parts[1], "created")?,
+            window_count: parse_number(parts[2], "window count")?,
+            panes: vec![pane],
+            tags: metadata::parse_tags(parts[3]),
+            note: non_empty(parts[4]),
+            agent: non_empty(parts[5]),
+            profile: non_empty(parts[6]),
+            created_by: non_empty(parts[7]),
+            task: parts[8].parse().ok(),
+            ..Session::default()
+        });
+    }
 
-        let created_epoch: i64 = parts[3].parse().map_err(|_| {
-            TmuxError::ParseError(format!("Invalid created timestamp: {}", parts[3]))
-        })?;
+    let agent_commands = agent_commands();
+    for session in &mut sessions {
+        session.select_agent_pane(&agent_commands);
+    }
 
-        let last_activity = Utc
-            .timestamp_opt(activity_epoch, 0)
-            .single()
-            .ok_or_else(|| {
-                TmuxError::ParseError(format!("Invalid activity epoch: {activity_epoch}"))
-            })?;
+    Ok(sessions)
+}
 
-        let created_at = Utc
-            .timestamp_opt(created_epoch, 0)
-            .single()
-            .ok_or_else(|| {
-                TmuxError::ParseError(format!("Invalid created epoch: {created_epoch}"))
-            })?;
+/// Sets a user option such as `@ams-tags` on a session, or unsets it when
+/// `value` is `None`
+pub fn set_session_option(
+    session: &str,
+    option: &str,
+    value: Option<&str>,
+) -> Result<(), TmuxError> {
+    set_session_options(session, &[(option, value)])
+}
 
-        let working_directory = PathBuf::from(parts[4]);
+/// Sets or unsets several user options of a session with one tmux command
+pub fn set_session_options(
+    session: &str,
+    options: &[(&str, Option<&str>)],
+) -> Result<(), TmuxError> {
+    let mut cmd = tmux_command();
+    for (i, (option, value)) in options.iter().enumerate() {
+        if i > 0 {
+            cmd.arg(";");
+        }
+        match value {
+            Some(value) => cmd.args(["set-option", "-t", session, option, &literal(value)]),
+            None => cmd.args(["set-option", "-u", "-t", session, option]),
+        };
+    }
 
-        let window_count: u32 = parts[5]
-            .parse()
-            .map_err(|_| TmuxError::ParseError(format!("Invalid window count: {}", parts[5])))?;
+    let output = cmd.output()?;
 
-        sessions.push(Session {
-            name,
-            status,
-            working_directory,
-            last_activity,
-            created_at,
-            window_count,
-        });
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("can't find") || no_server(&stderr) {
+            return Err(TmuxError::SessionNotFound(session.to_string()));
+        }
+        return Err(TmuxError::ParseError(stderr.to_string()));
     }
 
-    Ok(sessions)
+    Ok(())
+}
+
+/// Reports whether a tmux server is reachable
+pub fn server_running() -> Result<bool, TmuxError> {
+    let output = tmux_command().arg("has-session").output()?;
+
+    if output.status.success() {
+        return Ok(true);
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Ok(!no_server(&stderr))
+}
+
+/// Whether tmux stderr says that no server is listening on the socket
+fn no_server(stderr: &str) -> bool {
+    stderr.contains("no server running") || stderr.contains("error connecting")
 }
 
 /// Attaches to an existing tmux session
+///
+/// The target may also name a window or pane (`session:window.pane`), in which
+/// case that window and pane are made current on attach.
+///
+/// On a remote host this runs `ssh -t`, so the session gets a terminal.
 pub fn attach_session(name: &str) -> Result<(), TmuxError> {
-    let status = Command::new("tmux")
+    let status = TmuxCommand::new(host(), &socket(), true)
         .args(["attach-session", "-t", name])
         .status()?;
 
@@ -137,14 +733,34 @@ pub fn attach_session(name: &str) -> Result<(), TmuxError> {
 
 /// Creates a new tmux session
 pub fn create_session(name: &str, directory: Option<&str>) -> Result<(), TmuxError> {
-    let mut cmd = Command::new("tmux");
-    cmd.args(["new-session", "-d", "-s", name]);
+    create_session_with_env(name, directory, &BTreeMap::new())
+}
 
+/// Creates a new tmux session with environment variables set in it
+pub fn create_session_with_env(
+    name: &str,
+    directory: Option<&str>,
+    env: &BTreeMap<String, String>,
+) -> Result<(), TmuxError> {
+    let mut args = vec![
+        "new-session".to_string(),
+        "-d".to_string(),
+        "-s".to_string(),
+    ];
+    args.push(name.to_string());
     if let Some(dir) = directory {
-        cmd.args(["-c", dir]);
+        args.extend(["-c".to_string(), dir.to_string()]);
+    }
+    if !env.is_empty() {
+        require(Feature::NewSessionEnvironment)?;
+        for (key, value) in env {
+            args.extend(["-e".to_string(), format!("{key}={value}")]);
+        }
+        // Values may be secrets, so they are kept off the command line
+        return run_from_stdin(&args).map(drop);
     }
 
-    let output = cmd.output()?;
+    let output = tmux_command().args(&args).output()?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -159,9 +775,7 @@ pub fn create_session(name: &str, directory: Option<&str>) -> Result<(), TmuxErr
 
 /// Kills a tmux session
 pub fn kill_session(name: &str) -> Result<(), TmuxError> {
-    let output = Command::new("tmux")
-        .args(["kill-session", "-t", name])
-        .output()?;
+    let output = tmux_command().args(["kill-session", "-t", name]).output()?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -174,22 +788,158 @@ pub fn kill_session(name: &str) -> Result<(), TmuxError> {
     Ok(())
 }
 
+/// Detaches every client attached to a session
+pub fn detach_session(name: &str) -> Result<(), TmuxError> {
+    match run(&["detach-client", "-s", name]) {
+        Err(TmuxError::ParseError(stderr)) if stderr.contains("can't find session") => {
+            Err(TmuxError::SessionNotFound(name.to_string()))
+        }
+        result => result.map(drop),
+    }
+}
+
+/// Runs a tmux command on the selected host and server, returning its
+/// output without the trailing newline
+pub(crate) fn run<S: AsRef<OsStr>>(args: &[S]) -> Result<String, TmuxError> {
+    command_output(&tmux_command().args(args).output()?)
+}
+
+/// Runs a tmux command read from standard input (`source-file -`) rather
+/// than the command line, so its arguments, such as secret `-e` values,
+/// never show in the process list or on an ssh command line
+pub(crate) fn run_from_stdin<S: AsRef<str>>(args: &[S]) -> Result<String, TmuxError> {
+    let line = args
+        .iter()
+        .map(|arg| config_quote(arg.as_ref()))
+        .collect::<Vec<_>>()
+        .join(" ");
+    // Like `new-session` on the command line, start a server if needed
+    let output = tmux_command()
+        .args(["start-server", ";", "source-file", "-"])
+        .output_with_input(&(line + "\n"))?;
+    command_output(&output)
+}
+
+/// Quotes an argument for a tmux command file, escaping everything tmux
+/// would otherwise expand or split on
+fn config_quote(arg: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+        match c {
+            '\\' | '"' | '$' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Returns the standard output of a finished tmux command, or its error
+fn command_output(output: &Output) -> Result<String, TmuxError> {
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if let Some(name) = stderr.trim().strip_prefix("duplicate session: ") {
+            return Err(TmuxError::SessionExists(name.to_string()));
+        }
+        if no_server(&stderr) {
+            return Err(TmuxError::ServerNotRunning);
+        }
+        return Err(TmuxError::ParseError(stderr.to_string()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim_end_matches('\n')
+        .to_string())
+}
+
+/// Types text into a pane, followed by Enter unless `enter` is false
+pub fn send_keys(target: &str, text: &str, enter: bool) -> Result<(), TmuxError> {
+    let mut cmd = tmux_command();
+    // `-l` sends the text literally instead of looking up key names
+    cmd.args(["send-keys", "-t", target, "-l", &literal(text)]);
+    if enter {
+        cmd.args([";", "send-keys", "-t", target, "Enter"]);
+    }
+
+    let output = cmd.output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("can't find") || no_server(&stderr) {
+            return Err(TmuxError::SessionNotFound(target.to_string()));
+        }
+        return Err(TmuxError::ParseError(stderr.to_string()));
+    }
+
+    Ok(())
+}
+
+/// Pastes text into a pane as a bracketed paste, so applications that
+/// support it take line breaks as part of the text instead of as Enter
+pub fn paste_text(target: &str, text: &str) -> Result<(), TmuxError> {
+    let mut cmd = tmux_command();
+    cmd.args(["set-buffer", "-b", PASTE_BUFFER, "--", &literal(text)]);
+    cmd.args([
+        ";",
+        "paste-buffer",
+        "-p",
+        "-d",
+        "-b",
+        PASTE_BUFFER,
+        "-t",
+        target,
+    ]);
+
+    let output = cmd.output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("can't find") || no_server(&stderr) {
+            return Err(TmuxError::SessionNotFound(target.to_string()));
+        }
+        return Err(TmuxError::ParseError(stderr.to_string()));
+    }
+
+    Ok(())
+}
+
+/// Name of the paste buffer used by [`paste_text`], deleted after pasting
+const PASTE_BUFFER: &str = "ams-paste";
+
+/// Escapes a trailing semicolon, which tmux would take as a command
+/// separator and drop
+fn literal(text: &str) -> String {
+    text.strip_suffix(';')
+        .map_or_else(|| text.to_string(), |rest| format!("{rest}\\;"))
+}
+
 /// Gets information about a specific session
+///
+/// Filters in tmux where supported, and falls back to listing every session
+/// on releases without `-f`.
 pub fn get_session(name: &str) -> Result<Session, TmuxError> {
+    if !TmuxVersion::detect()?.supports(Feature::ListFilter) {
+        return list_sessions()?
+            .into_iter()
+            .find(|session| session.name == name)
+            .ok_or_else(|| TmuxError::SessionNotFound(name.to_string()));
+    }
+
     let filter = format!("#{{==:#{{session_name}},{name}}}");
-    let output = Command::new("tmux")
-        .args([
-            "list-sessions",
-            "-F",
-            "#{session_name}\t#{session_attached}\t#{session_activity}\t#{session_created}\t#{pane_current_path}\t#{session_windows}",
-            "-f",
-            &filter,
-        ])
+    let output = tmux_command()
+        .args(LIST_SESSIONS)
+        .args([SESSION_FORMAT, "-f", &filter])
         .output()?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        if stderr.contains("no server running") || stderr.contains("no sessions") {
+        if no_server(&stderr) || stderr.contains("no sessions") {
             return Err(TmuxError::SessionNotFound(name.to_string()));
         }
         return Err(TmuxError::ParseError(stderr.to_string()));
@@ -204,9 +954,602 @@ pub fn get_session(name: &str) -> Result<Session, TmuxError> {
         .ok_or_else(|| TmuxError::SessionNotFound(name.to_string()))
 }
 
+/// Format string whose output `parse_windows` understands
+const WINDOW_FORMAT: &str = "#{session_name}\t#{window_index}\t#{window_active}\t#{window_panes}\t#{window_width}\t#{window_height}\t#{window_name}";
+
+/// Lists the windows of a session
+pub fn list_windows(session: &str) -> Result<Vec<Window>, TmuxError> {
+    list_windows_with(tmux_command(), session)
+}
+
+fn list_windows_with(mut command: TmuxCommand, session: &str) -> Result<Vec<Window>, TmuxError> {
+    let output = command
+        .args(["list-windows", "-t", session, "-F", WINDOW_FORMAT])
+        .output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("can't find") || no_server(&stderr) {
+            return Err(TmuxError::SessionNotFound(session.to_string()));
+        }
+        return Err(TmuxError::ParseError(stderr.to_string()));
+    }
+
+    parse_windows(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Lists the panes of every window of a session
+pub fn list_panes(session: &str) -> Result<Vec<Pane>, TmuxError> {
+    list_panes_with(tmux_command(), session)
+}
+
+fn list_panes_with(mut command: TmuxCommand, session: &str) -> Result<Vec<Pane>, TmuxError> {
+    let output = command
+        .args(["list-panes", "-s", "-t", session, "-F", PANE_FORMAT])
+        .output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("can't find") || no_server(&stderr) {
+            return Err(TmuxError::SessionNotFound(session.to_string()));
+        }
+        return Err(TmuxError::ParseError(stderr.to_string()));
+    }
+
+    parse_panes(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Splits a line into `count` tab-separated fields, the last of which may
+/// itself contain tabs
+fn split_fields(line: &str, count: usize) -> Result<Vec<&str>, TmuxError> {
+    let parts: Vec<&str> = line.splitn(count, '\t').collect();
+    if parts.len() < count {
+        return Err(TmuxError::ParseError(format!(
+            "Expected {count} fields, got {}: {line}",
+            parts.len()
+        )));
+    }
+    Ok(parts)
+}
+
+/// Returns a user option's value, or `None` if it is unset or empty
+fn non_empty(value: &str) -> Option<String> {
+    Some(value.to_string()).filter(|value| !value.is_empty())
+}
+
+fn parse_number(value: &str, what: &str) -> Result<u32, TmuxError> {
+    value
+        .parse()
+        .map_err(|_| TmuxError::ParseError(format!("Invalid {what}: {value}")))
+}
+
+fn parse_epoch(value: &str, what: &str) -> Result<DateTime<Utc>, TmuxError> {
+    let epoch: i64 = value
+        .parse()
+        .map_err(|_| TmuxError::ParseError(format!("Invalid {what} timestamp: {value}")))?;
+    Utc.timestamp_opt(epoch, 0)
+        .single()
+        .ok_or_else(|| TmuxError::ParseError(format!("Invalid {what} epoch: {epoch}")))
+}
+
+/// Parses the `pane_fields!` of one line
+fn parse_pane(parts: &[&str]) -> Result<Pane, TmuxError> {
+    Ok(Pane {
+        session: parts[0].to_string(),
+        window_index: parse_number(parts[1], "window index")?,
+        window_active: parts[2] == "1",
+        index: parse_number(parts[3], "pane index")?,
+        active: parts[4] == "1",
+        pid: parse_number(parts[5], "pane pid")?,
+        width: parse_number(parts[6], "pane width")?,
+        height: parse_number(parts[7], "pane height")?,
+        last_activity: parse_epoch(parts[8], "activity")?,
+        current_command: parts[9].to_string(),
+        current_path: PathBuf::from(parts[10]),
+    })
+}
+
+fn parse_windows(output: &str) -> Result<Vec<Window>, TmuxError> {
+    output
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let parts = split_fields(line, 7)?;
+            Ok(Window {
+                session: parts[0].to_string(),
+                index: parse_number(parts[1], "window index")?,
+                active: parts[2] == "1",
+                pane_count: parse_number(parts[3], "pane count")?,
+                width: parse_number(parts[4], "window width")?,
+                height: parse_number(parts[5], "window height")?,
+                name: parts[6].to_string(),
+            })
+        })
+        .collect()
+}
+
+fn parse_panes(output: &str) -> Result<Vec<Pane>, TmuxError> {
+    output
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| parse_pane(&split_fields(line, PANE_FIELDS)?))
+        .collect()
+}
+
+/// Lists every pane of every session
+pub fn list_pane_targets() -> Result<Vec<PaneTarget>, TmuxError> {
+    let output = tmux_command()
+        .args([
+            "list-panes",
+            "-a",
+            "-F",
+            "#{session_name}\t#{window_index}\t#{pane_index}",
+        ])
+        .output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if no_server(&stderr) || stderr.contains("no sessions") {
+            return Ok(Vec::new());
+        }
+        return Err(TmuxError::ParseError(stderr.to_string()));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    parse_pane_targets(&stdout)
+}
+
+/// Parses `session\twindow\tpane` lines, hiding the panes of the
+/// control-mode helper session
+fn parse_pane_targets(output: &str) -> Result<Vec<PaneTarget>, TmuxError> {
+    let mut targets = Vec::new();
+
+    for line in output.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let parts: Vec<&str> = line.split('\t').collect();
+        if parts.len() < 3 {
+            return Err(TmuxError::ParseError(format!(
+                "Expected 3 fields, got {}: {}",
+                parts.len(),
+                line
+            )));
+        }
+
+        let window_index: u32 = parts[1]
+            .parse()
+            .map_err(|_| TmuxError::ParseError(format!("Invalid window index: {}", parts[1])))?;
+
+        let pane_index: u32 = parts[2]
+            .parse()
+            .map_err(|_| TmuxError::ParseError(format!("Invalid pane index: {}", parts[2])))?;
+
+        if parts[0] == CONTROL_SESSION {
+            continue;
+        }
+        targets.push(PaneTarget {
+            session: parts[0].to_string(),
+            window_index,
+            pane_index,
+        });
+    }
+
+    Ok(targets)
+}
+
+/// Captures the full scrollback history of a pane as plain text
+pub fn capture_pane(target: &str) -> Result<String, TmuxError> {
+    capture(target, &["-J", "-S", "-"])
+}
+
+/// Captures the full scrollback history of a pane, keeping escape sequences
+/// for colors and text attributes
+pub fn capture_pane_with_escapes(target: &str) -> Result<String, TmuxError> {
+    capture(target, &["-J", "-S", "-", "-e"])
+}
+
+/// Captures only the visible screen of a pane as plain text
+pub fn capture_screen(target: &str) -> Result<String, TmuxError> {
+    capture(target, &[])
+}
+
+fn capture(target: &str, extra_args: &[&str]) -> Result<String, TmuxError> {
+    let output = tmux_command()
+        .args(["capture-pane", "-p", "-t", target])
+        .args(extra_args)
+        .output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("can't find") {
+            return Err(TmuxError::SessionNotFound(target.to_string()));
+        }
+        return Err(TmuxError::ParseError(stderr.to_string()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Expands a tmux format string in the context of a session, window or pane
+pub fn display_message(target: &str, format: &str) -> Result<String, TmuxError> {
+    let output = tmux_command()
+        .args(["display-message", "-p", "-t", target, format])
+        .output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("can't find") || no_server(&stderr) {
+            return Err(TmuxError::SessionNotFound(target.to_string()));
+        }
+        return Err(TmuxError::ParseError(stderr.to_string()));
+    }
+
+    // Only the newline is stripped; empty trailing fields are significant
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim_end_matches('\n')
+        .to_string())
+}
+
+/// Pipes a pane's output to a shell command, or stops piping when `command`
+/// is `None`
+pub fn pipe_pane(target: &str, command: Option<&str>) -> Result<(), TmuxError> {
+    let mut cmd = tmux_command();
+    cmd.args(["pipe-pane", "-t", target]);
+
+    if let Some(command) = command {
+        cmd.arg(command);
+    }
+
+    let output = cmd.output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("can't find") || no_server(&stderr) {
+            return Err(TmuxError::SessionNotFound(target.to_string()));
+        }
+        return Err(TmuxError::ParseError(stderr.to_string()));
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_socket_from_label() {
+        assert_eq!(Socket::from_label("default"), Socket::Default);
+        assert_eq!(
+            Socket::from_label("agents"),
+            Socket::Name("agents".to_string())
+        );
+        assert_eq!(
+            Socket::from_label("/tmp/agents.sock"),
+            Socket::Path(PathBuf::from("/tmp/agents.sock"))
+        );
+        for label in ["default", "agents", "/tmp/agents.sock"] {
+            assert_eq!(Socket::from_label(label).to_string(), label);
+        }
+    }
+
+    fn command_line(command: &TmuxCommand) -> Vec<String> {
+        let command = command.command();
+        std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    /// A host whose "ssh" is `sh`, running the remote command locally
+    fn loopback_host() -> Host {
+        Host {
+            name: "loopback".to_string(),
+            destination: "me@devbox".to_string(),
+            command: "sh".to_string(),
+            args: vec![
+                "-c".to_string(),
+                r#"[ "$1" = -t ] && shift; shift; exec sh -c "$1""#.to_string(),
+                "ssh".to_string(),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_tmux_command_for_socket() {
+        let args = |socket: &Socket| -> Vec<String> {
+            command_line(&TmuxCommand::new(None, socket, false))[1..].to_vec()
+        };
+        assert_eq!(args(&Socket::Default), vec!["-u"]);
+        assert_eq!(
+            args(&Socket::Name("agents".to_string())),
+            vec!["-u", "-L", "agents"]
+        );
+        assert_eq!(
+            args(&Socket::Path(PathBuf::from("/tmp/s"))),
+            vec!["-u", "-S", "/tmp/s"]
+        );
+    }
+
+    #[test]
+    fn test_tmux_command_over_ssh() {
+        let host = Host::new("devbox", "me@devbox");
+        let mut command = TmuxCommand::new(Some(host.clone()), &Socket::Default, false);
+        command.args([
+            "list-sessions",
+            "-F",
+            "#{session_name}\t#{pane_current_path}",
+        ]);
+        assert_eq!(
+            command_line(&command),
+            vec![
+                "ssh",
+                "me@devbox",
+                "tmux '-u' 'list-sessions' '-F' '#{session_name}\t#{pane_current_path}'"
+            ]
+        );
+
+        let mut command = TmuxCommand::new(Some(host), &Socket::Name("agents".to_string()), true);
+        command.args(["attach-session", "-t", "it's"]);
+        assert_eq!(
+            command_line(&command),
+            vec![
+                "ssh",
+                "-t",
+                "me@devbox",
+                r"tmux '-L' 'agents' 'attach-session' '-t' 'it'\''s'"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tmux_command_through_wrapper() {
+        let Ok(local) = Command::new("tmux").arg("-V").output() else {
+            return;
+        };
+        let remote = TmuxCommand::new(Some(loopback_host()), &Socket::Default, false)
+            .arg("-V")
+            .output()
+            .unwrap();
+        assert!(remote.status.success());
+        assert_eq!(remote.stdout, local.stdout);
+    }
+
+    #[test]
+    fn test_aggregate_is_empty() {
+        assert!(Aggregate::default().is_empty());
+        assert!(!Aggregate {
+            hosts: vec![None],
+            servers: Vec::new(),
+        }
+        .is_empty());
+    }
+
+    #[test]
+    fn test_version_parse() {
+        assert_eq!(
+            TmuxVersion::parse("tmux 3.3a\n"),
+            Some(TmuxVersion::new(3, 3))
+        );
+        assert_eq!(
+            TmuxVersion::parse("tmux next-3.5"),
+            Some(TmuxVersion::new(3, 5))
+        );
+        assert_eq!(
+            TmuxVersion::parse("tmux 2.9a"),
+            Some(TmuxVersion::new(2, 9))
+        );
+        assert_eq!(TmuxVersion::parse("tmux master"), None);
+        assert_eq!(TmuxVersion::parse("screen 4.0"), None);
+    }
+
+    #[test]
+    fn test_version_ordering_and_display() {
+        assert!(TmuxVersion::new(3, 10) > TmuxVersion::new(3, 9));
+        assert!(TmuxVersion::new(2, 9) < TmuxVersion::MINIMUM);
+        assert_eq!(TmuxVersion::new(3, 2).to_string(), "3.2");
+        assert_eq!(TmuxVersion::DEVELOPMENT.to_string(), "development build");
+    }
+
+    #[test]
+    fn test_version_supports() {
+        let v30 = TmuxVersion::new(3, 0);
+        let v31 = TmuxVersion::new(3, 1);
+        assert!(!v30.supports(Feature::ListFilter));
+        assert!(v31.supports(Feature::ListFilter));
+        assert!(!v31.supports(Feature::NewSessionEnvironment));
+        for feature in Feature::ALL {
+            assert!(TmuxVersion::DEVELOPMENT.supports(feature));
+        }
+    }
+
+    #[test]
+    fn test_version_detect_is_cached() {
+        let cache = OnceLock::new();
+        assert!(TmuxVersion::cached(&cache, || Err(TmuxError::ServerNotRunning)).is_err());
+        assert_eq!(cache.get(), None);
+
+        let probes = Cell::new(0);
+        let probe = || {
+            probes.set(probes.get() + 1);
+            Ok(TmuxVersion::new(3, 3))
+        };
+        assert_eq!(
+            TmuxVersion::cached(&cache, probe).unwrap(),
+            TmuxVersion::new(3, 3)
+        );
+        assert_eq!(
+            TmuxVersion::cached(&cache, probe).unwrap(),
+            TmuxVersion::new(3, 3)
+        );
+        assert_eq!(probes.get(), 1);
+    }
+
+    #[test]
+    fn test_no_server() {
+        assert!(no_server("no server running on /tmp/tmux-0/default\n"));
+        assert!(no_server(
+            "error connecting to /tmp/tmux-0/default (No such file or directory)\n"
+        ));
+        assert!(!no_server("can't find session: agent\n"));
+    }
+
+    #[test]
+    fn test_parse_pane_targets() {
+        let output = "agent\t0\t0\nagent\t0\t1\nother\t2\t0\n";
+        let targets = parse_pane_targets(output).unwrap();
+        assert_eq!(targets.len(), 3);
+        assert_eq!(targets[1].to_string(), "agent:0.1");
+        assert_eq!(targets[2].session, "other");
+        assert_eq!(targets[2].window_index, 2);
+    }
+
+    #[test]
+    fn test_parse_windows() {
+        let output = "agent\t0\t1\t2\t120\t40\tclaude\nagent\t1\t0\t1\t120\t40\tdev server\n";
+        let windows = parse_windows(output).unwrap();
+        assert_eq!(windows.len(), 2);
+        assert_eq!(windows[0].name, "claude");
+        assert!(windows[0].active);
+        assert_eq!(windows[0].pane_count, 2);
+        assert_eq!(windows[1].target(), "agent:1");
+        assert_eq!(windows[1].name, "dev server");
+        assert!(!windows[1].active);
+        assert_eq!((windows[1].width, windows[1].height), (120, 40));
+    }
+
+    #[test]
+    fn test_parse_windows_invalid() {
+        assert!(parse_windows("agent\t0\t1").is_err());
+        assert!(parse_windows("agent\tx\t1\t2\t120\t40\tclaude").is_err());
+        assert!(parse_windows("\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_panes() {
+        let output = "agent\t0\t1\t0\t1\t100\t60\t40\t1704067200\tclaude\t/src/app\n\
+                      agent\t0\t1\t1\t0\t101\t59\t40\t1704067200\tnode\t/src/app/with\ttab\n";
+        let panes = parse_panes(output).unwrap();
+        assert_eq!(panes.len(), 2);
+        assert!(panes[0].active);
+        assert_eq!(panes[0].current_command, "claude");
+        assert_eq!(panes[0].pid, 100);
+        assert!(panes[0].window_active);
+        assert_eq!(panes[0].last_activity.timestamp(), 1_704_067_200);
+        assert_eq!(panes[1].target().to_string(), "agent:0.1");
+        assert_eq!((panes[1].width, panes[1].height), (59, 40));
+        assert_eq!(panes[1].current_path, PathBuf::from("/src/app/with\ttab"));
+    }
+
+    #[test]
+    fn test_parse_panes_invalid() {
+        assert!(parse_panes("agent\t0\t0\t1").is_err());
+        assert!(parse_panes("agent\t0\t1\t0\t1\tpid\t60\t40\t1704067200\tsh\t/").is_err());
+    }
+
+    #[test]
+    fn test_aggregate_command_for_session() {
+        let devbox = Host::new("devbox", "me@devbox");
+        let aggregate = Aggregate {
+            hosts: vec![None, Some(devbox)],
+            servers: vec![Socket::Default, Socket::Name("agents".to_string())],
+        };
+        let mut session = parse_sessions(&session_line(
+            "agent",
+            "0",
+            "1704067200",
+            "1",
+            "1704067200",
+            "/src",
+        ))
+        .unwrap()
+        .remove(0);
+
+        session.host = Some("devbox".to_string());
+        session.server = Some("agents".to_string());
+        assert_eq!(
+            command_line(&aggregate.command_for(&session)),
+            vec!["ssh", "me@devbox", "tmux '-u' '-L' 'agents'"]
+        );
+
+        session.host = Some(Host::LOCAL.to_string());
+        session.server = Some("default".to_string());
+        assert_eq!(
+            command_line(&aggregate.command_for(&session)),
+            vec!["tmux", "-u"]
+        );
+    }
+
+    #[test]
+    fn test_parse_pane_targets_empty() {
+        assert!(parse_pane_targets("\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_pane_targets_invalid() {
+        assert!(parse_pane_targets("agent\t0").is_err());
+        assert!(parse_pane_targets("agent\tx\t0").is_err());
+        assert!(parse_pane_targets("agent\t0\tx").is_err());
+    }
+
+    /// A `SESSION_FORMAT` line for a session with one pane running bash
+    fn session_line(
+        name: &str,
+        attached: &str,
+        created: &str,
+        windows: &str,
+        activity: &str,
+        path: &str,
+    ) -> String {
+        format!("{attached}\t{created}\t{windows}\t\t\t\t\t\t\t{name}\t0\t1\t0\t1\t42\t80\t24\t{activity}\tbash\t{path}")
+    }
+
+    #[test]
+    fn test_list_sessions_on_reports_failed_hosts() {
+        let fake_host = |name: &str, script: String| Host {
+            name: name.to_string(),
+            destination: name.to_string(),
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), script],
+        };
+        let line = session_line("agent", "0", "1704067200", "1", "1704067200", "/repo");
+        let good = fake_host("devbox", format!("printf '%s\\n' '{line}'"));
+        let bad = fake_host(
+            "gpu",
+            "echo 'ssh: connect to host gpu' >&2; exit 255".to_string(),
+        );
+
+        let aggregate = Aggregate {
+            hosts: vec![Some(good), Some(bad.clone())],
+            servers: Vec::new(),
+        };
+        let listing = list_sessions_on(&aggregate).unwrap();
+        assert_eq!(listing.sessions.len(), 1);
+        assert_eq!(listing.sessions[0].host.as_deref(), Some("devbox"));
+        assert_eq!(listing.failures.len(), 1);
+        assert_eq!(listing.failures[0].0, "gpu");
+        assert!(listing.failures[0]
+            .1
+            .to_string()
+            .contains("connect to host gpu"));
+
+        let aggregate = Aggregate {
+            hosts: vec![Some(bad)],
+            servers: Vec::new(),
+        };
+        assert!(list_sessions_on(&aggregate).is_err());
+    }
+
+    /// A `SESSION_FORMAT` line for one pane of session `split`
+    fn pane_line(window: u32, pane: u32, active: bool, activity: i64, command: &str) -> String {
+        let active = u8::from(active);
+        format!(
+            "0\t1704067200\t2\t\t\t\t\t\t\tsplit\t{window}\t{active}\t{pane}\t{active}\t42\t80\t24\t{activity}\t{command}\t/src/{command}"
+        )
+    }
 
     #[test]
     fn test_parse_sessions_empty() {
@@ -222,27 +1565,43 @@ mod tests {
 
     #[test]
     fn test_parse_sessions_single() {
-        let output = "test-session\t0\t1704067200\t1704067200\t/home/user/project\t1";
-        let sessions = parse_sessions(output).unwrap();
+        let output = session_line(
+            "test-session",
+            "0",
+            "1704067200",
+            "1",
+            "1704067260",
+            "/home/user/project",
+        );
+        let sessions = parse_sessions(&output).unwrap();
         assert_eq!(sessions.len(), 1);
         assert_eq!(sessions[0].name, "test-session");
         assert_eq!(sessions[0].status, SessionStatus::Idle);
         assert_eq!(sessions[0].window_count, 1);
+        assert_eq!(sessions[0].last_activity.timestamp(), 1_704_067_260);
+        assert_eq!(
+            sessions[0].working_directory,
+            PathBuf::from("/home/user/project")
+        );
+        assert_eq!(sessions[0].agent_target(), "test-session:0.0");
     }
 
     #[test]
     fn test_parse_sessions_active() {
-        let output = "active-session\t1\t1704067200\t1704067200\t/home/user/project\t2";
-        let sessions = parse_sessions(output).unwrap();
+        let output = session_line("active-session", "1", "1704067200", "2", "1704067200", "/p");
+        let sessions = parse_sessions(&output).unwrap();
         assert_eq!(sessions.len(), 1);
         assert_eq!(sessions[0].status, SessionStatus::Active);
     }
 
     #[test]
     fn test_parse_sessions_multiple() {
-        let output = "session1\t0\t1704067200\t1704067200\t/home/user/proj1\t1\n\
-                      session2\t2\t1704067200\t1704067200\t/home/user/proj2\t3";
-        let sessions = parse_sessions(output).unwrap();
+        let output = [
+            session_line("session1", "0", "1704067200", "1", "1704067200", "/proj1"),
+            session_line("session2", "2", "1704067200", "3", "1704067200", "/proj2"),
+        ]
+        .join("\n");
+        let sessions = parse_sessions(&output).unwrap();
         assert_eq!(sessions.len(), 2);
         assert_eq!(sessions[0].name, "session1");
         assert_eq!(sessions[0].status, SessionStatus::Idle);
@@ -251,9 +1610,77 @@ mod tests {
         assert_eq!(sessions[1].window_count, 3);
     }
 
+    #[test]
+    fn test_parse_sessions_groups_panes() {
+        // The dev server's window is current and busier than the agent's
+        let output = [
+            pane_line(0, 0, false, 1_704_067_200, "vim"),
+            pane_line(0, 1, true, 1_704_067_200, "bash"),
+            pane_line(1, 0, true, 1_704_067_900, "npm"),
+        ]
+        .join("\n");
+        let sessions = parse_sessions(&output).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].panes.len(), 3);
+        assert_eq!(sessions[0].panes[2].target().to_string(), "split:1.0");
+
+        set_agent_commands(vec!["vim".to_string()]);
+        let sessions = parse_sessions(&output).unwrap();
+        set_agent_commands(Vec::new());
+        assert_eq!(sessions[0].agent_target(), "split:0.0");
+        assert_eq!(sessions[0].working_directory, PathBuf::from("/src/vim"));
+        assert_eq!(sessions[0].last_activity.timestamp(), 1_704_067_200);
+    }
+
+    #[test]
+    fn test_parse_sessions_user_options() {
+        let output = "0\t1704067200\t1\tticket:ABC-12,frontend\twaiting on review\tclaude\tclaude-code\tams queue\t7\tweb\t0\t1\t0\t1\t42\t80\t24\t1704067200\tclaude\t/src/web";
+        let sessions = parse_sessions(output).unwrap();
+        assert_eq!(sessions[0].tags, vec!["ticket:ABC-12", "frontend"]);
+        assert_eq!(sessions[0].note.as_deref(), Some("waiting on review"));
+        assert_eq!(sessions[0].agent.as_deref(), Some("claude"));
+        assert_eq!(sessions[0].profile.as_deref(), Some("claude-code"));
+        assert_eq!(sessions[0].created_by.as_deref(), Some("ams queue"));
+        assert_eq!(sessions[0].task, Some(7));
+
+        let sessions = parse_sessions(&session_line("plain", "0", "1", "1", "1", "/")).unwrap();
+        assert!(sessions[0].tags.is_empty());
+        assert!(sessions[0].note.is_none());
+        assert!(sessions[0].profile.is_none());
+        assert!(sessions[0].task.is_none());
+    }
+
+    #[test]
+    fn test_parse_sessions_hides_control_session() {
+        let output = [
+            session_line(
+                CONTROL_SESSION,
+                "1",
+                "1704067200",
+                "1",
+                "1704067200",
+                "/tmp",
+            ),
+            session_line("session1", "0", "1704067200", "1", "1704067200", "/proj1"),
+        ]
+        .join("\n");
+        let sessions = parse_sessions(&output).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].name, "session1");
+    }
+
+    #[test]
+    fn test_parse_pane_targets_hides_control_session() {
+        let output = format!("{CONTROL_SESSION}\t0\t0\nsession1\t1\t2\n");
+        let targets = parse_pane_targets(&output).unwrap();
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].session, "session1");
+        assert_eq!((targets[0].window_index, targets[0].pane_index), (1, 2));
+    }
+
     #[test]
     fn test_parse_sessions_insufficient_fields() {
-        let output = "session\t0\t1704067200";
+        let output = "0\t1704067200\t1\tsession\t0";
         let result = parse_sessions(output);
         assert!(result.is_err());
         let err = result.unwrap_err();
@@ -262,8 +1689,8 @@ mod tests {
 
     #[test]
     fn test_parse_sessions_invalid_attached_count() {
-        let output = "session\tnot_a_number\t1704067200\t1704067200\t/home/user\t1";
-        let result = parse_sessions(output);
+        let output = session_line("s", "not_a_number", "1704067200", "1", "1704067200", "/");
+        let result = parse_sessions(&output);
         assert!(result.is_err());
         let err = result.unwrap_err();
         assert!(matches!(err, TmuxError::ParseError(_)));
@@ -271,39 +1698,34 @@ mod tests {
 
     #[test]
     fn test_parse_sessions_invalid_activity_timestamp() {
-        let output = "session\t0\tnot_a_timestamp\t1704067200\t/home/user\t1";
-        let result = parse_sessions(output);
-        assert!(result.is_err());
+        let output = session_line("s", "0", "1704067200", "1", "not_a_timestamp", "/");
+        assert!(parse_sessions(&output).is_err());
     }
 
     #[test]
     fn test_parse_sessions_invalid_created_timestamp() {
-        let output = "session\t0\t1704067200\tnot_a_timestamp\t/home/user\t1";
-        let result = parse_sessions(output);
-        assert!(result.is_err());
+        let output = session_line("s", "0", "not_a_timestamp", "1", "1704067200", "/");
+        assert!(parse_sessions(&output).is_err());
     }
 
     #[test]
     fn test_parse_sessions_invalid_window_count() {
-        let output = "session\t0\t1704067200\t1704067200\t/home/user\tnot_a_number";
-        let result = parse_sessions(output);
-        assert!(result.is_err());
+        let output = session_line("s", "0", "1704067200", "not_a_number", "1704067200", "/");
+        assert!(parse_sessions(&output).is_err());
     }
 
     #[test]
     fn test_parse_sessions_invalid_activity_epoch() {
         // Use an epoch value that results in None from timestamp_opt
-        let output = "session\t0\t-9999999999999999\t1704067200\t/home/user\t1";
-        let result = parse_sessions(output);
-        assert!(result.is_err());
+        let output = session_line("s", "0", "1704067200", "1", "-9999999999999999", "/");
+        assert!(parse_sessions(&output).is_err());
     }
 
     #[test]
     fn test_parse_sessions_invalid_created_epoch() {
         // Use an epoch value that results in None from timestamp_opt
-        let output = "session\t0\t1704067200\t-9999999999999999\t/home/user\t1";
-        let result = parse_sessions(output);
-        assert!(result.is_err());
+        let output = session_line("s", "0", "-9999999999999999", "1", "1704067200", "/");
+        assert!(parse_sessions(&output).is_err());
     }
 
     #[test]
@@ -319,12 +1741,42 @@ mod tests {
 
         let err = TmuxError::ParseError("invalid".to_string());
         assert_eq!(err.to_string(), "Failed to parse tmux output: invalid");
+
+        let err = TmuxError::ControlMode("closed".to_string());
+        assert_eq!(err.to_string(), "Tmux control mode error: closed");
+
+        let err = TmuxError::Unsupported {
+            feature: Feature::ListFilter,
+            required_version: Feature::ListFilter.required_version(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "list filters (-f) requires tmux 3.1 or newer"
+        );
     }
 
     #[test]
+    #[allow(clippy::uninlined_format_args)]
     fn test_tmux_error_debug() {
         let err = TmuxError::ServerNotRunning;
         let debug_str = format!("{:?}", err);
         assert!(debug_str.contains("ServerNotRunning"));
     }
+
+    #[test]
+    fn test_literal_escapes_trailing_semicolon() {
+        assert_eq!(literal("run the tests"), "run the tests");
+        assert_eq!(literal("a; b"), "a; b");
+        assert_eq!(literal("done;"), "done\\;");
+    }
+
+    #[test]
+    fn test_config_quote() {
+        assert_eq!(config_quote("KEY=plain"), "\"KEY=plain\"");
+        assert_eq!(
+            config_quote("K=$HOME \"q\" \\ ~ #{x}; ok"),
+            "\"K=\\$HOME \\\"q\\\" \\\\ ~ #{x}; ok\""
+        );
+        assert_eq!(config_quote("a\nb\tc"), "\"a\\nb\\tc\"");
+    }
 }
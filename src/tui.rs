@@ -1,7 +1,19 @@
 //! TUI module for interactive session management.
 
-use crate::session::{Session, SessionStatus};
-use crate::tmux::list_sessions;
+use crate::cleanup::{format_duration, repo_root};
+use crate::config::{Column, TuiConfig};
+use crate::control::{ControlClient, ControlEvent};
+use crate::daemon;
+use crate::git;
+use crate::metadata::Origin;
+use crate::process::{format_memory, ProcessTable, Usage};
+use crate::recording::Recording;
+use crate::search::{search_all, SearchMatch};
+use crate::session::{AgentState, Pane, Session, SessionStatus, Window};
+use crate::text::{abbreviate_path, truncate};
+use crate::tmux::{self, create_session, list_sessions_on, server_running, Aggregate, TmuxError};
+use crate::watch::{snapshot_sessions, StateMatcher};
+use chrono::{DateTime, Local, Utc};
 use crossterm::{
     event::{self, Event, KeyCode, KeyEventKind},
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
@@ -9,82 +21,1150 @@ use crossterm::{
 };
 use ratatui::{
     prelude::*,
-    widgets::{Block, Borders, Cell, Row, Table, TableState},
+    widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState, Wrap},
 };
+use regex::RegexBuilder;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::io::{self, stdout};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Which view the TUI is currently showing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// The session table
+    Sessions,
+    /// Typing a scrollback search pattern
+    SearchInput,
+    /// Browsing scrollback search matches
+    SearchResults,
+    /// Typing the name of a new session
+    NewSession,
+    /// Viewing the error history
+    Errors,
+}
+
+/// Most error history entries kept.
+const ERROR_HISTORY_LIMIT: usize = 100;
+
+/// Why the last refresh failed, classified for the empty-state screen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RefreshError {
+    /// No tmux server is running
+    NoServer,
+    /// The tmux binary could not be found
+    NotInstalled,
+    /// tmux output could not be parsed or tmux reported an error
+    ParseFailure(String),
+    /// Any other failure
+    Other(String),
+}
+
+impl From<&TmuxError> for RefreshError {
+    fn from(err: &TmuxError) -> Self {
+        match err {
+            TmuxError::ServerNotRunning => Self::NoServer,
+            TmuxError::CommandFailed(e) if e.kind() == io::ErrorKind::NotFound => {
+                Self::NotInstalled
+            }
+            TmuxError::ParseError(details) => Self::ParseFailure(details.trim().to_string()),
+            other => Self::Other(other.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for RefreshError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoServer => write!(f, "no tmux server running"),
+            Self::NotInstalled => write!(f, "tmux not installed"),
+            Self::ParseFailure(details) => write!(f, "parse failure: {details}"),
+            Self::Other(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+/// Identifies a session across hosts and servers.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct SessionKey {
+    host: Option<String>,
+    server: Option<String>,
+    name: String,
+}
+
+impl From<&Session> for SessionKey {
+    fn from(session: &Session) -> Self {
+        Self {
+            host: session.host.clone(),
+            server: session.server.clone(),
+            name: session.name.clone(),
+        }
+    }
+}
+
+/// A window of an expanded session, with its panes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct WindowNode {
+    window: Window,
+    panes: Vec<Pane>,
+}
+
+/// One row of the session table: a group header, a session, or a window
+/// or pane of an expanded session, as indexes into `App::groups`,
+/// `App::sessions` and its tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TreeRow {
+    Group(usize),
+    Session(usize),
+    Window(usize, usize),
+    Pane(usize, usize, usize),
+}
+
+impl TreeRow {
+    const fn session(self) -> Option<usize> {
+        match self {
+            Self::Group(_) => None,
+            Self::Session(session) | Self::Window(session, _) | Self::Pane(session, _, _) => {
+                Some(session)
+            }
+        }
+    }
+
+    /// The same row under the session now at index `session`
+    const fn with_session(self, session: usize) -> Self {
+        match self {
+            Self::Group(group) => Self::Group(group),
+            Self::Session(_) => Self::Session(session),
+            Self::Window(_, window) => Self::Window(session, window),
+            Self::Pane(_, window, pane) => Self::Pane(session, window, pane),
+        }
+    }
+}
+
+/// Values shown in the table that are not part of `Session`, collected by
+/// the refresh worker only for the columns that need them.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Details {
+    state: Option<AgentState>,
+    branch: Option<String>,
+    usage: Option<Usage>,
+}
+
+/// Header label of a column.
+const fn column_title(column: Column) -> &'static str {
+    match column {
+        Column::Name => "Name",
+        Column::Status => "Status",
+        Column::Agent => "Agent",
+        Column::State => "State",
+        Column::Windows => "Windows",
+        Column::Dir => "Working Directory",
+        Column::Branch => "Branch",
+        Column::LastActivity => "Activity",
+        Column::Age => "Age",
+        Column::Cpu => "CPU",
+        Column::Memory => "Memory",
+        Column::Tags => "Tags",
+    }
+}
+
+/// Most display columns a cell of `column` takes; working directories are
+/// abbreviated to this width.
+const fn column_length(column: Column) -> u16 {
+    match column {
+        Column::Name => 24,
+        Column::Status | Column::Windows | Column::Memory => 9,
+        Column::Agent | Column::LastActivity => 12,
+        Column::State => 15,
+        Column::Dir => 40,
+        Column::Branch | Column::Tags => 20,
+        Column::Age => 8,
+        Column::Cpu => 7,
+    }
+}
+
+/// Width of a column; the working directory takes the remaining space.
+const fn column_width(column: Column) -> Constraint {
+    match column {
+        Column::Dir => Constraint::Min(20),
+        column => Constraint::Length(column_length(column)),
+    }
+}
+
+/// Column the session table is sorted by, and in which direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Sort {
+    column: Column,
+    reverse: bool,
+}
+
+/// Order of agent states when sorting, those needing attention first.
+const fn state_rank(state: AgentState) -> u8 {
+    match state {
+        AgentState::NeedsApproval => 0,
+        AgentState::WaitingForInput => 1,
+        AgentState::Errored => 2,
+        AgentState::Working => 3,
+        AgentState::Finished => 4,
+    }
+}
+
+/// Compares optional values with missing ones last.
+fn missing_last<T: Ord>(a: Option<T>, b: Option<T>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (a, b) => a.is_none().cmp(&b.is_none()),
+    }
+}
+
+/// Profile or agent a session was created with, or the command running
+/// in its agent pane.
+fn agent_label(session: &Session) -> Option<&str> {
+    session
+        .profile
+        .as_deref()
+        .or(session.agent.as_deref())
+        .or_else(|| {
+            session
+                .agent_pane()
+                .map(|pane| pane.current_command.as_str())
+        })
+}
+
+/// Formats the time since `at`, e.g. `5m 12s`.
+fn format_age(at: DateTime<Utc>) -> String {
+    format_duration((Utc::now() - at).to_std().unwrap_or_default())
+}
+
+/// What the session table groups its rows by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Grouping {
+    /// No groups
+    #[default]
+    None,
+    /// Each tag; a session with several tags shows under each
+    Tag,
+    /// The git repository of the working directory
+    Repo,
+    /// The agent profile ams launched, or else the command running in
+    /// the agent pane
+    Agent,
+}
+
+impl Grouping {
+    /// The grouping `g` switches to
+    const fn next(self) -> Self {
+        match self {
+            Self::None => Self::Tag,
+            Self::Tag => Self::Repo,
+            Self::Repo => Self::Agent,
+            Self::Agent => Self::None,
+        }
+    }
+
+    /// Labels of the groups a session belongs to
+    fn labels(self, session: &Session) -> Vec<String> {
+        match self {
+            Self::None => Vec::new(),
+            Self::Tag if session.tags.is_empty() => vec![self.fallback().to_string()],
+            Self::Tag => session.tags.clone(),
+            Self::Repo => vec![repo_root(&session.working_directory)
+                .to_string_lossy()
+                .into_owned()],
+            Self::Agent => vec![session
+                .profile
+                .as_deref()
+                .or_else(|| {
+                    session
+                        .agent_pane()
+                        .map(|pane| pane.current_command.as_str())
+                })
+                .unwrap_or_else(|| self.fallback())
+                .to_string()],
+        }
+    }
+
+    /// Label of the group of sessions without a tag or agent, listed last
+    const fn fallback(self) -> &'static str {
+        match self {
+            Self::Agent => "unknown agent",
+            _ => "untagged",
+        }
+    }
+
+    /// Describes the grouping in the table title
+    const fn title(self) -> &'static str {
+        match self {
+            Self::None => "",
+            Self::Tag => " by tag",
+            Self::Repo => " by repository",
+            Self::Agent => " by agent",
+        }
+    }
+}
+
+/// A group header and the sessions under it, as indexes into
+/// `App::sessions`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Group {
+    label: String,
+    sessions: Vec<usize>,
+}
+
+/// Groups sessions by `grouping`, in label order with ungrouped sessions
+/// last.
+fn group_sessions(sessions: &[Session], grouping: Grouping) -> Vec<Group> {
+    let mut groups: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (i, session) in sessions.iter().enumerate() {
+        for label in grouping.labels(session) {
+            groups.entry(label).or_default().push(i);
+        }
+    }
+    let fallback = grouping.fallback();
+    let ungrouped = groups.remove(fallback);
+    groups
+        .into_iter()
+        .chain(ungrouped.map(|sessions| (fallback.to_string(), sessions)))
+        .map(|(label, sessions)| Group { label, sessions })
+        .collect()
+}
 
 /// TUI application state.
 pub struct App {
     sessions: Vec<Session>,
+    grouping: Grouping,
+    groups: Vec<Group>,
+    collapsed_groups: BTreeSet<String>,
+    expanded: BTreeSet<SessionKey>,
+    expanded_changed: bool,
+    trees: HashMap<SessionKey, Vec<WindowNode>>,
+    details: HashMap<SessionKey, Details>,
+    columns: Vec<Column>,
+    sort: Sort,
+    home: Option<PathBuf>,
     table_state: TableState,
     should_quit: bool,
     selected_session: Option<String>,
+    selected_host: Option<String>,
+    selected_server: Option<String>,
+    mode: Mode,
+    search_query: String,
+    search_matches: Vec<SearchMatch>,
+    search_state: TableState,
+    status_message: Option<String>,
+    refresh_requested: bool,
+    refresh_error: Option<RefreshError>,
+    unreachable: Vec<String>,
+    last_refreshed: Option<DateTime<Local>>,
+    errors: Vec<(DateTime<Local>, String)>,
+    new_session_name: String,
 }
 
 impl App {
     /// Creates a new TUI application instance.
     #[must_use]
     pub fn new() -> Self {
+        Self::with_config(&TuiConfig::default())
+    }
+
+    /// Creates a TUI application instance showing and sorting by the
+    /// configured columns; the name column is always shown.
+    #[must_use]
+    pub fn with_config(config: &TuiConfig) -> Self {
+        let mut columns = config.columns.clone();
+        if !columns.contains(&Column::Name) {
+            columns.insert(0, Column::Name);
+        }
         Self {
             sessions: Vec::new(),
+            grouping: Grouping::None,
+            groups: Vec::new(),
+            collapsed_groups: BTreeSet::new(),
+            expanded: BTreeSet::new(),
+            expanded_changed: false,
+            trees: HashMap::new(),
+            details: HashMap::new(),
+            columns,
+            sort: Sort {
+                column: config.sort,
+                reverse: config.sort_reverse,
+            },
+            home: std::env::var_os("HOME").map(PathBuf::from),
             table_state: TableState::default(),
             should_quit: false,
             selected_session: None,
+            selected_host: None,
+            selected_server: None,
+            mode: Mode::Sessions,
+            search_query: String::new(),
+            search_matches: Vec::new(),
+            search_state: TableState::default(),
+            status_message: None,
+            refresh_requested: false,
+            refresh_error: None,
+            unreachable: Vec::new(),
+            last_refreshed: None,
+            errors: Vec::new(),
+            new_session_name: String::new(),
         }
     }
 
-    /// Refreshes the session list from tmux.
+    /// Refreshes the session list from tmux on the calling thread.
     pub fn refresh_sessions(&mut self) {
-        self.sessions = list_sessions().unwrap_or_default();
-        if !self.sessions.is_empty() && self.table_state.selected().is_none() {
-            self.table_state.select(Some(0));
+        self.apply_snapshot(Snapshot::take(
+            None,
+            &Aggregate::default(),
+            &self.expanded,
+            None,
+        ));
+    }
+
+    /// Applies a refresh result, keeping the previous sessions on failure.
+    fn apply_snapshot(&mut self, snapshot: Snapshot) {
+        match snapshot.sessions {
+            Ok(sessions) => {
+                self.trees = snapshot.trees;
+                self.details = snapshot.details;
+                self.set_sessions(sessions);
+                self.refresh_error = None;
+                for source in &snapshot.unreachable {
+                    if !self.unreachable.contains(source) {
+                        self.record_error(format!("listing failed: {source}"));
+                    }
+                }
+                self.unreachable = snapshot.unreachable;
+                self.last_refreshed = Some(snapshot.taken_at);
+            }
+            Err(e) => {
+                let error = RefreshError::from(&e);
+                // A persistent failure is recorded once, not on every refresh.
+                if self.refresh_error.as_ref() != Some(&error) {
+                    self.record_error(format!("refresh failed: {error}"));
+                }
+                self.refresh_error = Some(error);
+            }
         }
     }
 
-    fn next(&mut self) {
-        if self.sessions.is_empty() {
-            return;
+    /// Adds an entry to the error history shown with `?`.
+    fn record_error(&mut self, message: String) {
+        if self.errors.len() == ERROR_HISTORY_LIMIT {
+            self.errors.remove(0);
         }
-        let i = match self.table_state.selected() {
-            Some(i) => {
-                if i >= self.sessions.len() - 1 {
-                    0
-                } else {
-                    i + 1
+        self.errors.push((Local::now(), message));
+    }
+
+    /// Replaces and sorts the session list, keeping the selected row
+    /// selected if its session is still listed, or else the selection in
+    /// range.
+    pub fn set_sessions(&mut self, mut sessions: Vec<Session>) {
+        let previous = self.selected_row().and_then(|row| {
+            let session = &self.sessions[row.session()?];
+            Some((row, SessionKey::from(session)))
+        });
+
+        sessions.sort_by(|a, b| {
+            let order = self.compare(a, b);
+            if self.sort.reverse {
+                order.reverse()
+            } else {
+                order
+            }
+        });
+        self.sessions = sessions;
+        self.groups = group_sessions(&self.sessions, self.grouping);
+
+        let rows = self.rows();
+        let moved = previous.and_then(|(row, key)| {
+            let session = self
+                .sessions
+                .iter()
+                .position(|session| SessionKey::from(session) == key)?;
+            rows.iter().position(|r| *r == row.with_session(session))
+        });
+        let selected = match self.table_state.selected() {
+            _ if rows.is_empty() => None,
+            _ if moved.is_some() => moved,
+            Some(index) => Some(index.min(rows.len() - 1)),
+            None => Some(0),
+        };
+        self.table_state.select(selected);
+    }
+
+    /// Orders two sessions by the sort column, then by name.
+    fn compare(&self, a: &Session, b: &Session) -> Ordering {
+        let details = |session: &Session| self.details.get(&SessionKey::from(session));
+        let (da, db) = (details(a), details(b));
+        let order = match self.sort.column {
+            Column::Name => Ordering::Equal,
+            Column::Status => {
+                let rank = |status: &SessionStatus| match status {
+                    SessionStatus::Active => 0,
+                    SessionStatus::Idle => 1,
+                    SessionStatus::Dead => 2,
+                };
+                rank(&a.status).cmp(&rank(&b.status))
+            }
+            Column::Agent => missing_last(agent_label(a), agent_label(b)),
+            Column::State => missing_last(
+                da.and_then(|d| d.state).map(state_rank),
+                db.and_then(|d| d.state).map(state_rank),
+            ),
+            Column::Windows => a.window_count.cmp(&b.window_count),
+            Column::Dir => a.working_directory.cmp(&b.working_directory),
+            Column::Branch => missing_last(
+                da.and_then(|d| d.branch.as_deref()),
+                db.and_then(|d| d.branch.as_deref()),
+            ),
+            // Most recent first, like the ages shown.
+            Column::LastActivity => b.last_activity.cmp(&a.last_activity),
+            Column::Age => b.created_at.cmp(&a.created_at),
+            Column::Cpu => {
+                let cpu = |d: Option<&Details>| d.and_then(|d| d.usage).map(|u| u.cpu);
+                match (cpu(da), cpu(db)) {
+                    (Some(a), Some(b)) => a.total_cmp(&b),
+                    (a, b) => a.is_none().cmp(&b.is_none()),
                 }
             }
-            None => 0,
+            Column::Memory => missing_last(
+                da.and_then(|d| d.usage).map(|u| u.memory_kb),
+                db.and_then(|d| d.usage).map(|u| u.memory_kb),
+            ),
+            Column::Tags => missing_last(
+                Some(a.tags.join(",")).filter(|tags| !tags.is_empty()),
+                Some(b.tags.join(",")).filter(|tags| !tags.is_empty()),
+            ),
         };
-        self.table_state.select(Some(i));
+        order.then_with(|| SessionKey::from(a).cmp(&SessionKey::from(b)))
+    }
+
+    /// Columns shown in the table; tags only once a session has some.
+    fn visible_columns(&self) -> Vec<Column> {
+        let tagged = self.sessions.iter().any(|session| !session.tags.is_empty());
+        self.columns
+            .iter()
+            .copied()
+            .filter(|&column| column != Column::Tags || tagged)
+            .collect()
+    }
+
+    /// Sorts by the next visible column.
+    fn cycle_sort(&mut self) {
+        let columns = self.visible_columns();
+        let next = columns
+            .iter()
+            .position(|&column| column == self.sort.column)
+            .map_or(0, |i| (i + 1) % columns.len());
+        self.sort.column = columns[next];
+        self.set_sessions(self.sessions.clone());
+    }
+
+    /// Reverses the sort order.
+    fn reverse_sort(&mut self) {
+        self.sort.reverse = !self.sort.reverse;
+        self.set_sessions(self.sessions.clone());
+    }
+
+    /// Lists the table rows: group headers when grouping, each with its
+    /// sessions unless collapsed, and the windows and panes of expanded
+    /// sessions below them.
+    fn rows(&self) -> Vec<TreeRow> {
+        let mut rows = Vec::new();
+        if self.grouping == Grouping::None {
+            for i in 0..self.sessions.len() {
+                self.push_session_rows(&mut rows, i);
+            }
+            return rows;
+        }
+        for (g, group) in self.groups.iter().enumerate() {
+            rows.push(TreeRow::Group(g));
+            if self.collapsed_groups.contains(&group.label) {
+                continue;
+            }
+            for &i in &group.sessions {
+                self.push_session_rows(&mut rows, i);
+            }
+        }
+        rows
+    }
+
+    fn push_session_rows(&self, rows: &mut Vec<TreeRow>, i: usize) {
+        rows.push(TreeRow::Session(i));
+        let key = SessionKey::from(&self.sessions[i]);
+        if !self.expanded.contains(&key) {
+            return;
+        }
+        for (w, node) in self.trees.get(&key).into_iter().flatten().enumerate() {
+            rows.push(TreeRow::Window(i, w));
+            rows.extend((0..node.panes.len()).map(|p| TreeRow::Pane(i, w, p)));
+        }
+    }
+
+    fn selected_row(&self) -> Option<TreeRow> {
+        self.table_state
+            .selected()
+            .and_then(|i| self.rows().get(i).copied())
+    }
+
+    fn window_node(&self, session: usize, window: usize) -> Option<&WindowNode> {
+        let key = SessionKey::from(self.sessions.get(session)?);
+        self.trees.get(&key)?.get(window)
+    }
+
+    /// Expands or collapses the selected group, or the tree of the
+    /// selected session; collapsing moves the selection back to the
+    /// session row.
+    fn set_expanded(&mut self, expand: Option<bool>) {
+        let Some(row) = self.selected_row() else {
+            return;
+        };
+        let Some(session) = row.session() else {
+            if let TreeRow::Group(g) = row {
+                self.set_group_expanded(g, expand);
+            }
+            return;
+        };
+        let key = SessionKey::from(&self.sessions[session]);
+        let expand = expand.unwrap_or_else(|| !self.expanded.contains(&key));
+
+        if expand {
+            self.expanded_changed |= self.expanded.insert(key);
+        } else {
+            // A session may show in several groups; return to this row's
+            let selected = self.table_state.selected().unwrap_or_default();
+            let index = self.rows()[..=selected]
+                .iter()
+                .rposition(|row| *row == TreeRow::Session(session));
+            self.expanded_changed |= self.expanded.remove(&key);
+            self.table_state.select(index);
+        }
+    }
+
+    fn set_group_expanded(&mut self, group: usize, expand: Option<bool>) {
+        let label = &self.groups[group].label;
+        let expand = expand.unwrap_or_else(|| self.collapsed_groups.contains(label));
+        if expand {
+            self.collapsed_groups.remove(label);
+        } else {
+            self.collapsed_groups.insert(label.clone());
+        }
+    }
+
+    /// Switches to the next grouping, keeping the selected session selected
+    fn cycle_grouping(&mut self) {
+        let selected = self.selected_row().and_then(TreeRow::session);
+        self.grouping = self.grouping.next();
+        self.collapsed_groups.clear();
+        self.groups = group_sessions(&self.sessions, self.grouping);
+        let rows = self.rows();
+        let index = selected
+            .and_then(|session| {
+                rows.iter()
+                    .position(|row| *row == TreeRow::Session(session))
+            })
+            .or_else(|| (!rows.is_empty()).then_some(0));
+        self.table_state.select(index);
+    }
+
+    /// Returns the note of the session on the selected row
+    fn selected_note(&self) -> Option<&str> {
+        let session = self.selected_row()?.session()?;
+        self.sessions[session].note.as_deref()
+    }
+
+    fn next(&mut self) {
+        if self.mode == Mode::SearchResults {
+            step(&mut self.search_state, self.search_matches.len(), true);
+        } else {
+            let rows = self.rows().len();
+            step(&mut self.table_state, rows, true);
+        }
     }
 
     fn previous(&mut self) {
-        if self.sessions.is_empty() {
+        if self.mode == Mode::SearchResults {
+            step(&mut self.search_state, self.search_matches.len(), false);
+        } else {
+            let rows = self.rows().len();
+            step(&mut self.table_state, rows, false);
+        }
+    }
+
+    fn select_current(&mut self) {
+        let target = if self.mode == Mode::SearchResults {
+            self.search_state
+                .selected()
+                .and_then(|i| self.search_matches.get(i))
+                .map(|m| (m.pane.to_string(), None, None))
+        } else {
+            self.selected_row().and_then(|row| {
+                let session = &self.sessions[row.session()?];
+                let target = match row {
+                    TreeRow::Group(_) => return None,
+                    TreeRow::Session(_) => session.name.clone(),
+                    TreeRow::Window(i, w) => self.window_node(i, w)?.window.target(),
+                    TreeRow::Pane(i, w, p) => {
+                        self.window_node(i, w)?.panes.get(p)?.target().to_string()
+                    }
+                };
+                Some((target, session.host.clone(), session.server.clone()))
+            })
+        };
+
+        if let Some((target, host, server)) = target {
+            self.selected_session = Some(target);
+            self.selected_host = host;
+            self.selected_server = server;
+            self.should_quit = true;
+        } else if let Some(TreeRow::Group(g)) = self.selected_row() {
+            self.set_group_expanded(g, None);
+        }
+    }
+
+    /// Runs the current query against the scrollback of every pane.
+    ///
+    /// The search is case-insensitive unless the query contains uppercase.
+    fn run_search(&mut self) {
+        let case_insensitive = !self.search_query.chars().any(char::is_uppercase);
+        let pattern = match RegexBuilder::new(&self.search_query)
+            .case_insensitive(case_insensitive)
+            .build()
+        {
+            Ok(pattern) => pattern,
+            Err(e) => {
+                self.show_error(format!("Invalid pattern: {e}"));
+                self.mode = Mode::Sessions;
+                return;
+            }
+        };
+
+        match search_all(&pattern, 0) {
+            Ok(matches) => self.show_search_results(matches),
+            Err(e) => {
+                self.show_error(format!("Search failed: {e}"));
+                self.mode = Mode::Sessions;
+            }
+        }
+    }
+
+    /// Creates a detached session named after the typed input.
+    fn create_new_session(&mut self) {
+        let name = std::mem::take(&mut self.new_session_name);
+        self.mode = Mode::Sessions;
+
+        let name = name.trim();
+        if let Err(e) = create_session(name, None) {
+            self.show_error(format!("Failed to create session: {e}"));
             return;
         }
-        let i = match self.table_state.selected() {
-            Some(i) => {
-                if i == 0 {
-                    self.sessions.len() - 1
-                } else {
-                    i - 1
+        daemon::notify_changed();
+        self.refresh_requested = true;
+        match Origin::new("ams tui", None).record(name) {
+            Ok(()) => self.status_message = Some(format!("Created session {name}")),
+            Err(e) => self.show_error(format!(
+                "Created session {name}, but could not record its metadata: {e}"
+            )),
+        }
+    }
+
+    /// Shows an error in the status line and keeps it in the history.
+    fn show_error(&mut self, message: String) {
+        self.status_message = Some(message.clone());
+        self.record_error(message);
+    }
+
+    fn show_search_results(&mut self, matches: Vec<SearchMatch>) {
+        self.status_message = Some(format!(
+            "{} matches for /{}",
+            matches.len(),
+            self.search_query
+        ));
+        self.search_state
+            .select(if matches.is_empty() { None } else { Some(0) });
+        self.search_matches = matches;
+        self.mode = Mode::SearchResults;
+    }
+
+    fn handle_key(&mut self, code: KeyCode) {
+        match self.mode {
+            Mode::SearchInput => match code {
+                KeyCode::Esc => self.mode = Mode::Sessions,
+                KeyCode::Enter if !self.search_query.is_empty() => self.run_search(),
+                KeyCode::Backspace => {
+                    self.search_query.pop();
+                }
+                KeyCode::Char(c) => self.search_query.push(c),
+                _ => {}
+            },
+            Mode::SearchResults => match code {
+                KeyCode::Char('q') | KeyCode::Esc => {
+                    self.mode = Mode::Sessions;
+                    self.status_message = None;
+                }
+                KeyCode::Down | KeyCode::Char('j') => self.next(),
+                KeyCode::Up | KeyCode::Char('k') => self.previous(),
+                KeyCode::Enter => self.select_current(),
+                KeyCode::Char('/') => self.start_search(),
+                _ => {}
+            },
+            Mode::NewSession => match code {
+                KeyCode::Esc => self.mode = Mode::Sessions,
+                KeyCode::Enter if !self.new_session_name.trim().is_empty() => {
+                    self.create_new_session();
+                }
+                KeyCode::Backspace => {
+                    self.new_session_name.pop();
+                }
+                KeyCode::Char(c) => self.new_session_name.push(c),
+                _ => {}
+            },
+            Mode::Errors => match code {
+                KeyCode::Char('q' | '?') | KeyCode::Esc => self.mode = Mode::Sessions,
+                _ => {}
+            },
+            Mode::Sessions => match code {
+                KeyCode::Char('q') | KeyCode::Esc => self.should_quit = true,
+                KeyCode::Down | KeyCode::Char('j') => self.next(),
+                KeyCode::Up | KeyCode::Char('k') => self.previous(),
+                KeyCode::Enter => self.select_current(),
+                KeyCode::Char(' ') => self.set_expanded(None),
+                KeyCode::Right | KeyCode::Char('l') => self.set_expanded(Some(true)),
+                KeyCode::Left | KeyCode::Char('h') => self.set_expanded(Some(false)),
+                KeyCode::Char('r') => self.refresh_requested = true,
+                KeyCode::Char('g') => self.cycle_grouping(),
+                KeyCode::Char('s') => self.cycle_sort(),
+                KeyCode::Char('S') => self.reverse_sort(),
+                KeyCode::Char('/') => self.start_search(),
+                KeyCode::Char('n') => {
+                    self.new_session_name.clear();
+                    self.status_message = None;
+                    self.mode = Mode::NewSession;
+                }
+                KeyCode::Char('?') => self.mode = Mode::Errors,
+                _ => {}
+            },
+        }
+    }
+
+    fn start_search(&mut self) {
+        self.search_query.clear();
+        self.status_message = None;
+        self.mode = Mode::SearchInput;
+    }
+}
+
+/// Sessions fetched by one refresh, with the trees of expanded sessions
+/// and the details of the shown columns.
+struct Snapshot {
+    sessions: Result<Vec<Session>, TmuxError>,
+    /// Hosts and servers of an aggregated listing that failed, with why
+    unreachable: Vec<String>,
+    trees: HashMap<SessionKey, Vec<WindowNode>>,
+    details: HashMap<SessionKey, Details>,
+    taken_at: DateTime<Local>,
+}
+
+/// Which details the refresh worker collects, and how it detects agent
+/// states.
+struct DetailSource {
+    columns: Vec<Column>,
+    matcher: StateMatcher,
+}
+
+impl DetailSource {
+    /// Collects the details of the listed sessions.
+    ///
+    /// Agent states are only detected for sessions of the selected host
+    /// and server, and branches and resource use only on this machine.
+    fn load(&self, aggregate: &Aggregate, sessions: &[Session]) -> HashMap<SessionKey, Details> {
+        let mut details: HashMap<SessionKey, Details> = HashMap::new();
+        if !aggregate.is_empty() {
+            return details;
+        }
+
+        if self.columns.contains(&Column::State) {
+            // A failed capture just leaves the states unknown.
+            for snapshot in snapshot_sessions(sessions, &self.matcher).unwrap_or_default() {
+                if let Some(session) = sessions.iter().find(|s| s.name == snapshot.name) {
+                    details.entry(SessionKey::from(session)).or_default().state =
+                        Some(snapshot.state);
                 }
             }
-            None => 0,
+        }
+        if tmux::host().is_some() {
+            return details;
+        }
+
+        if self.columns.contains(&Column::Branch) {
+            for session in sessions {
+                details.entry(SessionKey::from(session)).or_default().branch =
+                    git::branch(&session.working_directory);
+            }
+        }
+        if self.columns.contains(&Column::Cpu) || self.columns.contains(&Column::Memory) {
+            if let Ok(table) = ProcessTable::read() {
+                for session in sessions {
+                    let pids: Vec<u32> = session.panes.iter().map(|pane| pane.pid).collect();
+                    details.entry(SessionKey::from(session)).or_default().usage =
+                        Some(table.tree_usage(&pids));
+                }
+            }
+        }
+        details
+    }
+}
+
+impl Snapshot {
+    /// Lists sessions over the control connection, falling back to the
+    /// daemon or a direct tmux query. Sessions of several hosts or servers
+    /// are always queried directly.
+    fn take(
+        control: Option<&mut ControlClient>,
+        aggregate: &Aggregate,
+        expanded: &BTreeSet<SessionKey>,
+        details: Option<&DetailSource>,
+    ) -> Self {
+        let mut unreachable = Vec::new();
+        let sessions = if aggregate.is_empty() {
+            control
+                .map_or_else(daemon::list_sessions, |client| {
+                    client.list_sessions().or_else(|_| daemon::list_sessions())
+                })
+                .and_then(|sessions| {
+                    // An empty list is also what tmux reports without a server.
+                    if sessions.is_empty() && !server_running()? {
+                        return Err(TmuxError::ServerNotRunning);
+                    }
+                    Ok(sessions)
+                })
+        } else {
+            list_sessions_on(aggregate).map(|listing| {
+                unreachable = listing
+                    .failures
+                    .iter()
+                    .map(|(source, error)| format!("{source} ({})", error.to_string().trim_end()))
+                    .collect();
+                listing.sessions
+            })
+        };
+
+        // A session that vanished since it was listed just has no tree.
+        let trees = sessions
+            .iter()
+            .flatten()
+            .filter(|session| expanded.contains(&SessionKey::from(*session)))
+            .filter_map(|session| {
+                let tree = load_tree(aggregate, session).ok()?;
+                Some((SessionKey::from(session), tree))
+            })
+            .collect();
+        let details = match (&sessions, details) {
+            (Ok(sessions), Some(source)) => source.load(aggregate, sessions),
+            _ => HashMap::new(),
         };
-        self.table_state.select(Some(i));
+
+        Self {
+            sessions,
+            unreachable,
+            trees,
+            details,
+            taken_at: Local::now(),
+        }
     }
+}
 
-    fn select_current(&mut self) {
-        if let Some(i) = self.table_state.selected() {
-            if let Some(session) = self.sessions.get(i) {
-                self.selected_session = Some(session.name.clone());
-                self.should_quit = true;
+/// Lists the windows of a session with the panes of each.
+fn load_tree(aggregate: &Aggregate, session: &Session) -> Result<Vec<WindowNode>, TmuxError> {
+    let panes = aggregate.list_panes(session)?;
+    Ok(aggregate
+        .list_windows(session)?
+        .into_iter()
+        .map(|window| WindowNode {
+            panes: panes
+                .iter()
+                .filter(|pane| pane.window_index == window.index)
+                .cloned()
+                .collect(),
+            window,
+        })
+        .collect())
+}
+
+/// What the UI asks of the refresh worker.
+enum RefreshRequest {
+    /// Refresh as soon as possible
+    Now,
+    /// Also load the trees of these sessions, starting now
+    Expand(BTreeSet<SessionKey>),
+}
+
+/// Where the refresh worker gets its snapshots.
+trait SnapshotSource: Send + 'static {
+    /// Takes a snapshot, with the trees of the `expanded` sessions.
+    fn take(&mut self, expanded: &BTreeSet<SessionKey>) -> Snapshot;
+
+    /// Whether the sessions changed since the last call, calling for an
+    /// early refresh.
+    fn changed(&mut self) -> bool;
+}
+
+/// Snapshots of tmux, refreshed early when the control connection reports
+/// a session change.
+struct TmuxSource {
+    control: Option<ControlClient>,
+    aggregate: Aggregate,
+    details: DetailSource,
+}
+
+impl SnapshotSource for TmuxSource {
+    fn take(&mut self, expanded: &BTreeSet<SessionKey>) -> Snapshot {
+        if self.control.is_none() {
+            self.control = ControlClient::connect().ok();
+        }
+        Snapshot::take(
+            self.control.as_mut(),
+            &self.aggregate,
+            expanded,
+            Some(&self.details),
+        )
+    }
+
+    fn changed(&mut self) -> bool {
+        let Some(client) = &self.control else {
+            return false;
+        };
+        let events: Vec<ControlEvent> = client.try_events().collect();
+        if events
+            .iter()
+            .any(|event| matches!(event, ControlEvent::Exit { .. }))
+        {
+            self.control = None;
+            return false;
+        }
+        events.iter().any(ControlEvent::changes_sessions)
+    }
+}
+
+/// How long quitting waits for the refresh worker to stop before leaving it
+/// behind, e.g. while it waits for an unreachable host.
+const STOP_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Background worker that refreshes the session list on an interval and
+/// whenever its source reports a session change.
+struct Refresher {
+    requests: Sender<RefreshRequest>,
+    snapshots: Receiver<Snapshot>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Refresher {
+    fn spawn(interval: Duration, source: impl SnapshotSource) -> Self {
+        let (requests, request_rx) = mpsc::channel();
+        let (snapshot_tx, snapshots) = mpsc::channel();
+        let worker = std::thread::spawn(move || {
+            refresh_loop(interval, source, &request_rx, &snapshot_tx);
+        });
+        Self {
+            requests,
+            snapshots,
+            worker: Some(worker),
+        }
+    }
+
+    /// Asks for a refresh as soon as possible.
+    fn request(&self) {
+        let _ = self.requests.send(RefreshRequest::Now);
+    }
+
+    /// Sets the sessions whose trees are loaded, and refreshes.
+    fn expand(&self, expanded: BTreeSet<SessionKey>) {
+        let _ = self.requests.send(RefreshRequest::Expand(expanded));
+    }
+
+    /// Returns the newest snapshot received since the last call.
+    fn latest(&self) -> Option<Snapshot> {
+        self.snapshots.try_iter().last()
+    }
+}
+
+impl Drop for Refresher {
+    fn drop(&mut self) {
+        // Disconnecting the request channel stops the worker once its
+        // current snapshot is taken; a slow one is not waited for.
+        self.requests = mpsc::channel().0;
+        if let Some(worker) = self.worker.take() {
+            let deadline = Instant::now() + STOP_TIMEOUT;
+            while !worker.is_finished() && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            if worker.is_finished() {
+                let _ = worker.join();
+            }
+        }
+    }
+}
+
+fn refresh_loop(
+    interval: Duration,
+    mut source: impl SnapshotSource,
+    requests: &Receiver<RefreshRequest>,
+    snapshots: &Sender<Snapshot>,
+) {
+    let mut expanded = BTreeSet::new();
+    let mut due = Instant::now();
+
+    loop {
+        if source.changed() {
+            due = Instant::now();
+        }
+
+        if Instant::now() >= due {
+            if snapshots.send(source.take(&expanded)).is_err() {
+                return;
             }
+            due = Instant::now() + interval;
+        }
+
+        match requests.recv_timeout(Duration::from_millis(100)) {
+            Ok(RefreshRequest::Now) => due = Instant::now(),
+            Ok(RefreshRequest::Expand(sessions)) => {
+                expanded = sessions;
+                due = Instant::now();
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
         }
     }
 }
 
+/// Moves a table selection one row forward or backward, wrapping around.
+fn step(state: &mut TableState, len: usize, forward: bool) {
+    if len == 0 {
+        return;
+    }
+    let i = match state.selected() {
+        Some(i) if forward => {
+            if i >= len - 1 {
+                0
+            } else {
+                i + 1
+            }
+        }
+        Some(i) => {
+            if i == 0 {
+                len - 1
+            } else {
+                i - 1
+            }
+        }
+        None => 0,
+    };
+    state.select(Some(i));
+}
+
 impl Default for App {
     fn default() -> Self {
         Self::new()
@@ -115,81 +1195,480 @@ pub fn install_panic_hook() {
     }));
 }
 
+/// What the user chose to attach to when leaving the TUI
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    /// Session name or `session:window.pane` target
+    pub target: String,
+    /// Host label of the session, when sessions of several hosts were
+    /// listed
+    pub host: Option<String>,
+    /// Server label of the session, when sessions of several servers were
+    /// listed
+    pub server: Option<String>,
+}
+
 /// Run the TUI application
-pub fn run() -> io::Result<Option<String>> {
+///
+/// With a non-empty `aggregate`, the dashboard lists the sessions of all
+/// its hosts and servers. `matcher` detects agent states for the state
+/// column.
+pub fn run(
+    config: &TuiConfig,
+    matcher: StateMatcher,
+    aggregate: &Aggregate,
+) -> io::Result<Option<Selection>> {
     install_panic_hook();
     let mut terminal = init_terminal()?;
-    let mut app = App::new();
-    app.refresh_sessions();
+    let mut app = App::with_config(config);
+    let mut columns = app.columns.clone();
+    columns.push(app.sort.column);
+    let refresher = Refresher::spawn(
+        Duration::from_millis(config.refresh_rate_ms),
+        TmuxSource {
+            control: None,
+            aggregate: aggregate.clone(),
+            details: DetailSource { columns, matcher },
+        },
+    );
 
     loop {
         terminal.draw(|frame| ui(frame, &mut app))?;
 
-        if event::poll(std::time::Duration::from_millis(100))? {
+        if event::poll(Duration::from_millis(100))? {
             if let Event::Key(key) = event::read()? {
                 if key.kind == KeyEventKind::Press {
-                    match key.code {
-                        KeyCode::Char('q') | KeyCode::Esc => app.should_quit = true,
-                        KeyCode::Down | KeyCode::Char('j') => app.next(),
-                        KeyCode::Up | KeyCode::Char('k') => app.previous(),
-                        KeyCode::Enter => app.select_current(),
-                        KeyCode::Char('r') => app.refresh_sessions(),
-                        _ => {}
-                    }
+                    app.handle_key(key.code);
                 }
             }
         }
 
+        if std::mem::take(&mut app.refresh_requested) {
+            refresher.request();
+        }
+        if std::mem::take(&mut app.expanded_changed) {
+            refresher.expand(app.expanded.clone());
+        }
+        if let Some(snapshot) = refresher.latest() {
+            app.apply_snapshot(snapshot);
+        }
+
         if app.should_quit {
             break;
         }
     }
 
     restore_terminal()?;
-    Ok(app.selected_session)
+    Ok(app.selected_session.map(|target| Selection {
+        target,
+        host: app.selected_host,
+        server: app.selected_server,
+    }))
 }
 
 fn ui(frame: &mut Frame<'_>, app: &mut App) {
-    let area = frame.area();
+    let banner = error_banner(app);
+    let [banner_area, area, status_area] = Layout::vertical([
+        Constraint::Length(u16::from(banner.is_some())),
+        Constraint::Min(0),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    if let Some(banner) = banner {
+        let style = Style::default().fg(Color::White).bg(Color::Red);
+        frame.render_widget(Paragraph::new(banner).style(style), banner_area);
+    }
+
+    if let Some(refreshed) = refresh_indicator(app) {
+        let indicator = Paragraph::new(refreshed)
+            .alignment(Alignment::Right)
+            .style(Style::default().fg(Color::DarkGray));
+        frame.render_widget(indicator, status_area);
+    }
+
+    match app.mode {
+        Mode::SearchInput => {
+            let input = Paragraph::new(format!("/{}", app.search_query));
+            frame.render_widget(input, status_area);
+        }
+        Mode::NewSession => {
+            let input = Paragraph::new(format!("New session name: {}", app.new_session_name));
+            frame.render_widget(input, status_area);
+        }
+        Mode::Sessions | Mode::SearchResults | Mode::Errors => {
+            if let Some(message) = &app.status_message {
+                frame.render_widget(Paragraph::new(message.as_str()), status_area);
+            } else if let Some(note) = app.selected_note().filter(|_| app.mode == Mode::Sessions) {
+                let note = Paragraph::new(format!("Note: {note}")).style(Style::default().italic());
+                frame.render_widget(note, status_area);
+            }
+        }
+    }
+
+    match app.mode {
+        Mode::SearchResults => render_search_results(frame, area, app),
+        Mode::Errors => render_errors(frame, area, app),
+        _ if app.sessions.is_empty() => render_empty_state(frame, area, app),
+        _ => render_sessions(frame, area, app),
+    }
+}
+
+/// Explains why there are no sessions to show.
+fn empty_state_message(app: &App) -> String {
+    match &app.refresh_error {
+        Some(RefreshError::NoServer) => {
+            "No tmux server running — press n to create a session".to_string()
+        }
+        Some(RefreshError::NotInstalled) => {
+            "tmux not installed — install tmux and make sure it is on your PATH".to_string()
+        }
+        Some(error) => error.to_string(),
+        None if app.last_refreshed.is_none() => "Loading sessions…".to_string(),
+        None => "No sessions — press n to create a session".to_string(),
+    }
+}
+
+fn render_empty_state(frame: &mut Frame<'_>, area: Rect, app: &App) {
+    let style = if app.refresh_error.is_some() {
+        Style::default().fg(Color::Red)
+    } else {
+        Style::default()
+    };
+    let [_, line, _] = Layout::vertical([
+        Constraint::Fill(1),
+        Constraint::Length(3),
+        Constraint::Fill(1),
+    ])
+    .areas(area);
 
-    let header = Row::new(vec!["Name", "Status", "Windows", "Working Directory"])
+    frame.render_widget(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" AMS - Agents Manager Service ")
+            .title_bottom(" q:quit  n:new session  r:refresh  ?:errors "),
+        area,
+    );
+    let message = Paragraph::new(empty_state_message(app))
+        .style(style)
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true });
+    frame.render_widget(message, line.inner(Margin::new(2, 0)));
+}
+
+fn render_errors(frame: &mut Frame<'_>, area: Rect, app: &App) {
+    let lines: Vec<Line<'_>> = if app.errors.is_empty() {
+        vec![Line::from("No errors")]
+    } else {
+        app.errors
+            .iter()
+            .rev()
+            .map(|(at, message)| {
+                Line::from(vec![
+                    Span::styled(
+                        format!("{} ", at.format("%H:%M:%S")),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                    Span::raw(message.as_str()),
+                ])
+            })
+            .collect()
+    };
+
+    let history = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" AMS - Error History (newest first) ")
+            .title_bottom(" Esc/?:back "),
+    );
+    frame.render_widget(history, area);
+}
+
+/// Describes the last failed refresh and how stale the shown data is, or
+/// the hosts and servers the last refresh could not list.
+///
+/// With no sessions to show, the empty-state screen explains a failed
+/// refresh instead.
+fn error_banner(app: &App) -> Option<String> {
+    if let Some(error) = &app.refresh_error {
+        if app.sessions.is_empty() {
+            return None;
+        }
+        let stale = app.last_refreshed.map_or_else(String::new, |at| {
+            format!(" (showing sessions from {})", at.format("%H:%M:%S"))
+        });
+        return Some(format!(" tmux error: {error}{stale}"));
+    }
+    (!app.unreachable.is_empty()).then(|| format!(" unreachable: {}", app.unreachable.join(", ")))
+}
+
+/// Formats when the session list was last refreshed successfully, and how
+/// many errors the history holds.
+fn refresh_indicator(app: &App) -> Option<String> {
+    let errors = match app.errors.len() {
+        0 => String::new(),
+        1 => "1 error (?)  ".to_string(),
+        n => format!("{n} errors (?)  "),
+    };
+    let refreshed = app
+        .last_refreshed
+        .map(|at| format!("refreshed {} ", at.format("%H:%M:%S")));
+    match refreshed {
+        None if errors.is_empty() => None,
+        refreshed => Some(errors + refreshed.as_deref().unwrap_or_default()),
+    }
+}
+
+/// Builds the cells of a row for the shown columns.
+///
+/// Group rows show their label and attached count in the first two
+/// columns; window rows show their pane count and pane rows their command,
+/// pid, size and directory; `*` marks the active window and pane, and the
+/// agent pane is labelled.
+fn tree_row_cells(app: &App, row: TreeRow, columns: &[Column]) -> Vec<Cell<'static>> {
+    if let TreeRow::Group(g) = row {
+        let group = &app.groups[g];
+        let marker = if app.collapsed_groups.contains(&group.label) {
+            '▸'
+        } else {
+            '▾'
+        };
+        let attached = group
+            .sessions
+            .iter()
+            .filter(|&&i| app.sessions[i].status == SessionStatus::Active)
+            .count();
+        return vec![
+            Cell::from(format!(
+                "{marker} {} ({})",
+                group.label,
+                group.sessions.len()
+            ))
+            .style(Style::default().bold()),
+            Cell::from(if attached > 0 {
+                format!("{attached} active")
+            } else {
+                String::new()
+            })
+            .style(Style::default().fg(Color::DarkGray)),
+        ];
+    }
+    columns
+        .iter()
+        .map(|&column| {
+            column_cell(app, row, column).map_or_else(Cell::default, |(text, style)| {
+                let text = truncate(&text, column_length(column).into());
+                Cell::from(text).style(style)
+            })
+        })
+        .collect()
+}
+
+/// Builds the text and style of one column of a session, window or pane
+/// row, or `None` if the row has nothing to show there.
+fn column_cell(app: &App, row: TreeRow, column: Column) -> Option<(String, Style)> {
+    let dim = Style::default().fg(Color::DarkGray);
+    let active = |active: bool| if active { " *" } else { "" };
+    let session = &app.sessions[row.session()?];
+
+    match row {
+        TreeRow::Group(_) => None,
+        TreeRow::Session(_) => session_cell(app, session, column),
+        TreeRow::Window(i, w) => {
+            let window = &app.window_node(i, w)?.window;
+            match column {
+                Column::Name => Some((
+                    format!(
+                        "  {}: {}{}",
+                        window.index,
+                        window.name,
+                        active(window.active)
+                    ),
+                    Style::default(),
+                )),
+                Column::Windows => Some((
+                    match window.pane_count {
+                        1 => "1 pane".to_string(),
+                        n => format!("{n} panes"),
+                    },
+                    dim,
+                )),
+                _ => None,
+            }
+        }
+        TreeRow::Pane(i, w, p) => {
+            let pane = app.window_node(i, w)?.panes.get(p)?;
+            let cell = match column {
+                Column::Name => {
+                    let agent = session
+                        .agent_pane()
+                        .is_some_and(|agent| agent.target() == pane.target());
+                    return Some((
+                        format!(
+                            "    .{} {}{}{}",
+                            pane.index,
+                            pane.current_command,
+                            active(pane.active),
+                            if agent { " (agent)" } else { "" }
+                        ),
+                        Style::default(),
+                    ));
+                }
+                Column::Status => format!("pid {}", pane.pid),
+                Column::Windows => format!("{}x{}", pane.width, pane.height),
+                Column::Dir => shorten_path(app, &pane.current_path),
+                Column::LastActivity => format_age(pane.last_activity),
+                _ => return None,
+            };
+            Some((cell, dim))
+        }
+    }
+}
+
+/// Builds the text and style of one column of a session row.
+fn session_cell(app: &App, session: &Session, column: Column) -> Option<(String, Style)> {
+    let details = app.details.get(&SessionKey::from(session));
+    let text = match column {
+        Column::Name => {
+            let marker = if app.expanded.contains(&SessionKey::from(session)) {
+                '▾'
+            } else {
+                '▸'
+            };
+            let indent = if app.grouping == Grouping::None {
+                ""
+            } else {
+                "  "
+            };
+            format!("{indent}{marker} {}", session.name)
+        }
+        Column::Status => {
+            let color = match session.status {
+                SessionStatus::Active => Color::Green,
+                SessionStatus::Idle => Color::Yellow,
+                SessionStatus::Dead => Color::Red,
+            };
+            return Some((session.status.to_string(), Style::default().fg(color)));
+        }
+        Column::Agent => agent_label(session)?.to_string(),
+        Column::State => {
+            let state = details?.state?;
+            let color = match state {
+                AgentState::NeedsApproval | AgentState::Errored => Color::Red,
+                AgentState::WaitingForInput => Color::Yellow,
+                AgentState::Working => Color::Green,
+                AgentState::Finished => Color::DarkGray,
+            };
+            return Some((state.to_string(), Style::default().fg(color)));
+        }
+        Column::Windows => session.window_count.to_string(),
+        Column::Dir => shorten_path(app, &session.working_directory),
+        Column::Branch => details?.branch.clone()?,
+        Column::LastActivity => format_age(session.last_activity),
+        Column::Age => format_age(session.created_at),
+        Column::Cpu => format!("{:.1}%", details?.usage?.cpu),
+        Column::Memory => format_memory(details?.usage?.memory_kb),
+        Column::Tags => {
+            return Some((session.tags.join(", "), Style::default().fg(Color::Cyan)));
+        }
+    };
+    Some((text, Style::default()))
+}
+
+/// Abbreviates a path to the width of the directory column, writing the
+/// home directory as `~`.
+fn shorten_path(app: &App, path: &Path) -> String {
+    abbreviate_path(path, app.home.as_deref(), column_length(Column::Dir).into())
+}
+
+fn render_sessions(frame: &mut Frame<'_>, area: Rect, app: &mut App) {
+    let show_host = app.sessions.iter().any(|session| session.host.is_some());
+    let show_server = app.sessions.iter().any(|session| session.server.is_some());
+    let columns = app.visible_columns();
+    let mut header: Vec<String> = columns
+        .iter()
+        .map(|&column| {
+            let title = column_title(column);
+            match (column == app.sort.column, app.sort.reverse) {
+                (false, _) => title.to_string(),
+                (true, false) => format!("{title} ▲"),
+                (true, true) => format!("{title} ▼"),
+            }
+        })
+        .collect();
+    let mut widths: Vec<Constraint> = columns.iter().map(|&column| column_width(column)).collect();
+    if show_server {
+        header.insert(0, "Server".to_string());
+        widths.insert(0, Constraint::Length(12));
+    }
+    if show_host {
+        header.insert(0, "Host".to_string());
+        widths.insert(0, Constraint::Length(12));
+    }
+    let header = Row::new(header)
         .style(Style::default().bold())
         .bottom_margin(1);
 
     let rows: Vec<Row<'_>> = app
-        .sessions
-        .iter()
-        .map(|session| {
-            let status_style = match session.status {
-                SessionStatus::Active => Style::default().fg(Color::Green),
-                SessionStatus::Idle => Style::default().fg(Color::Yellow),
-                SessionStatus::Dead => Style::default().fg(Color::Red),
+        .rows()
+        .into_iter()
+        .map(|row| {
+            let mut cells = tree_row_cells(app, row, &columns);
+            let session = match row {
+                TreeRow::Session(i) => Some(&app.sessions[i]),
+                _ => None,
             };
+            if show_server {
+                let server = session.and_then(|session| session.server.as_deref());
+                cells.insert(0, Cell::from(server.unwrap_or_default().to_string()));
+            }
+            if show_host {
+                let host = session.and_then(|session| session.host.as_deref());
+                cells.insert(0, Cell::from(host.unwrap_or_default().to_string()));
+            }
+            Row::new(cells)
+        })
+        .collect();
 
-            let working_dir = session
-                .working_directory
-                .to_string_lossy()
-                .chars()
-                .rev()
-                .take(40)
-                .collect::<String>()
-                .chars()
-                .rev()
-                .collect::<String>();
+    let table = Table::new(rows, widths)
+        .header(header)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!(
+                    " AMS - Agents Manager Service{} ",
+                    app.grouping.title()
+                ))
+                .title_bottom(
+                    " q:quit  j/k:nav  Space:tree  Enter:attach  g:group  s/S:sort  n:new  r:refresh  /:search  ?:errors ",
+                ),
+        )
+        .row_highlight_style(Style::default().bg(Color::DarkGray))
+        .highlight_symbol("▶ ");
+
+    frame.render_stateful_widget(table, area, &mut app.table_state);
+}
 
+fn render_search_results(frame: &mut Frame<'_>, area: Rect, app: &mut App) {
+    let header = Row::new(vec!["Pane", "Line", "Text"])
+        .style(Style::default().bold())
+        .bottom_margin(1);
+
+    let rows: Vec<Row<'_>> = app
+        .search_matches
+        .iter()
+        .map(|m| {
             Row::new(vec![
-                Cell::from(session.name.clone()),
-                Cell::from(session.status.to_string()).style(status_style),
-                Cell::from(session.window_count.to_string()),
-                Cell::from(working_dir),
+                Cell::from(m.pane.to_string()),
+                Cell::from(m.line_number.to_string()),
+                Cell::from(m.line.trim_end().to_string()),
             ])
         })
         .collect();
 
     let widths = [
-        Constraint::Length(20),
-        Constraint::Length(10),
-        Constraint::Length(8),
+        Constraint::Length(24),
+        Constraint::Length(7),
         Constraint::Min(20),
     ];
 
@@ -198,20 +1677,224 @@ fn ui(frame: &mut Frame<'_>, app: &mut App) {
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .title(" AMS - Agents Manager Service ")
-                .title_bottom(" q:quit  j/k:nav  Enter:attach  r:refresh "),
+                .title(" AMS - Scrollback Search ")
+                .title_bottom(" Esc:back  j/k:nav  Enter:attach to pane  /:new search "),
         )
         .row_highlight_style(Style::default().bg(Color::DarkGray))
         .highlight_symbol("▶ ");
 
-    frame.render_stateful_widget(table, area, &mut app.table_state);
+    frame.render_stateful_widget(table, area, &mut app.search_state);
+}
+
+/// Playback state of a recording shown in the preview pane.
+struct Player {
+    parser: vt100::Parser,
+    title: String,
+    /// Output events with idle gaps already clamped
+    events: Vec<(f64, String)>,
+    next_event: usize,
+    position: f64,
+    speed: f64,
+    paused: bool,
+}
+
+impl Player {
+    fn new(recording: &Recording, speed: f64) -> Self {
+        let limit = recording.header.idle_time_limit.unwrap_or(f64::INFINITY);
+        let mut events = Vec::new();
+        let (mut last_raw, mut last_adjusted) = (0.0_f64, 0.0_f64);
+        for event in recording.output() {
+            last_adjusted += (event.time - last_raw).clamp(0.0, limit);
+            last_raw = event.time;
+            events.push((last_adjusted, event.data.clone()));
+        }
+
+        Self {
+            parser: vt100::Parser::new(recording.header.height, recording.header.width, 0),
+            title: recording
+                .header
+                .title
+                .clone()
+                .unwrap_or_else(|| "recording".to_string()),
+            events,
+            next_event: 0,
+            position: 0.0,
+            speed,
+            paused: false,
+        }
+    }
+
+    fn duration(&self) -> f64 {
+        self.events.last().map_or(0.0, |(time, _)| *time)
+    }
+
+    fn finished(&self) -> bool {
+        self.next_event >= self.events.len()
+    }
+
+    /// Moves playback forward by `elapsed` wall-clock seconds.
+    fn advance(&mut self, elapsed: f64) {
+        if self.paused {
+            return;
+        }
+        self.position = elapsed
+            .mul_add(self.speed, self.position)
+            .min(self.duration());
+        while let Some((time, data)) = self.events.get(self.next_event) {
+            if *time > self.position {
+                break;
+            }
+            self.parser.process(data.as_bytes());
+            self.next_event += 1;
+        }
+    }
+
+    /// Handles a key press, returning true when playback should stop.
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            KeyCode::Char(' ') => self.paused = !self.paused,
+            KeyCode::Char('+' | '=') => self.speed = (self.speed * 2.0).min(64.0),
+            KeyCode::Char('-') => self.speed = (self.speed / 2.0).max(0.125),
+            _ => {}
+        }
+        false
+    }
+}
+
+/// Plays back an asciicast recording in the preview pane
+pub fn replay(recording: &Recording, speed: f64) -> io::Result<()> {
+    install_panic_hook();
+    let mut terminal = init_terminal()?;
+    let mut player = Player::new(recording, speed);
+    let mut last_tick = Instant::now();
+
+    loop {
+        let now = Instant::now();
+        player.advance(now.duration_since(last_tick).as_secs_f64());
+        last_tick = now;
+
+        terminal.draw(|frame| render_player(frame, frame.area(), &player))?;
+
+        if event::poll(std::time::Duration::from_millis(16))? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && player.handle_key(key.code) {
+                    break;
+                }
+            }
+        }
+    }
+
+    restore_terminal()
+}
+
+fn render_player(frame: &mut Frame<'_>, area: Rect, player: &Player) {
+    let state = if player.finished() {
+        "finished"
+    } else if player.paused {
+        "paused"
+    } else {
+        "playing"
+    };
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!(" Replay: {} ", player.title))
+        .title(
+            Line::from(format!(
+                " {state} {:.1}s / {:.1}s  {}x ",
+                player.position,
+                player.duration(),
+                player.speed
+            ))
+            .right_aligned(),
+        )
+        .title_bottom(" q:quit  space:pause  +/-:speed ");
+
+    let preview = Paragraph::new(screen_lines(player.parser.screen())).block(block);
+    frame.render_widget(preview, area);
+}
+
+/// Converts a virtual terminal screen into styled lines for rendering.
+fn screen_lines(screen: &vt100::Screen) -> Vec<Line<'static>> {
+    let (rows, cols) = screen.size();
+    (0..rows)
+        .map(|row| {
+            // Trailing blank cells are left out so lines don't pad the pane
+            let end = (0..cols)
+                .rev()
+                .find(|&col| {
+                    screen.cell(row, col).is_some_and(|cell| {
+                        cell.has_contents() || cell.bgcolor() != vt100::Color::Default
+                    })
+                })
+                .map_or(0, |col| col + 1);
+
+            let mut spans: Vec<Span<'static>> = Vec::new();
+            let mut text = String::new();
+            let mut style = Style::default();
+
+            for col in 0..end {
+                let Some(cell) = screen.cell(row, col) else {
+                    continue;
+                };
+                if cell.is_wide_continuation() {
+                    continue;
+                }
+
+                let cell_style = cell_style(cell);
+                if cell_style != style && !text.is_empty() {
+                    spans.push(Span::styled(std::mem::take(&mut text), style));
+                }
+                style = cell_style;
+
+                if cell.has_contents() {
+                    text.push_str(&cell.contents());
+                } else {
+                    text.push(' ');
+                }
+            }
+
+            if !text.is_empty() {
+                spans.push(Span::styled(text, style));
+            }
+            Line::from(spans)
+        })
+        .collect()
+}
+
+fn cell_style(cell: &vt100::Cell) -> Style {
+    let convert = |color: vt100::Color| match color {
+        vt100::Color::Default => Color::Reset,
+        vt100::Color::Idx(i) => Color::Indexed(i),
+        vt100::Color::Rgb(r, g, b) => Color::Rgb(r, g, b),
+    };
+
+    let mut style = Style::default()
+        .fg(convert(cell.fgcolor()))
+        .bg(convert(cell.bgcolor()));
+    if cell.bold() {
+        style = style.add_modifier(Modifier::BOLD);
+    }
+    if cell.italic() {
+        style = style.add_modifier(Modifier::ITALIC);
+    }
+    if cell.underline() {
+        style = style.add_modifier(Modifier::UNDERLINED);
+    }
+    if cell.inverse() {
+        style = style.add_modifier(Modifier::REVERSED);
+    }
+    style
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::Utc;
-    use std::path::PathBuf;
+    use crate::session::PaneTarget;
+    use chrono::{TimeZone, Utc};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
 
     fn create_test_session(name: &str, status: SessionStatus) -> Session {
         Session {
@@ -221,6 +1904,7 @@ mod tests {
             last_activity: Utc::now(),
             created_at: Utc::now(),
             window_count: 1,
+            ..Session::default()
         }
     }
 
@@ -233,6 +1917,23 @@ mod tests {
         assert!(app.selected_session.is_none());
     }
 
+    #[test]
+    fn test_set_sessions_keeps_selection_in_range() {
+        let mut app = App::new();
+        app.set_sessions(vec![
+            create_test_session("a", SessionStatus::Idle),
+            create_test_session("b", SessionStatus::Idle),
+        ]);
+        assert_eq!(app.table_state.selected(), Some(0));
+        app.table_state.select(Some(1));
+
+        app.set_sessions(vec![create_test_session("a", SessionStatus::Idle)]);
+        assert_eq!(app.table_state.selected(), Some(0));
+
+        app.set_sessions(Vec::new());
+        assert!(app.table_state.selected().is_none());
+    }
+
     #[test]
     fn test_app_default() {
         let app = App::default();
@@ -333,6 +2034,23 @@ mod tests {
 
         assert!(app.should_quit);
         assert_eq!(app.selected_session, Some("session2".to_string()));
+        assert!(app.selected_server.is_none());
+    }
+
+    #[test]
+    fn test_app_select_current_keeps_host_and_server() {
+        let mut app = App::new();
+        let mut session = create_test_session("agent", SessionStatus::Idle);
+        session.host = Some("devbox".to_string());
+        session.server = Some("agents".to_string());
+        app.sessions = vec![session];
+        app.table_state.select(Some(0));
+
+        app.select_current();
+
+        assert_eq!(app.selected_session, Some("agent".to_string()));
+        assert_eq!(app.selected_host, Some("devbox".to_string()));
+        assert_eq!(app.selected_server, Some("agents".to_string()));
     }
 
     #[test]
@@ -360,4 +2078,776 @@ mod tests {
         assert!(!app.should_quit);
         assert!(app.selected_session.is_none());
     }
+
+    fn create_test_match(session: &str, pane_index: u32) -> SearchMatch {
+        SearchMatch {
+            pane: PaneTarget {
+                session: session.to_string(),
+                window_index: 0,
+                pane_index,
+            },
+            line_number: 1,
+            line: "error: boom".to_string(),
+            before: Vec::new(),
+            after: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_handle_key_search_input() {
+        let mut app = App::new();
+        app.handle_key(KeyCode::Char('/'));
+        assert_eq!(app.mode, Mode::SearchInput);
+
+        app.handle_key(KeyCode::Char('q'));
+        app.handle_key(KeyCode::Char('x'));
+        app.handle_key(KeyCode::Backspace);
+        assert_eq!(app.search_query, "q");
+        assert!(!app.should_quit);
+
+        app.handle_key(KeyCode::Esc);
+        assert_eq!(app.mode, Mode::Sessions);
+        assert!(!app.should_quit);
+    }
+
+    #[test]
+    fn test_handle_key_search_empty_query_ignored() {
+        let mut app = App::new();
+        app.handle_key(KeyCode::Char('/'));
+        app.handle_key(KeyCode::Enter);
+        assert_eq!(app.mode, Mode::SearchInput);
+    }
+
+    #[test]
+    fn test_run_search_invalid_pattern() {
+        let mut app = App::new();
+        app.handle_key(KeyCode::Char('/'));
+        app.handle_key(KeyCode::Char('('));
+        app.handle_key(KeyCode::Enter);
+        assert_eq!(app.mode, Mode::Sessions);
+        assert!(app
+            .status_message
+            .as_deref()
+            .is_some_and(|m| m.starts_with("Invalid pattern")));
+    }
+
+    #[test]
+    fn test_search_results_navigation_and_select() {
+        let mut app = App::new();
+        app.search_query = "error".to_string();
+        app.show_search_results(vec![create_test_match("a", 0), create_test_match("b", 1)]);
+        assert_eq!(app.mode, Mode::SearchResults);
+        assert_eq!(app.search_state.selected(), Some(0));
+        assert_eq!(app.status_message.as_deref(), Some("2 matches for /error"));
+
+        app.handle_key(KeyCode::Char('j'));
+        assert_eq!(app.search_state.selected(), Some(1));
+        app.handle_key(KeyCode::Char('j'));
+        assert_eq!(app.search_state.selected(), Some(0));
+        app.handle_key(KeyCode::Char('k'));
+        assert_eq!(app.search_state.selected(), Some(1));
+
+        app.handle_key(KeyCode::Enter);
+        assert!(app.should_quit);
+        assert_eq!(app.selected_session, Some("b:0.1".to_string()));
+    }
+
+    #[test]
+    fn test_search_results_empty() {
+        let mut app = App::new();
+        app.show_search_results(Vec::new());
+        assert!(app.search_state.selected().is_none());
+        app.handle_key(KeyCode::Enter);
+        assert!(!app.should_quit);
+    }
+
+    #[test]
+    fn test_search_results_back() {
+        let mut app = App::new();
+        app.show_search_results(vec![create_test_match("a", 0)]);
+        app.handle_key(KeyCode::Char('q'));
+        assert_eq!(app.mode, Mode::Sessions);
+        assert!(app.status_message.is_none());
+        assert!(!app.should_quit);
+    }
+
+    #[test]
+    fn test_handle_key_sessions() {
+        let mut app = App::new();
+        app.sessions = vec![
+            create_test_session("session1", SessionStatus::Active),
+            create_test_session("session2", SessionStatus::Idle),
+        ];
+        app.table_state.select(Some(0));
+        app.handle_key(KeyCode::Down);
+        assert_eq!(app.table_state.selected(), Some(1));
+        app.handle_key(KeyCode::Up);
+        assert_eq!(app.table_state.selected(), Some(0));
+        app.handle_key(KeyCode::Char('r'));
+        assert!(app.refresh_requested);
+        app.handle_key(KeyCode::Char('q'));
+        assert!(app.should_quit);
+    }
+
+    fn snapshot(sessions: Result<Vec<Session>, TmuxError>) -> Snapshot {
+        Snapshot {
+            sessions,
+            unreachable: Vec::new(),
+            trees: HashMap::new(),
+            details: HashMap::new(),
+            taken_at: Local.with_ymd_and_hms(2026, 1, 2, 9, 30, 5).unwrap(),
+        }
+    }
+
+    /// An app with sessions `a` and `b`, where `a` has a window with two
+    /// panes and is expanded
+    fn create_tree_app() -> App {
+        let mut app = App::new();
+        let a = create_test_session("a", SessionStatus::Idle);
+        let pane = |index: u32| Pane {
+            session: "a".to_string(),
+            window_index: 1,
+            index,
+            active: index == 0,
+            window_active: true,
+            current_command: "claude".to_string(),
+            pid: 100 + index,
+            width: 80,
+            height: 24,
+            current_path: PathBuf::from("/src"),
+            last_activity: Utc::now(),
+        };
+        let node = WindowNode {
+            window: Window {
+                session: "a".to_string(),
+                index: 1,
+                name: "agent".to_string(),
+                active: true,
+                pane_count: 2,
+                width: 160,
+                height: 24,
+            },
+            panes: vec![pane(0), pane(1)],
+        };
+        app.trees.insert(SessionKey::from(&a), vec![node]);
+        app.expanded.insert(SessionKey::from(&a));
+        app.set_sessions(vec![a, create_test_session("b", SessionStatus::Idle)]);
+        app
+    }
+
+    #[test]
+    fn test_rows_include_expanded_tree() {
+        let mut app = create_tree_app();
+        assert_eq!(
+            app.rows(),
+            vec![
+                TreeRow::Session(0),
+                TreeRow::Window(0, 0),
+                TreeRow::Pane(0, 0, 0),
+                TreeRow::Pane(0, 0, 1),
+                TreeRow::Session(1),
+            ]
+        );
+
+        app.expanded.clear();
+        assert_eq!(app.rows(), vec![TreeRow::Session(0), TreeRow::Session(1)]);
+    }
+
+    #[test]
+    fn test_select_window_and_pane_targets() {
+        let mut app = create_tree_app();
+        app.table_state.select(Some(1));
+        app.select_current();
+        assert_eq!(app.selected_session, Some("a:1".to_string()));
+
+        let mut app = create_tree_app();
+        app.table_state.select(Some(3));
+        app.select_current();
+        assert_eq!(app.selected_session, Some("a:1.1".to_string()));
+    }
+
+    #[test]
+    fn test_navigation_walks_tree_rows() {
+        let mut app = create_tree_app();
+        for _ in 0..4 {
+            app.handle_key(KeyCode::Char('j'));
+        }
+        assert_eq!(app.selected_row(), Some(TreeRow::Session(1)));
+    }
+
+    #[test]
+    fn test_expand_and_collapse() {
+        let mut app = create_tree_app();
+        app.table_state.select(Some(4));
+        app.handle_key(KeyCode::Char('l'));
+        assert!(app.expanded_changed);
+        assert_eq!(app.expanded.len(), 2);
+
+        // Collapsing from a pane row returns to its session
+        app.table_state.select(Some(2));
+        app.handle_key(KeyCode::Left);
+        assert_eq!(app.selected_row(), Some(TreeRow::Session(0)));
+        assert!(!app.expanded.contains(&SessionKey::from(&app.sessions[0])));
+
+        app.handle_key(KeyCode::Char(' '));
+        assert!(app.expanded.contains(&SessionKey::from(&app.sessions[0])));
+    }
+
+    fn tagged(name: &str, tags: &[&str]) -> Session {
+        Session {
+            tags: tags.iter().map(ToString::to_string).collect(),
+            ..create_test_session(name, SessionStatus::Idle)
+        }
+    }
+
+    #[test]
+    fn test_group_sessions_by_tag() {
+        let sessions = [
+            tagged("a", &["ticket:ABC-12", "frontend"]),
+            tagged("b", &["frontend"]),
+            tagged("c", &[]),
+        ];
+        let groups = group_sessions(&sessions, Grouping::Tag);
+        let summary: Vec<(&str, &[usize])> = groups
+            .iter()
+            .map(|group| (group.label.as_str(), group.sessions.as_slice()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("frontend", &[0, 1][..]),
+                ("ticket:ABC-12", &[0][..]),
+                ("untagged", &[2][..]),
+            ]
+        );
+        assert!(group_sessions(&sessions, Grouping::None).is_empty());
+    }
+
+    #[test]
+    fn test_group_sessions_by_agent_profile() {
+        let mut queued = create_test_session("task-1", SessionStatus::Idle);
+        queued.profile = Some("codex".to_string());
+        let sessions = [queued, create_test_session("shell", SessionStatus::Idle)];
+        let labels: Vec<String> = group_sessions(&sessions, Grouping::Agent)
+            .into_iter()
+            .map(|group| group.label)
+            .collect();
+        assert_eq!(labels, vec!["codex", "unknown agent"]);
+    }
+
+    #[test]
+    fn test_grouped_rows_collapse() {
+        let mut app = App::new();
+        app.set_sessions(vec![tagged("a", &["x", "y"]), tagged("b", &["y"])]);
+        app.table_state.select(Some(1));
+        app.handle_key(KeyCode::Char('g'));
+        assert_eq!(app.grouping, Grouping::Tag);
+        assert_eq!(
+            app.rows(),
+            vec![
+                TreeRow::Group(0),
+                TreeRow::Session(0),
+                TreeRow::Group(1),
+                TreeRow::Session(0),
+                TreeRow::Session(1),
+            ]
+        );
+        // The selected session stays selected
+        assert_eq!(app.selected_row(), Some(TreeRow::Session(1)));
+
+        app.table_state.select(Some(2));
+        app.handle_key(KeyCode::Char(' '));
+        assert_eq!(app.rows().len(), 3);
+        app.handle_key(KeyCode::Enter);
+        assert!(!app.should_quit);
+        assert_eq!(app.rows().len(), 5);
+        app.handle_key(KeyCode::Left);
+        assert!(app.collapsed_groups.contains("y"));
+        app.handle_key(KeyCode::Right);
+        assert!(app.collapsed_groups.is_empty());
+
+        for _ in 0..3 {
+            app.handle_key(KeyCode::Char('g'));
+        }
+        assert_eq!(app.grouping, Grouping::None);
+        assert_eq!(app.rows().len(), 2);
+    }
+
+    #[test]
+    fn test_collapse_returns_to_row_in_same_group() {
+        let mut app = create_tree_app();
+        app.sessions[0].tags = vec!["x".to_string(), "y".to_string()];
+        app.handle_key(KeyCode::Char('g'));
+        // Group x: a and its tree, group y: a and its tree, then b's group
+        let rows = app.rows();
+        assert_eq!(rows[5], TreeRow::Group(1));
+        app.table_state.select(Some(8));
+        assert_eq!(app.selected_row(), Some(TreeRow::Pane(0, 0, 0)));
+        app.handle_key(KeyCode::Left);
+        assert_eq!(app.table_state.selected(), Some(6));
+    }
+
+    #[test]
+    fn test_selected_note() {
+        let mut app = App::new();
+        let mut session = create_test_session("a", SessionStatus::Idle);
+        session.note = Some("waiting on review".to_string());
+        app.set_sessions(vec![session, create_test_session("b", SessionStatus::Idle)]);
+        assert_eq!(app.selected_note(), Some("waiting on review"));
+        app.handle_key(KeyCode::Down);
+        assert_eq!(app.selected_note(), None);
+    }
+
+    fn session_names(app: &App) -> Vec<&str> {
+        app.sessions.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_with_config_always_shows_name() {
+        let config = TuiConfig {
+            columns: vec![Column::State, Column::Cpu],
+            ..TuiConfig::default()
+        };
+        let app = App::with_config(&config);
+        assert_eq!(app.columns, vec![Column::Name, Column::State, Column::Cpu]);
+    }
+
+    #[test]
+    fn test_sort_by_column_and_reverse() {
+        let mut app = App::with_config(&TuiConfig {
+            sort: Column::Windows,
+            ..TuiConfig::default()
+        });
+        let mut a = create_test_session("a", SessionStatus::Idle);
+        a.window_count = 3;
+        let b = create_test_session("b", SessionStatus::Idle);
+        let c = create_test_session("c", SessionStatus::Idle);
+        app.set_sessions(vec![a, c, b]);
+        assert_eq!(session_names(&app), vec!["b", "c", "a"]);
+
+        app.handle_key(KeyCode::Char('S'));
+        assert_eq!(session_names(&app), vec!["a", "c", "b"]);
+    }
+
+    #[test]
+    fn test_sort_by_state_puts_unknown_last() {
+        let mut app = App::with_config(&TuiConfig {
+            sort: Column::State,
+            ..TuiConfig::default()
+        });
+        let sessions = ["a", "b", "c"].map(|name| create_test_session(name, SessionStatus::Idle));
+        for (session, state) in [
+            (&sessions[1], AgentState::Working),
+            (&sessions[2], AgentState::NeedsApproval),
+        ] {
+            let details = Details {
+                state: Some(state),
+                ..Details::default()
+            };
+            app.details.insert(SessionKey::from(session), details);
+        }
+        app.set_sessions(sessions.to_vec());
+        assert_eq!(session_names(&app), vec!["c", "b", "a"]);
+    }
+
+    #[test]
+    fn test_cycle_sort_keeps_selected_session() {
+        let mut app = App::with_config(&TuiConfig {
+            columns: vec![Column::Name, Column::Status],
+            ..TuiConfig::default()
+        });
+        app.set_sessions(vec![
+            create_test_session("a", SessionStatus::Idle),
+            create_test_session("b", SessionStatus::Active),
+        ]);
+        app.handle_key(KeyCode::Down);
+
+        app.handle_key(KeyCode::Char('s'));
+        assert_eq!(app.sort.column, Column::Status);
+        assert_eq!(session_names(&app), vec!["b", "a"]);
+        assert_eq!(app.table_state.selected(), Some(0));
+
+        // Tags are skipped while no session has any.
+        app.handle_key(KeyCode::Char('s'));
+        assert_eq!(app.sort.column, Column::Name);
+        assert_eq!(app.table_state.selected(), Some(1));
+    }
+
+    #[test]
+    fn test_visible_columns_hide_tags_until_tagged() {
+        let mut app = App::new();
+        app.set_sessions(vec![create_test_session("a", SessionStatus::Idle)]);
+        assert!(!app.visible_columns().contains(&Column::Tags));
+        app.set_sessions(vec![tagged("a", &["auth"])]);
+        assert!(app.visible_columns().contains(&Column::Tags));
+    }
+
+    #[test]
+    fn test_row_cells_follow_columns() {
+        let mut app = create_tree_app();
+        app.details.insert(
+            SessionKey::from(&app.sessions[0]),
+            Details {
+                branch: Some("main".to_string()),
+                ..Details::default()
+            },
+        );
+        let columns = [Column::Name, Column::Branch, Column::Windows, Column::Cpu];
+        let cells = |row| tree_row_cells(&app, row, &columns);
+
+        assert_eq!(
+            cells(TreeRow::Session(0)),
+            vec![
+                Cell::from("▾ a"),
+                Cell::from("main"),
+                Cell::from("1"),
+                Cell::default(),
+            ]
+        );
+        assert_eq!(
+            cells(TreeRow::Window(0, 0))[2],
+            Cell::from("2 panes").dark_gray()
+        );
+        assert_eq!(
+            cells(TreeRow::Pane(0, 0, 1))[0],
+            Cell::from("    .1 claude")
+        );
+        assert_eq!(cells(TreeRow::Session(1))[1], Cell::default());
+    }
+
+    #[test]
+    fn test_apply_snapshot_replaces_trees() {
+        let mut app = create_tree_app();
+        app.apply_snapshot(snapshot(Ok(app.sessions.clone())));
+        assert!(app.trees.is_empty());
+        assert_eq!(app.rows().len(), 2);
+    }
+
+    #[test]
+    fn test_shorten_path() {
+        let mut app = App::new();
+        app.home = Some(PathBuf::from("/home/me"));
+        assert_eq!(shorten_path(&app, Path::new("/src")), "/src");
+        assert_eq!(shorten_path(&app, Path::new("/home/me/src")), "~/src");
+        let long = format!("/home/me/{}/repo", "x".repeat(50));
+        assert_eq!(shorten_path(&app, Path::new(&long)), "~/…/repo");
+    }
+
+    #[test]
+    fn test_row_cells_truncate_wide_names() {
+        let mut app = App::new();
+        app.set_sessions(vec![create_test_session(
+            "日本語のエージェントセッション",
+            SessionStatus::Idle,
+        )]);
+        let cells = tree_row_cells(&app, TreeRow::Session(0), &[Column::Name]);
+        assert_eq!(cells, vec![Cell::from("▸ 日本語のエージェント…")]);
+    }
+
+    #[test]
+    fn test_apply_snapshot_success() {
+        let mut app = App::new();
+        app.refresh_error = Some(RefreshError::NoServer);
+        app.apply_snapshot(snapshot(Ok(vec![create_test_session(
+            "a",
+            SessionStatus::Idle,
+        )])));
+        assert_eq!(app.sessions.len(), 1);
+        assert!(app.refresh_error.is_none());
+        assert!(error_banner(&app).is_none());
+        assert_eq!(
+            refresh_indicator(&app).as_deref(),
+            Some("refreshed 09:30:05 ")
+        );
+    }
+
+    #[test]
+    fn test_apply_snapshot_error_keeps_sessions() {
+        let mut app = App::new();
+        assert!(refresh_indicator(&app).is_none());
+        app.apply_snapshot(snapshot(Ok(vec![create_test_session(
+            "a",
+            SessionStatus::Idle,
+        )])));
+        app.apply_snapshot(snapshot(Err(TmuxError::ParseError("bad".to_string()))));
+        assert_eq!(app.sessions.len(), 1);
+        assert_eq!(
+            error_banner(&app).as_deref(),
+            Some(" tmux error: parse failure: bad (showing sessions from 09:30:05)")
+        );
+    }
+
+    #[test]
+    fn test_banner_shows_unreachable_hosts() {
+        let mut app = App::new();
+        let mut partial = snapshot(Ok(vec![create_test_session("a", SessionStatus::Idle)]));
+        partial.unreachable = vec!["gpu (parse failure: ssh: connect to host gpu)".to_string()];
+        app.apply_snapshot(partial);
+        assert_eq!(app.sessions.len(), 1);
+        assert_eq!(
+            error_banner(&app).as_deref(),
+            Some(" unreachable: gpu (parse failure: ssh: connect to host gpu)")
+        );
+        assert_eq!(app.errors.len(), 1);
+
+        app.apply_snapshot(snapshot(Ok(Vec::new())));
+        assert!(error_banner(&app).is_none());
+    }
+
+    #[test]
+    fn test_empty_state_replaces_banner() {
+        let mut app = App::new();
+        assert_eq!(empty_state_message(&app), "Loading sessions…");
+        app.apply_snapshot(snapshot(Err(TmuxError::ServerNotRunning)));
+        assert!(app.sessions.is_empty());
+        assert!(error_banner(&app).is_none());
+        assert_eq!(
+            empty_state_message(&app),
+            "No tmux server running — press n to create a session"
+        );
+
+        app.apply_snapshot(snapshot(Ok(Vec::new())));
+        assert_eq!(
+            empty_state_message(&app),
+            "No sessions — press n to create a session"
+        );
+    }
+
+    #[test]
+    fn test_empty_state_explains_errors() {
+        let mut app = App::new();
+        let missing = io::Error::new(io::ErrorKind::NotFound, "No such file or directory");
+        app.apply_snapshot(snapshot(Err(TmuxError::CommandFailed(missing))));
+        assert_eq!(
+            empty_state_message(&app),
+            "tmux not installed — install tmux and make sure it is on your PATH"
+        );
+
+        app.apply_snapshot(snapshot(Err(TmuxError::ParseError(
+            "Expected 6 fields, got 2: a\tb\n".to_string(),
+        ))));
+        assert_eq!(
+            empty_state_message(&app),
+            "parse failure: Expected 6 fields, got 2: a\tb"
+        );
+    }
+
+    #[test]
+    fn test_refresh_error_classification() {
+        let denied = io::Error::new(io::ErrorKind::PermissionDenied, "denied");
+        assert_eq!(
+            RefreshError::from(&TmuxError::CommandFailed(denied)),
+            RefreshError::Other("Failed to execute tmux command: denied".to_string())
+        );
+        assert_eq!(
+            RefreshError::from(&TmuxError::ServerNotRunning).to_string(),
+            "no tmux server running"
+        );
+    }
+
+    #[test]
+    fn test_error_history_records_changes_only() {
+        let mut app = App::new();
+        app.apply_snapshot(snapshot(Err(TmuxError::ServerNotRunning)));
+        app.apply_snapshot(snapshot(Err(TmuxError::ServerNotRunning)));
+        assert_eq!(app.errors.len(), 1);
+        assert_eq!(app.errors[0].1, "refresh failed: no tmux server running");
+
+        app.apply_snapshot(snapshot(Err(TmuxError::ParseError("x".to_string()))));
+        app.apply_snapshot(snapshot(Ok(Vec::new())));
+        app.apply_snapshot(snapshot(Err(TmuxError::ParseError("x".to_string()))));
+        assert_eq!(app.errors.len(), 3);
+        assert_eq!(
+            refresh_indicator(&app).as_deref(),
+            Some("3 errors (?)  refreshed 09:30:05 ")
+        );
+    }
+
+    #[test]
+    fn test_error_history_is_bounded() {
+        let mut app = App::new();
+        for i in 0..=ERROR_HISTORY_LIMIT {
+            app.record_error(format!("error {i}"));
+        }
+        assert_eq!(app.errors.len(), ERROR_HISTORY_LIMIT);
+        assert_eq!(app.errors[0].1, "error 1");
+        assert_eq!(refresh_indicator(&app).as_deref(), Some("100 errors (?)  "));
+    }
+
+    #[test]
+    fn test_handle_key_error_history() {
+        let mut app = App::new();
+        app.handle_key(KeyCode::Char('?'));
+        assert_eq!(app.mode, Mode::Errors);
+        app.handle_key(KeyCode::Char('j'));
+        assert_eq!(app.mode, Mode::Errors);
+        app.handle_key(KeyCode::Char('?'));
+        assert_eq!(app.mode, Mode::Sessions);
+        assert!(!app.should_quit);
+    }
+
+    #[test]
+    fn test_handle_key_new_session_input() {
+        let mut app = App::new();
+        app.handle_key(KeyCode::Char('n'));
+        assert_eq!(app.mode, Mode::NewSession);
+        for c in "agentx".chars() {
+            app.handle_key(KeyCode::Char(c));
+        }
+        app.handle_key(KeyCode::Backspace);
+        assert_eq!(app.new_session_name, "agent");
+        app.handle_key(KeyCode::Esc);
+        assert_eq!(app.mode, Mode::Sessions);
+
+        app.handle_key(KeyCode::Char('n'));
+        assert!(app.new_session_name.is_empty());
+        app.handle_key(KeyCode::Char(' '));
+        app.handle_key(KeyCode::Enter);
+        assert_eq!(app.mode, Mode::NewSession);
+    }
+
+    /// A snapshot source with one session per snapshot taken, named after
+    /// the count, that reports a change when `changed` is set and blocks
+    /// while `blocked` is set
+    #[derive(Default, Clone)]
+    struct FakeSource {
+        taken: Arc<AtomicUsize>,
+        changed: Arc<AtomicBool>,
+        blocked: Arc<AtomicBool>,
+    }
+
+    impl SnapshotSource for FakeSource {
+        fn take(&mut self, expanded: &BTreeSet<SessionKey>) -> Snapshot {
+            while self.blocked.load(Ordering::SeqCst) {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            let count = self.taken.fetch_add(1, Ordering::SeqCst) + 1;
+            let mut session = create_test_session(&count.to_string(), SessionStatus::Idle);
+            session.note = Some(format!("{} expanded", expanded.len()));
+            snapshot(Ok(vec![session]))
+        }
+
+        fn changed(&mut self) -> bool {
+            self.changed.swap(false, Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn test_refresher_sends_snapshots_on_request() {
+        let source = FakeSource::default();
+        let refresher = Refresher::spawn(Duration::from_secs(3600), source.clone());
+        let name = |snapshot: Snapshot| snapshot.sessions.unwrap()[0].name.clone();
+        let recv = || refresher.snapshots.recv_timeout(Duration::from_secs(10));
+
+        assert_eq!(name(recv().unwrap()), "1");
+        refresher.request();
+        assert_eq!(name(recv().unwrap()), "2");
+
+        source.changed.store(true, Ordering::SeqCst);
+        assert_eq!(name(recv().unwrap()), "3");
+
+        refresher.expand(BTreeSet::from([SessionKey::from(&create_test_session(
+            "a",
+            SessionStatus::Idle,
+        ))]));
+        let expanded = recv().unwrap().sessions.unwrap()[0].note.clone();
+        assert_eq!(expanded.as_deref(), Some("1 expanded"));
+    }
+
+    #[test]
+    fn test_refresher_drop_does_not_wait_for_slow_source() {
+        let source = FakeSource::default();
+        let refresher = Refresher::spawn(Duration::from_secs(3600), source.clone());
+        refresher
+            .snapshots
+            .recv_timeout(Duration::from_secs(10))
+            .unwrap();
+
+        source.blocked.store(true, Ordering::SeqCst);
+        refresher.request();
+        std::thread::sleep(Duration::from_millis(200));
+        let started = Instant::now();
+        drop(refresher);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        source.blocked.store(false, Ordering::SeqCst);
+    }
+
+    fn create_test_recording() -> Recording {
+        let cast = "{\"version\":2,\"width\":20,\"height\":3,\"idle_time_limit\":1.0}\n\
+                    [0.5,\"o\",\"\\u001b[1;31mhello\\u001b[0m\"]\n\
+                    [10.0,\"o\",\" world\"]\n";
+        Recording::parse(cast.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_player_clamps_idle_time() {
+        let player = Player::new(&create_test_recording(), 1.0);
+        assert!((player.duration() - 1.5).abs() < f64::EPSILON);
+        assert_eq!(player.title, "recording");
+    }
+
+    #[test]
+    fn test_player_advance() {
+        let mut player = Player::new(&create_test_recording(), 1.0);
+        player.advance(0.6);
+        assert_eq!(player.next_event, 1);
+        assert!(player.parser.screen().contents().starts_with("hello"));
+
+        player.advance(5.0);
+        assert!(player.finished());
+        assert!((player.position - 1.5).abs() < f64::EPSILON);
+        assert!(player.parser.screen().contents().starts_with("hello world"));
+    }
+
+    #[test]
+    fn test_player_pause_and_speed() {
+        let mut player = Player::new(&create_test_recording(), 1.0);
+        assert!(!player.handle_key(KeyCode::Char(' ')));
+        player.advance(1.0);
+        assert_eq!(player.next_event, 0);
+
+        player.handle_key(KeyCode::Char(' '));
+        player.handle_key(KeyCode::Char('+'));
+        assert!((player.speed - 2.0).abs() < f64::EPSILON);
+        player.advance(0.3);
+        assert_eq!(player.next_event, 1);
+
+        player.handle_key(KeyCode::Char('-'));
+        player.handle_key(KeyCode::Char('-'));
+        assert!((player.speed - 0.5).abs() < f64::EPSILON);
+        assert!(!player.handle_key(KeyCode::Char('x')));
+        assert!(player.handle_key(KeyCode::Char('q')));
+    }
+
+    #[test]
+    fn test_screen_lines_styles() {
+        let mut parser = vt100::Parser::new(2, 10, 0);
+        parser.process(b"\x1b[1;31mab\x1b[0mc\x1b[4;7;3;38;2;1;2;3;48;5;4md");
+        let lines = screen_lines(parser.screen());
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].spans.len(), 3);
+        assert_eq!(lines[0].spans[0].content, "ab");
+        assert_eq!(lines[0].spans[0].style.fg, Some(Color::Indexed(1)));
+        assert!(lines[0].spans[0]
+            .style
+            .add_modifier
+            .contains(Modifier::BOLD));
+        assert_eq!(lines[0].spans[1].content, "c");
+        assert_eq!(lines[0].spans[2].content, "d");
+        assert_eq!(lines[0].spans[2].style.fg, Some(Color::Rgb(1, 2, 3)));
+        assert_eq!(lines[0].spans[2].style.bg, Some(Color::Indexed(4)));
+        assert!(lines[0].spans[2]
+            .style
+            .add_modifier
+            .contains(Modifier::UNDERLINED | Modifier::REVERSED | Modifier::ITALIC));
+        assert!(lines[1].spans.is_empty());
+    }
+
+    #[test]
+    fn test_screen_lines_wide_characters() {
+        let mut parser = vt100::Parser::new(1, 6, 0);
+        parser.process("日本".as_bytes());
+        let lines = screen_lines(parser.screen());
+        assert_eq!(lines[0].spans[0].content, "日本");
+    }
 }
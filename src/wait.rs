@@ -0,0 +1,206 @@
+//! Waiting for a session's agent to reach a state, for `ams wait`.
+
+use crate::cleanup::format_duration;
+use crate::session::AgentState;
+use crate::tmux::TmuxError;
+use crate::watch::{self, StateMatcher};
+use regex::Regex;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// How often the session is checked
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long a state condition must hold before it counts, so an agent that
+/// was just given a prompt is not taken as idle before it starts working
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// Exit status of `ams wait` when the timeout expires
+pub const EXIT_TIMEOUT: i32 = 3;
+
+/// Exit status of `ams wait` when the session disappears
+pub const EXIT_GONE: i32 = 4;
+
+/// Errors that end a wait without the condition being met.
+#[derive(Error, Debug)]
+pub enum WaitError {
+    /// The condition was not met in time.
+    #[error("Timed out after {} waiting for {session} to be {condition}", format_duration(*.timeout))]
+    Timeout {
+        /// Session name
+        session: String,
+        /// The condition as given
+        condition: String,
+        /// How long was waited
+        timeout: Duration,
+    },
+
+    /// The session no longer exists.
+    #[error("Session {0} no longer exists")]
+    Gone(String),
+
+    /// A tmux command failed.
+    #[error(transparent)]
+    Tmux(#[from] TmuxError),
+}
+
+impl WaitError {
+    /// Returns the exit status that tells scripts what happened
+    #[must_use]
+    pub const fn exit_code(&self) -> i32 {
+        match self {
+            Self::Timeout { .. } => EXIT_TIMEOUT,
+            Self::Gone(_) => EXIT_GONE,
+            Self::Tmux(_) => 1,
+        }
+    }
+}
+
+/// What to wait for
+#[derive(Debug, Clone)]
+pub enum Condition {
+    /// The agent is not working: it waits for input or approval, or exited
+    Idle,
+    /// The agent shows its input prompt
+    Waiting,
+    /// The agent exited back to the shell or its pane died
+    Exited,
+    /// The regular expression matches the visible screen
+    Pattern(Regex),
+}
+
+impl Condition {
+    /// Whether the agent's state and screen meet the condition
+    #[must_use]
+    pub fn is_met(&self, state: AgentState, screen: &str) -> bool {
+        match self {
+            Self::Idle => state != AgentState::Working,
+            Self::Waiting => state == AgentState::WaitingForInput,
+            Self::Exited => matches!(state, AgentState::Finished | AgentState::Errored),
+            Self::Pattern(pattern) => pattern.is_match(screen),
+        }
+    }
+
+    /// How long the condition must hold before it counts
+    const fn settle_time(&self) -> Duration {
+        match self {
+            Self::Pattern(_) => Duration::ZERO,
+            _ => SETTLE_TIME,
+        }
+    }
+}
+
+impl std::str::FromStr for Condition {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        if let Some(pattern) = text.strip_prefix("pattern:") {
+            return Regex::new(pattern)
+                .map(Self::Pattern)
+                .map_err(|e| e.to_string());
+        }
+        match text {
+            "idle" => Ok(Self::Idle),
+            "waiting" => Ok(Self::Waiting),
+            "exited" => Ok(Self::Exited),
+            _ => Err(format!(
+                "unknown condition {text:?} (expected idle, waiting, exited or pattern:<regex>)"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Idle => write!(f, "idle"),
+            Self::Waiting => write!(f, "waiting"),
+            Self::Exited => write!(f, "exited"),
+            Self::Pattern(pattern) => write!(f, "pattern:{pattern}"),
+        }
+    }
+}
+
+/// Polls `session` until `condition` is met, giving up after `timeout`
+pub fn wait_for(
+    session: &str,
+    condition: &Condition,
+    matcher: &StateMatcher,
+    timeout: Option<Duration>,
+) -> Result<(), WaitError> {
+    let start = Instant::now();
+    let mut met_since = None;
+    loop {
+        let Some((state, screen)) = watch::observe_session(session, matcher)? else {
+            return Err(WaitError::Gone(session.to_string()));
+        };
+        let now = Instant::now();
+        if condition.is_met(state, &screen) {
+            let since = *met_since.get_or_insert(now);
+            if now - since >= condition.settle_time() {
+                return Ok(());
+            }
+        } else {
+            met_since = None;
+        }
+        if let Some(timeout) = timeout.filter(|&timeout| now - start >= timeout) {
+            return Err(WaitError::Timeout {
+                session: session.to_string(),
+                condition: condition.to_string(),
+                timeout,
+            });
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_condition() {
+        assert!(matches!("idle".parse(), Ok(Condition::Idle)));
+        assert!(matches!("waiting".parse(), Ok(Condition::Waiting)));
+        assert!(matches!("exited".parse(), Ok(Condition::Exited)));
+        let condition: Condition = "pattern:tests? passed".parse().unwrap();
+        assert_eq!(condition.to_string(), "pattern:tests? passed");
+        assert!("pattern:(".parse::<Condition>().is_err());
+        assert!("done".parse::<Condition>().is_err());
+    }
+
+    #[test]
+    fn test_condition_is_met() {
+        let idle = Condition::Idle;
+        assert!(!idle.is_met(AgentState::Working, ""));
+        assert!(idle.is_met(AgentState::NeedsApproval, ""));
+        assert!(idle.is_met(AgentState::Finished, ""));
+
+        assert!(Condition::Waiting.is_met(AgentState::WaitingForInput, ""));
+        assert!(!Condition::Waiting.is_met(AgentState::NeedsApproval, ""));
+
+        assert!(Condition::Exited.is_met(AgentState::Errored, ""));
+        assert!(!Condition::Exited.is_met(AgentState::WaitingForInput, ""));
+
+        let pattern: Condition = "pattern:^All \\d+ tests passed$".parse().unwrap();
+        assert!(!pattern.is_met(AgentState::Working, "running\nAll 12 tests passed\n"));
+        let pattern: Condition = "pattern:(?m)^All \\d+ tests passed$".parse().unwrap();
+        assert!(pattern.is_met(AgentState::Working, "running\nAll 12 tests passed\n"));
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let timeout = WaitError::Timeout {
+            session: "api".to_string(),
+            condition: "idle".to_string(),
+            timeout: Duration::from_secs(20 * 60),
+        };
+        assert_eq!(timeout.exit_code(), EXIT_TIMEOUT);
+        assert_eq!(
+            timeout.to_string(),
+            "Timed out after 20m waiting for api to be idle"
+        );
+        assert_eq!(WaitError::Gone("api".to_string()).exit_code(), EXIT_GONE);
+        assert_eq!(WaitError::Tmux(TmuxError::ServerNotRunning).exit_code(), 1);
+    }
+}
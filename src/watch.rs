@@ -0,0 +1,488 @@
+//! Agent state detection and session state-transition tracking.
+
+use crate::config::{StatePatterns, Trigger};
+use crate::session::{AgentState, Session};
+use crate::tmux::{capture_screen, display_message, get_session, TmuxError};
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Number of non-blank lines at the bottom of the screen that are inspected
+const TAIL_LINES: usize = 10;
+
+/// Compiled state detection patterns
+#[derive(Debug, Clone)]
+pub struct StateMatcher {
+    waiting: Vec<Regex>,
+    needs_approval: Vec<Regex>,
+    errored: Vec<Regex>,
+    shells: Vec<String>,
+}
+
+impl StateMatcher {
+    /// Compiles the configured patterns
+    pub fn new(patterns: &StatePatterns) -> Result<Self, regex::Error> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|p| Regex::new(p))
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        Ok(Self {
+            waiting: compile(&patterns.waiting)?,
+            needs_approval: compile(&patterns.needs_approval)?,
+            errored: compile(&patterns.errored)?,
+            shells: patterns.shells.clone(),
+        })
+    }
+
+    /// Returns a copy that recognises the input prompt by `patterns`
+    /// instead, unless there are none
+    pub fn with_waiting(&self, patterns: &[String]) -> Result<Self, regex::Error> {
+        let mut matcher = self.clone();
+        if !patterns.is_empty() {
+            matcher.waiting = patterns
+                .iter()
+                .map(|p| Regex::new(p))
+                .collect::<Result<_, _>>()?;
+        }
+        Ok(matcher)
+    }
+
+    /// Infers the agent state from the agent pane of a session
+    #[must_use]
+    pub fn detect(&self, pane: &PaneStatus, screen: &str) -> AgentState {
+        let tail: Vec<&str> = screen
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .collect();
+        let tail = &tail[tail.len().saturating_sub(TAIL_LINES)..];
+        let matches = |patterns: &[Regex]| {
+            tail.iter()
+                .any(|line| patterns.iter().any(|p| p.is_match(line)))
+        };
+
+        if pane.dead {
+            return if pane.dead_status == Some(0) {
+                AgentState::Finished
+            } else {
+                AgentState::Errored
+            };
+        }
+
+        if self.shells.contains(&pane.command) {
+            return if matches(&self.errored) {
+                AgentState::Errored
+            } else {
+                AgentState::Finished
+            };
+        }
+
+        if matches(&self.needs_approval) {
+            AgentState::NeedsApproval
+        } else if matches(&self.waiting) {
+            AgentState::WaitingForInput
+        } else if matches(&self.errored) {
+            AgentState::Errored
+        } else {
+            AgentState::Working
+        }
+    }
+}
+
+/// Process and alert information about a session's agent pane
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PaneStatus {
+    /// Foreground command of the pane
+    pub command: String,
+    /// Whether the pane's process has exited (with `remain-on-exit`)
+    pub dead: bool,
+    /// Exit status of a dead pane
+    pub dead_status: Option<i32>,
+    /// A window in the session rang the bell
+    pub bell: bool,
+    /// A window in the session had monitored activity
+    pub activity: bool,
+}
+
+const PANE_STATUS_FORMAT: &str =
+    "#{pane_current_command}\t#{pane_dead}\t#{pane_dead_status}\t#{session_alerts}";
+
+impl PaneStatus {
+    /// Parses the output of [`PANE_STATUS_FORMAT`]
+    fn parse(output: &str) -> Result<Self, TmuxError> {
+        let parts: Vec<&str> = output.split('\t').collect();
+        if parts.len() < 4 {
+            return Err(TmuxError::ParseError(format!(
+                "Expected 4 fields, got {}: {}",
+                parts.len(),
+                output
+            )));
+        }
+
+        // session_alerts lists windows like "1!,3#": ! is bell, # is activity
+        Ok(Self {
+            command: parts[0].to_string(),
+            dead: parts[1] == "1",
+            dead_status: parts[2].parse().ok(),
+            bell: parts[3].contains('!'),
+            activity: parts[3].contains('#'),
+        })
+    }
+}
+
+/// The observed state of one session at a point in time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionSnapshot {
+    /// Session name
+    pub name: String,
+    /// Working directory of the session
+    pub directory: PathBuf,
+    /// Inferred agent state
+    pub state: AgentState,
+    /// A window in the session rang the bell
+    pub bell: bool,
+    /// A window in the session had monitored activity
+    pub activity: bool,
+}
+
+/// Observes the agent state of each session once
+///
+/// Sessions that vanish while being inspected are skipped.
+pub fn snapshot_sessions(
+    sessions: &[Session],
+    matcher: &StateMatcher,
+) -> Result<Vec<SessionSnapshot>, TmuxError> {
+    let mut snapshots = Vec::new();
+
+    for session in sessions {
+        let Some((status, screen)) = observe_pane(&session.agent_target())? else {
+            continue;
+        };
+
+        snapshots.push(SessionSnapshot {
+            state: matcher.detect(&status, &screen),
+            name: session.name.clone(),
+            directory: session.working_directory.clone(),
+            bell: status.bell,
+            activity: status.activity,
+        });
+    }
+
+    Ok(snapshots)
+}
+
+/// Reads the status and visible screen of a pane, or `None` if it is gone
+fn observe_pane(target: &str) -> Result<Option<(PaneStatus, String)>, TmuxError> {
+    let status = match display_message(target, PANE_STATUS_FORMAT) {
+        Ok(output) => PaneStatus::parse(&output)?,
+        Err(TmuxError::SessionNotFound(_)) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    match capture_screen(target) {
+        Ok(screen) => Ok(Some((status, screen))),
+        Err(TmuxError::SessionNotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Observes the agent pane of one session, returning its state and visible
+/// screen, or `None` if the session does not exist
+pub fn observe_session(
+    session: &str,
+    matcher: &StateMatcher,
+) -> Result<Option<(AgentState, String)>, TmuxError> {
+    let session = match get_session(session) {
+        Ok(session) => session,
+        Err(TmuxError::SessionNotFound(_) | TmuxError::ServerNotRunning) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    Ok(observe_pane(&session.agent_target())?
+        .map(|(status, screen)| (matcher.detect(&status, &screen), screen)))
+}
+
+/// Observes the agent state of one session, or `None` if the session does
+/// not exist
+pub fn agent_state(session: &str, matcher: &StateMatcher) -> Result<Option<AgentState>, TmuxError> {
+    Ok(observe_session(session, matcher)?.map(|(state, _)| state))
+}
+
+/// A notable change observed in a session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchEvent {
+    /// What happened
+    pub trigger: Trigger,
+    /// Session name
+    pub session: String,
+    /// Working directory of the session
+    pub directory: PathBuf,
+    /// Agent state before the change
+    pub previous: AgentState,
+    /// Agent state after the change
+    pub state: AgentState,
+}
+
+/// Turns successive snapshots into state-transition events
+#[derive(Debug, Default)]
+pub struct TransitionDetector {
+    previous: HashMap<String, SessionSnapshot>,
+}
+
+impl TransitionDetector {
+    /// Creates a detector with no known sessions
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a new set of snapshots and returns what changed
+    ///
+    /// The first time a session is seen only establishes its baseline.
+    pub fn update(&mut self, snapshots: Vec<SessionSnapshot>) -> Vec<WatchEvent> {
+        let mut events = Vec::new();
+        let mut current = HashMap::new();
+
+        for snapshot in snapshots {
+            if let Some(before) = self.previous.get(&snapshot.name) {
+                let mut push = |trigger| {
+                    events.push(WatchEvent {
+                        trigger,
+                        session: snapshot.name.clone(),
+                        directory: snapshot.directory.clone(),
+                        previous: before.state,
+                        state: snapshot.state,
+                    });
+                };
+
+                if before.state != snapshot.state {
+                    if let Some(trigger) = state_trigger(snapshot.state) {
+                        push(trigger);
+                    }
+                }
+                if snapshot.bell && !before.bell {
+                    push(Trigger::Bell);
+                }
+                if snapshot.activity && !before.activity {
+                    push(Trigger::Activity);
+                }
+            }
+            current.insert(snapshot.name.clone(), snapshot);
+        }
+
+        self.previous = current;
+        events
+    }
+}
+
+const fn state_trigger(state: AgentState) -> Option<Trigger> {
+    match state {
+        AgentState::Working => None,
+        AgentState::WaitingForInput => Some(Trigger::Waiting),
+        AgentState::NeedsApproval => Some(Trigger::NeedsApproval),
+        AgentState::Finished => Some(Trigger::Finished),
+        AgentState::Errored => Some(Trigger::Errored),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher() -> StateMatcher {
+        StateMatcher::new(&StatePatterns::default()).unwrap()
+    }
+
+    fn running(command: &str) -> PaneStatus {
+        PaneStatus {
+            command: command.to_string(),
+            ..PaneStatus::default()
+        }
+    }
+
+    fn snapshot(name: &str, state: AgentState) -> SessionSnapshot {
+        SessionSnapshot {
+            name: name.to_string(),
+            directory: PathBuf::from("/repo"),
+            state,
+            bell: false,
+            activity: false,
+        }
+    }
+
+    #[test]
+    fn test_state_matcher_invalid_pattern() {
+        let patterns = StatePatterns {
+            waiting: vec!["(".to_string()],
+            ..StatePatterns::default()
+        };
+        assert!(StateMatcher::new(&patterns).is_err());
+    }
+
+    #[test]
+    fn test_with_waiting() {
+        let custom = matcher().with_waiting(&["^READY$".to_string()]).unwrap();
+        assert_eq!(
+            custom.detect(&running("agent"), "booting\nREADY\n"),
+            AgentState::WaitingForInput
+        );
+        assert_eq!(
+            custom.detect(&running("agent"), "aider> \n"),
+            AgentState::Working
+        );
+        let unchanged = matcher().with_waiting(&[]).unwrap();
+        assert_eq!(
+            unchanged.detect(&running("aider"), "aider> \n"),
+            AgentState::WaitingForInput
+        );
+        assert!(matcher().with_waiting(&["(".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_detect_working() {
+        let state = matcher().detect(&running("claude"), "Reading files...\n\n\n");
+        assert_eq!(state, AgentState::Working);
+    }
+
+    #[test]
+    fn test_detect_waiting() {
+        let screen = "Done.\n╭────╮\n│ >  │\n╰────╯\n  ? for shortcuts\n";
+        assert_eq!(
+            matcher().detect(&running("claude"), screen),
+            AgentState::WaitingForInput
+        );
+        assert_eq!(
+            matcher().detect(&running("aider"), "Applied edit\naider> \n"),
+            AgentState::WaitingForInput
+        );
+    }
+
+    #[test]
+    fn test_detect_needs_approval() {
+        let screen = "Edit src/main.rs\nDo you want to make this edit?\n❯ 1. Yes\n  2. No\n";
+        assert_eq!(
+            matcher().detect(&running("claude"), screen),
+            AgentState::NeedsApproval
+        );
+        assert_eq!(
+            matcher().detect(&running("codex"), "Run `rm -rf target`? [y/N]"),
+            AgentState::NeedsApproval
+        );
+    }
+
+    #[test]
+    fn test_detect_errored_while_running() {
+        let screen = "Calling API\nError: rate limit exceeded\n";
+        assert_eq!(
+            matcher().detect(&running("claude"), screen),
+            AgentState::Errored
+        );
+    }
+
+    #[test]
+    fn test_detect_only_inspects_tail() {
+        let steps: Vec<String> = (0..TAIL_LINES).map(|i| format!("step {i}")).collect();
+        let screen = format!("Error: old failure\n{}", steps.join("\n"));
+        assert_eq!(
+            matcher().detect(&running("claude"), &screen),
+            AgentState::Working
+        );
+    }
+
+    #[test]
+    fn test_detect_back_at_shell() {
+        assert_eq!(
+            matcher().detect(&running("zsh"), "Bye!\n$ "),
+            AgentState::Finished
+        );
+        assert_eq!(
+            matcher().detect(
+                &running("bash"),
+                "thread 'main' panicked at src/main.rs\n$ "
+            ),
+            AgentState::Errored
+        );
+    }
+
+    #[test]
+    fn test_detect_dead_pane() {
+        let mut pane = running("claude");
+        pane.dead = true;
+        pane.dead_status = Some(0);
+        assert_eq!(matcher().detect(&pane, ""), AgentState::Finished);
+        pane.dead_status = Some(1);
+        assert_eq!(matcher().detect(&pane, ""), AgentState::Errored);
+    }
+
+    #[test]
+    fn test_pane_status_parse() {
+        let status = PaneStatus::parse("claude\t0\t\t1!,2#").unwrap();
+        assert_eq!(status.command, "claude");
+        assert!(!status.dead);
+        assert_eq!(status.dead_status, None);
+        assert!(status.bell);
+        assert!(status.activity);
+
+        let status = PaneStatus::parse("claude\t1\t2\t").unwrap();
+        assert!(status.dead);
+        assert_eq!(status.dead_status, Some(2));
+        assert!(!status.bell && !status.activity);
+
+        assert!(PaneStatus::parse("claude\t0").is_err());
+    }
+
+    #[test]
+    fn test_detector_baseline_has_no_events() {
+        let mut detector = TransitionDetector::new();
+        let events = detector.update(vec![snapshot("a", AgentState::WaitingForInput)]);
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn test_detector_state_transition() {
+        let mut detector = TransitionDetector::new();
+        detector.update(vec![snapshot("a", AgentState::Working)]);
+
+        let events = detector.update(vec![snapshot("a", AgentState::NeedsApproval)]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].trigger, Trigger::NeedsApproval);
+        assert_eq!(events[0].session, "a");
+        assert_eq!(events[0].previous, AgentState::Working);
+        assert_eq!(events[0].state, AgentState::NeedsApproval);
+
+        // No repeat while the state is unchanged
+        assert!(detector
+            .update(vec![snapshot("a", AgentState::NeedsApproval)])
+            .is_empty());
+
+        // Going back to work is not a notification
+        assert!(detector
+            .update(vec![snapshot("a", AgentState::Working)])
+            .is_empty());
+    }
+
+    #[test]
+    fn test_detector_bell_and_activity_edges() {
+        let mut detector = TransitionDetector::new();
+        detector.update(vec![snapshot("a", AgentState::Working)]);
+
+        let mut ringing = snapshot("a", AgentState::Working);
+        ringing.bell = true;
+        ringing.activity = true;
+        let events = detector.update(vec![ringing.clone()]);
+        let triggers: Vec<Trigger> = events.iter().map(|e| e.trigger).collect();
+        assert_eq!(triggers, vec![Trigger::Bell, Trigger::Activity]);
+
+        assert!(detector.update(vec![ringing]).is_empty());
+    }
+
+    #[test]
+    fn test_detector_forgets_removed_sessions() {
+        let mut detector = TransitionDetector::new();
+        detector.update(vec![snapshot("a", AgentState::Working)]);
+        detector.update(Vec::new());
+        assert!(detector
+            .update(vec![snapshot("a", AgentState::Finished)])
+            .is_empty());
+    }
+}